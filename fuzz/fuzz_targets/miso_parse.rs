@@ -0,0 +1,19 @@
+//! Parses arbitrary decoded frames and complete responses
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sps30_async::protocol::{parse_measurement, parse_miso_frame, process_response, Request};
+use sps30_async::MeasurementFormat;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&cmd, frame)) = data.split_first() else {
+        return;
+    };
+    let _ = parse_miso_frame::<(), ()>(frame, 0, cmd);
+
+    let request = Request::read_measurement(0);
+    if let Ok(response) = process_response::<(), ()>(&request, frame) {
+        let _ = parse_measurement::<(), ()>(&response, MeasurementFormat::Float);
+        let _ = parse_measurement::<(), ()>(&response, MeasurementFormat::Integer);
+    }
+});
@@ -0,0 +1,23 @@
+//! Decodes arbitrary bytes all at once, in place and incrementally
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sps30_async::shdlc::{decode, decode_in_place, Decoder, FrameAssembler};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode::<128>(data);
+
+    let mut copy = data.to_vec();
+    let _ = decode_in_place(&mut copy);
+
+    let mut decoder = Decoder::<64>::new();
+    let mut bytes = data;
+    while !bytes.is_empty() {
+        let _ = decoder.push(&mut bytes);
+    }
+
+    let mut assembler = FrameAssembler::new();
+    for byte in data {
+        let _ = assembler.push_byte(*byte);
+    }
+});
@@ -0,0 +1,301 @@
+//! Summarize measurements, for nodes that report for example one summary a
+//! minute instead of a measurement every second.
+
+use core::time::Duration;
+
+use crate::Measurement;
+
+/// Length of a [`TimeWeightedAverage`] bucket
+const BUCKET_SECS: u32 = 60 * 60;
+/// Hours covered by a [`TimeWeightedAverage`]
+const HOURS: usize = 24;
+
+/// Per field statistics over a window of measurements
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Summary {
+    pub min: Measurement,
+    pub max: Measurement,
+    pub mean: Measurement,
+    /// Population standard deviation
+    pub std_dev: Measurement,
+    /// Number of measurements summarized
+    pub count: u32,
+}
+
+/// Accumulates measurements in constant memory. Use
+/// [`take_summary`](Self::take_summary) for tumbling windows: summarize
+/// then start over. For a rolling window over the last N measurements see
+/// [`History::summary`](crate::history::History::summary).
+#[derive(Debug, Clone, Default)]
+pub struct Aggregator {
+    count: u32,
+    min: Measurement,
+    max: Measurement,
+    mean: Measurement,
+    /// Sum of squared differences from the mean (Welford's algorithm)
+    m2: Measurement,
+}
+
+impl Aggregator {
+    /// An empty aggregator
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a measurement to the window
+    pub fn push(&mut self, measurement: Measurement) {
+        if self.count == 0 {
+            self.min = measurement;
+            self.max = measurement;
+        } else {
+            self.min = self.min.zip_with(measurement, f32::min);
+            self.max = self.max.zip_with(measurement, f32::max);
+        }
+
+        self.count += 1;
+        let delta = measurement - self.mean;
+        #[allow(clippy::cast_precision_loss)] // exact up to 2^24 measurements
+        let count = self.count as f32;
+        self.mean = self.mean + delta / count;
+        self.m2 = self.m2 + delta.zip_with(measurement - self.mean, |a, b| a * b);
+    }
+
+    /// Number of measurements in the window
+    #[must_use]
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Statistics of the measurements pushed so far, `None` if there
+    /// are none
+    #[must_use]
+    pub fn summary(&self) -> Option<Summary> {
+        if self.count == 0 {
+            return None;
+        }
+        #[allow(clippy::cast_precision_loss)] // exact up to 2^24 measurements
+        let count = self.count as f32;
+        let variance = self.m2 / count;
+        Some(Summary {
+            min: self.min,
+            max: self.max,
+            mean: self.mean,
+            std_dev: variance.map(sqrt),
+            count: self.count,
+        })
+    }
+
+    /// Returns the summary and starts a new window
+    pub fn take_summary(&mut self) -> Option<Summary> {
+        let summary = self.summary();
+        *self = Self::new();
+        summary
+    }
+}
+
+impl Extend<Measurement> for Aggregator {
+    fn extend<I: IntoIterator<Item = Measurement>>(&mut self, iter: I) {
+        for measurement in iter {
+            self.push(measurement);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    /// Measurements multiplied by the seconds they were valid
+    weighted_sum: Measurement,
+    /// Seconds with data
+    covered: f32,
+}
+
+/// Rolling 8 and 24 hour averages, as used by WHO guidelines and most
+/// regulations for PM2.5 and PM10.
+///
+/// Each measurement is weighted by how long it was valid so irregular
+/// sampling, for example from duty cycling, does not skew the average.
+/// Time without data (see [`skip`](Self::skip)) is left out instead of
+/// counting as zero. Uses hourly buckets: the averages cover the current
+/// hour and the 7 or 23 before it.
+#[derive(Debug, Clone)]
+pub struct TimeWeightedAverage {
+    buckets: [Bucket; HOURS],
+    /// Index of the current bucket
+    current: usize,
+    /// Seconds passed in the current bucket
+    elapsed: u32,
+}
+
+impl Default for TimeWeightedAverage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeWeightedAverage {
+    /// No data yet
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            buckets: [Bucket::default(); HOURS],
+            current: 0,
+            elapsed: 0,
+        }
+    }
+
+    /// Adds `measurement` as representative for the `duration` that passed
+    /// since the previous sample.
+    pub fn push(&mut self, duration: Duration, measurement: Measurement) {
+        self.advance(duration, Some(measurement));
+    }
+
+    /// Lets `duration` pass without data, for example while the sensor was
+    /// off or failing.
+    pub fn skip(&mut self, duration: Duration) {
+        self.advance(duration, None);
+    }
+
+    /// Average over the last 8 hours, `None` if there is no data
+    #[must_use]
+    pub fn average_8h(&self) -> Option<Measurement> {
+        self.average(8)
+    }
+
+    /// Average over the last 24 hours, `None` if there is no data
+    #[must_use]
+    pub fn average_24h(&self) -> Option<Measurement> {
+        self.average(24)
+    }
+
+    /// Average over the last `hours` (at most 24), `None` if there is no
+    /// data
+    #[must_use]
+    pub fn average(&self, hours: usize) -> Option<Measurement> {
+        let (sum, covered) = self
+            .last(hours)
+            .fold((Measurement::default(), 0.0), |(sum, covered), bucket| {
+                (sum + bucket.weighted_sum, covered + bucket.covered)
+            });
+        (covered > 0.0).then(|| sum / covered)
+    }
+
+    /// Fraction of the last `hours` (at most 24) with data. Regulations
+    /// often require 75% coverage for an average to be valid.
+    #[must_use]
+    pub fn coverage(&self, hours: usize) -> f32 {
+        let hours = hours.clamp(1, HOURS);
+        let covered: f32 = self.last(hours).map(|bucket| bucket.covered).sum();
+        #[allow(clippy::cast_possible_truncation)] // hours is at most 24
+        let window = (hours as u32 - 1) * BUCKET_SECS + self.elapsed;
+        if window == 0 {
+            return 0.0;
+        }
+        #[allow(clippy::cast_precision_loss)] // at most a day of seconds
+        let window = window as f32;
+        covered / window
+    }
+
+    /// The current bucket and the `hours - 1` before it
+    fn last(&self, hours: usize) -> impl Iterator<Item = &Bucket> {
+        (0..hours.min(HOURS)).map(move |age| &self.buckets[(self.current + HOURS - age) % HOURS])
+    }
+
+    fn advance(&mut self, duration: Duration, measurement: Option<Measurement>) {
+        // longer gaps clear all buckets just the same
+        const MAX_SECS: u64 = BUCKET_SECS as u64 * HOURS as u64;
+        #[allow(clippy::cast_possible_truncation)] // clamped to MAX_SECS
+        let mut remaining = duration.as_secs().min(MAX_SECS) as u32;
+        while remaining > 0 {
+            let step = remaining.min(BUCKET_SECS - self.elapsed);
+            if let Some(measurement) = measurement {
+                #[allow(clippy::cast_precision_loss)] // at most an hour
+                let seconds = step as f32;
+                let bucket = &mut self.buckets[self.current];
+                bucket.weighted_sum = bucket.weighted_sum + measurement * seconds;
+                bucket.covered += seconds;
+            }
+            self.elapsed += step;
+            remaining -= step;
+            if self.elapsed == BUCKET_SECS {
+                self.current = (self.current + 1) % HOURS;
+                self.buckets[self.current] = Bucket::default();
+                self.elapsed = 0;
+            }
+        }
+    }
+}
+
+/// `f32::sqrt` needs std, Newton's method from an estimate based on the
+/// float representation converges in a few steps.
+fn sqrt(x: f32) -> f32 {
+    if x <= 0.0 || x.is_nan() || x.is_infinite() {
+        return x.max(0.0);
+    }
+    let mut y = f32::from_bits((x.to_bits() >> 1) + 0x1fbd_1df5);
+    for _ in 0..4 {
+        y = 0.5 * (y + x / y);
+    }
+    y
+}
+
+#[cfg(test)]
+mod test {
+    use super::{sqrt, Aggregator, TimeWeightedAverage};
+    use crate::Measurement;
+    use core::time::Duration;
+
+    fn measurement(pm2_5: f32) -> Measurement {
+        Measurement {
+            mass_pm2_5: pm2_5,
+            ..Measurement::default()
+        }
+    }
+
+    #[test]
+    fn summarizes_window() {
+        let mut aggregator = Aggregator::new();
+        assert!(aggregator.summary().is_none());
+        aggregator.extend([2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0].map(measurement));
+        let summary = aggregator.take_summary().unwrap();
+        assert_eq!(summary.count, 8);
+        assert_eq!(summary.min.mass_pm2_5, 2.0);
+        assert_eq!(summary.max.mass_pm2_5, 9.0);
+        assert_eq!(summary.mean.mass_pm2_5, 5.0);
+        assert!((summary.std_dev.mass_pm2_5 - 2.0).abs() < 1e-5);
+        assert_eq!(summary.std_dev.mass_pm10, 0.0);
+        assert_eq!(aggregator.count(), 0);
+    }
+
+    #[test]
+    fn time_weighted() {
+        let mut average = TimeWeightedAverage::new();
+        assert!(average.average_24h().is_none());
+        // 9 hours at 10 then 1 hour at 20
+        for _ in 0..9 * 60 {
+            average.push(Duration::from_secs(60), measurement(10.0));
+        }
+        average.push(Duration::from_secs(60 * 60), measurement(20.0));
+        assert!((average.average_24h().unwrap().mass_pm2_5 - 11.0).abs() < 1e-3);
+        // the current (empty) hour plus 7 earlier of which one at 20
+        assert!((average.average_8h().unwrap().mass_pm2_5 - 80.0 / 7.0).abs() < 1e-3);
+
+        // gaps are left out, not counted as zero
+        average.skip(Duration::from_secs(4 * 60 * 60));
+        assert!((average.average_24h().unwrap().mass_pm2_5 - 11.0).abs() < 1e-3);
+        assert!((average.coverage(24) - 10.0 / 23.0).abs() < 1e-3);
+
+        average.skip(Duration::from_secs(48 * 60 * 60));
+        assert!(average.average_24h().is_none());
+    }
+
+    #[test]
+    fn square_root() {
+        for x in [1e-6, 0.25, 2.0, 1e4, 3.3e7] {
+            let root = sqrt(x);
+            assert!((root * root - x).abs() <= x * 1e-6, "{x}");
+        }
+    }
+}
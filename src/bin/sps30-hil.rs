@@ -0,0 +1,156 @@
+//! Hardware in the loop test, runs every command against a real SPS30 and
+//! reports which behave as expected. Use it to validate a release against
+//! the sensor.
+//!
+//! Run with: `cargo run --features hil-test --bin sps30-hil -- /dev/ttyUSB0`
+//!
+//! Takes about half a minute, the sensor is left idle with its settings
+//! unchanged.
+
+use std::process::ExitCode;
+use std::thread;
+use std::time::Duration;
+
+use sps30_async::blocking::{block_on, Sps30};
+use sps30_async::{CleaningInterval, DeviceError, Error, Health, IoError};
+
+type Result<T> = std::result::Result<T, String>;
+type Check = fn(&mut Sps30) -> Result<String>;
+
+fn main() -> ExitCode {
+    let Some(port) = std::env::args().nth(1) else {
+        eprintln!("usage: sps30-hil <serial port>");
+        return ExitCode::FAILURE;
+    };
+    let mut sensor = match Sps30::open(&port) {
+        Ok(sensor) => sensor,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let checks: [(&str, Check); 10] = [
+        ("device info", device_info),
+        ("status register", status_register),
+        ("measurement", measurement),
+        ("cleaning interval", cleaning_interval),
+        ("fan cleaning", fan_cleaning),
+        ("stop measurement", stop_measurement),
+        ("sleep and wake up", sleep_and_wake_up),
+        ("ping", ping),
+        ("restart measurement", restart_measurement),
+        ("reset", reset),
+    ];
+
+    let mut failed = 0;
+    for (name, check) in checks {
+        match check(&mut sensor) {
+            Ok(details) => println!("PASS {name:<20} {details}"),
+            Err(reason) => {
+                failed += 1;
+                println!("FAIL {name:<20} {reason}");
+            }
+        }
+    }
+
+    println!();
+    if failed == 0 {
+        println!("all {} checks passed", checks.len());
+        ExitCode::SUCCESS
+    } else {
+        println!("{failed} of {} checks failed", checks.len());
+        ExitCode::FAILURE
+    }
+}
+
+fn err(e: Error<IoError, IoError>) -> String {
+    format!("{e:?}")
+}
+
+fn device_info(sensor: &mut Sps30) -> Result<String> {
+    let info = block_on(sensor.inner_mut().device_info()).map_err(err)?;
+    if info.product_type != "00080000" {
+        return Err(format!("unexpected product type: {}", info.product_type));
+    }
+    Ok(format!(
+        "serial {}, firmware {}",
+        info.serial_number, info.versions.firmware
+    ))
+}
+
+fn status_register(sensor: &mut Sps30) -> Result<String> {
+    let status = sensor.read_device_status_register(false).map_err(err)?;
+    Ok(format!("{:#010x} ({status})", status.bits()))
+}
+
+fn measurement(sensor: &mut Sps30) -> Result<String> {
+    thread::sleep(Duration::from_secs(1));
+    let m = sensor.read_measurement().map_err(err)?;
+    let mass = [m.mass_pm1_0, m.mass_pm2_5, m.mass_pm4_0, m.mass_pm10];
+    if mass.iter().any(|v| !v.is_finite() || *v < 0.0) {
+        return Err(format!("implausible mass concentrations: {mass:?}"));
+    }
+    // every size class includes the smaller ones
+    if mass.windows(2).any(|w| w[0] > w[1]) {
+        return Err(format!("mass concentrations not increasing: {mass:?}"));
+    }
+    Ok(format!("PM2.5 {:.1} µg/m³", m.mass_pm2_5))
+}
+
+fn cleaning_interval(sensor: &mut Sps30) -> Result<String> {
+    let original = sensor.read_cleaning_interval().map_err(err)?;
+    let changed = original.raw().wrapping_add(60);
+    let changed = CleaningInterval::from_secs(changed).unwrap_or(CleaningInterval::DEFAULT);
+    sensor.write_cleaning_interval(changed).map_err(err)?;
+    let read_back = sensor.read_cleaning_interval().map_err(err);
+    sensor.write_cleaning_interval(original).map_err(err)?;
+    match read_back? {
+        val if val == changed => Ok(format!("{original}")),
+        val => Err(format!("wrote {changed} but read back {val}")),
+    }
+}
+
+fn fan_cleaning(sensor: &mut Sps30) -> Result<String> {
+    sensor.start_fan_cleaning().map_err(err)?;
+    thread::sleep(Duration::from_secs(11));
+    sensor.read_measurement().map_err(err)?;
+    Ok("measuring after cleaning".to_owned())
+}
+
+fn stop_measurement(sensor: &mut Sps30) -> Result<String> {
+    sensor.stop_measurement().map_err(err)?;
+    match sensor.read_measurement() {
+        Err(
+            Error::Device(DeviceError::InvalidStateForCommand) | Error::WrongDriverState { .. },
+        ) => Ok("reads rejected while idle".to_owned()),
+        other => Err(format!("read while idle returned {other:?}")),
+    }
+}
+
+fn sleep_and_wake_up(sensor: &mut Sps30) -> Result<String> {
+    block_on(sensor.inner_mut().sleep()).map_err(err)?;
+    block_on(sensor.inner_mut().wake_up()).map_err(err)?;
+    block_on(sensor.inner_mut().product_type()).map_err(err)?;
+    Ok("responds after waking up".to_owned())
+}
+
+fn ping(sensor: &mut Sps30) -> Result<String> {
+    match block_on(sensor.inner_mut().ping()).map_err(err)? {
+        Health::Healthy => Ok("healthy".to_owned()),
+        other => Err(format!("{other:?}")),
+    }
+}
+
+fn restart_measurement(sensor: &mut Sps30) -> Result<String> {
+    let inner = sensor.inner_mut();
+    let m = block_on(inner.start_measurement_and_wait_ready()).map_err(err)?;
+    block_on(inner.stop_measurement()).map_err(err)?;
+    Ok(format!("PM10 {:.1} µg/m³", m.mass_pm10))
+}
+
+fn reset(sensor: &mut Sps30) -> Result<String> {
+    sensor.reset().map_err(err)?;
+    block_on(sensor.inner_mut().product_type()).map_err(err)?;
+    Ok("responds after reset".to_owned())
+}
@@ -0,0 +1,148 @@
+//! Command line tool for bring-up and validating wiring of an SPS30
+//!
+//! Install with: `cargo install sps30-async --features cli`
+
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use sps30_async::blocking::Sps30;
+use sps30_async::DeviceStatus;
+use sps30_async::Measurement;
+
+#[derive(Parser)]
+#[command(version, about = "Talk to a Sensirion SPS30 over a serial port")]
+struct Cli {
+    /// Serial port the sensor is attached to, e.g. /dev/ttyUSB0
+    port: String,
+    /// How to print results
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Json,
+    Table,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Read a single measurement
+    Read,
+    /// Keep reading measurements
+    Watch {
+        /// Seconds between measurements
+        #[arg(long, default_value_t = 1)]
+        interval: u64,
+    },
+    /// Print the serial number
+    SerialNumber,
+    /// Start a fan cleaning cycle (takes 10 seconds)
+    Clean,
+    /// Print the device status register
+    Status,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let mut sensor = match Sps30::open(&cli.port) {
+        Ok(sensor) => sensor,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let res = match cli.command {
+        Command::Read => sensor
+            .read_measurement()
+            .map(|m| print_measurement(&m, cli.format)),
+        Command::Watch { interval } => watch(&mut sensor, cli.format, interval),
+        Command::SerialNumber => sensor.serial_number().map(|serial| match cli.format {
+            Format::Json => println!("{{\"serial_number\":\"{serial}\"}}"),
+            Format::Table => println!("serial number: {serial}"),
+        }),
+        Command::Clean => sensor.start_fan_cleaning().map(|()| match cli.format {
+            Format::Json => println!("{{\"fan_cleaning\":\"started\"}}"),
+            Format::Table => println!("fan cleaning started"),
+        }),
+        Command::Status => sensor
+            .read_device_status_register(false)
+            .map(|register| print_status(register, cli.format)),
+    };
+
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error communicating with sensor: {e:?}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn watch(
+    sensor: &mut Sps30,
+    format: Format,
+    interval: u64,
+) -> Result<(), sps30_async::Error<sps30_async::IoError, sps30_async::IoError>> {
+    let interval = Duration::from_secs(interval);
+    loop {
+        let started = Instant::now();
+        let measurement = sensor.read_measurement()?;
+        print_measurement(&measurement, format);
+        thread::sleep(interval.saturating_sub(started.elapsed()));
+    }
+}
+
+fn print_measurement(measurement: &Measurement, format: Format) {
+    match format {
+        Format::Json => {
+            let mut buf = [0u8; 512];
+            let n = measurement
+                .to_json(&mut buf)
+                .expect("buffer fits every measurement");
+            println!("{}", String::from_utf8_lossy(&buf[..n]));
+        }
+        Format::Table => {
+            let rows = [
+                ("PM1.0", measurement.mass_pm1_0, "µg/m³"),
+                ("PM2.5", measurement.mass_pm2_5, "µg/m³"),
+                ("PM4.0", measurement.mass_pm4_0, "µg/m³"),
+                ("PM10", measurement.mass_pm10, "µg/m³"),
+                ("NC0.5", measurement.mass_pm0_5, "#/cm³"),
+                ("NC1.0", measurement.number_pm1_0, "#/cm³"),
+                ("NC2.5", measurement.number_pm2_5, "#/cm³"),
+                ("NC4.0", measurement.number_pm4_0, "#/cm³"),
+                ("NC10", measurement.number_pm10, "#/cm³"),
+                ("typical size", measurement.typical_particle_size, "µm"),
+            ];
+            for (name, value, unit) in rows {
+                println!("{name:>12} {value:>9.2} {unit}");
+            }
+            println!();
+        }
+    }
+}
+
+fn print_status(status: DeviceStatus, format: Format) {
+    let register = status.bits();
+    let fan_speed_warning = status.fan_speed_warning();
+    let laser_failure = status.laser_failure();
+    let fan_failure = status.fan_failure();
+    match format {
+        Format::Json => println!(
+            "{{\"register\":{register},\"fan_speed_warning\":{fan_speed_warning},\
+            \"laser_failure\":{laser_failure},\"fan_failure\":{fan_failure}}}"
+        ),
+        Format::Table => {
+            println!("         register {register:#010x}");
+            println!("fan speed warning {fan_speed_warning}");
+            println!("    laser failure {laser_failure}");
+            println!("      fan failure {fan_failure}");
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Measurements kept as the bit patterns the sensor sent, see
+//! [`MeasurementBits`].
+
+use core::mem;
+
+use crate::Measurement;
+
+/// An IEEE754 float as sent by the sensor. Decode it with
+/// [`to_f32`](Self::to_f32), which only reinterprets the bits and needs no
+/// floating point arithmetic.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(transparent)
+)]
+#[cfg_attr(
+    feature = "postcard",
+    derive(postcard::experimental::max_size::MaxSize)
+)]
+pub struct FloatBits(pub u32);
+
+impl FloatBits {
+    /// The value these bits encode
+    #[must_use]
+    pub const fn to_f32(self) -> f32 {
+        f32::from_bits(self.0)
+    }
+
+    /// The bytes as the sensor sent them
+    #[must_use]
+    pub const fn to_be_bytes(self) -> [u8; 4] {
+        self.0.to_be_bytes()
+    }
+}
+
+/// A [`Measurement`] in float format that has not been decoded. Relay
+/// nodes on microcontrollers without FPU can pass readings along without
+/// linking soft-float code, read these with
+/// [`Sps30::read_measurement_bits`](crate::Sps30::read_measurement_bits).
+/// The fields are those of [`Measurement`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(
+    feature = "postcard",
+    derive(postcard::experimental::max_size::MaxSize)
+)]
+pub struct MeasurementBits {
+    pub mass_pm1_0: FloatBits,
+    pub mass_pm2_5: FloatBits,
+    pub mass_pm4_0: FloatBits,
+    pub mass_pm10: FloatBits,
+    pub mass_pm0_5: FloatBits,
+    pub number_pm1_0: FloatBits,
+    pub number_pm2_5: FloatBits,
+    pub number_pm4_0: FloatBits,
+    pub number_pm10: FloatBits,
+    pub typical_particle_size: FloatBits,
+}
+
+impl MeasurementBits {
+    /// Splits a float format payload, `None` if it is too short
+    pub(crate) fn from_data(data: &[u8]) -> Option<Self> {
+        let mut words = data
+            .chunks_exact(mem::size_of::<u32>())
+            .map(<[u8; mem::size_of::<u32>()]>::try_from)
+            .map(Result::unwrap) // chunks exact guarantees correct size
+            .map(|bytes| FloatBits(u32::from_be_bytes(bytes)));
+        Some(Self {
+            mass_pm1_0: words.next()?,
+            mass_pm2_5: words.next()?,
+            mass_pm4_0: words.next()?,
+            mass_pm10: words.next()?,
+            mass_pm0_5: words.next()?,
+            number_pm1_0: words.next()?,
+            number_pm2_5: words.next()?,
+            number_pm4_0: words.next()?,
+            number_pm10: words.next()?,
+            typical_particle_size: words.next()?,
+        })
+    }
+
+    /// Decodes every field, without any
+    /// [calibration](crate::Sps30::set_calibration)
+    #[must_use]
+    pub fn to_measurement(&self) -> Measurement {
+        Measurement {
+            mass_pm1_0: self.mass_pm1_0.to_f32(),
+            mass_pm2_5: self.mass_pm2_5.to_f32(),
+            mass_pm4_0: self.mass_pm4_0.to_f32(),
+            mass_pm10: self.mass_pm10.to_f32(),
+            mass_pm0_5: self.mass_pm0_5.to_f32(),
+            number_pm1_0: self.number_pm1_0.to_f32(),
+            number_pm2_5: self.number_pm2_5.to_f32(),
+            number_pm4_0: self.number_pm4_0.to_f32(),
+            number_pm10: self.number_pm10.to_f32(),
+            typical_particle_size: self.typical_particle_size.to_f32(),
+        }
+    }
+}
+
+impl From<MeasurementBits> for Measurement {
+    fn from(bits: MeasurementBits) -> Self {
+        bits.to_measurement()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FloatBits, MeasurementBits};
+
+    #[test]
+    fn decodes_lazily() {
+        let data: [u8; 40] = core::array::from_fn(|i| if i % 4 == 0 { 0x40 } else { 0 });
+        let bits = MeasurementBits::from_data(&data).unwrap();
+        assert_eq!(bits.mass_pm2_5, FloatBits(0x4000_0000));
+        assert_eq!(bits.mass_pm2_5.to_f32(), 2.0);
+        assert_eq!(bits.to_measurement().typical_particle_size, 2.0);
+        assert_eq!(MeasurementBits::from_data(&data[..20]), None);
+    }
+}
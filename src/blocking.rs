@@ -0,0 +1,312 @@
+//! Blocking driver for host tools and scripts, built on [`serialport`].
+//!
+//! ```no_run
+//! let mut sensor = sps30_async::blocking::Sps30::open("/dev/ttyUSB0").unwrap();
+//! let measurement = sensor.read_measurement().unwrap();
+//! println!("{measurement:?}");
+//! ```
+
+use core::fmt;
+use core::future::Future;
+use std::io;
+use std::time::Duration;
+
+use embedded_hal_async::delay::DelayNs;
+use serialport::{DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+use crate::{
+    CleaningInterval, Config, Error, InitError, IoError, Measurement, MeasurementBits, Snapshot,
+    VerifyError, MAX_MEASUREMENT_LEN,
+};
+
+/// How long a read may block before failing with [`io::ErrorKind::TimedOut`]
+pub const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Runs an async driver method to completion on the current thread. Use
+/// this together with [`Sps30::inner_mut`] for methods without a blocking
+/// wrapper.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    futures_executor::block_on(future)
+}
+
+/// A [`SerialPort`] wrapped in the (blocking) embedded-io traits
+pub struct Port(pub Box<dyn SerialPort>);
+
+impl fmt::Debug for Port {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Port").field(&self.0.name()).finish()
+    }
+}
+
+impl embedded_io::ErrorType for Port {
+    type Error = IoError;
+}
+
+impl embedded_io::Read for Port {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Ok(io::Read::read(&mut self.0, buf)?)
+    }
+}
+
+impl embedded_io::Write for Port {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Ok(io::Write::write(&mut self.0, buf)?)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(io::Write::flush(&mut self.0)?)
+    }
+}
+
+// the async driver needs the async traits, these simply block
+impl embedded_io_async::Read for Port {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        embedded_io::Read::read(self, buf)
+    }
+}
+
+impl embedded_io_async::Write for Port {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        embedded_io::Write::write(self, buf)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        embedded_io::Write::flush(self)
+    }
+}
+
+/// [`DelayNs`] implementation that sleeps the current thread
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Delay;
+
+impl DelayNs for Delay {
+    async fn delay_ns(&mut self, ns: u32) {
+        std::thread::sleep(Duration::from_nanos(ns.into()));
+    }
+}
+
+/// Failed to open or initialize the sensor
+#[derive(Debug)]
+pub enum OpenError {
+    /// Could not open or configure the serial port
+    Port(serialport::Error),
+    /// The port opened but initializing the device failed, includes the
+    /// likely cause
+    Init(InitError<IoError, IoError>),
+}
+
+impl fmt::Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenError::Port(e) => write!(f, "Could not open serial port: {e}"),
+            OpenError::Init(e) => write!(f, "Could not initialize the sensor: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for OpenError {}
+
+/// Blocking Sps30 driver
+pub struct Sps30 {
+    inner: crate::Sps30<Port, Port, Delay>,
+}
+
+impl Sps30 {
+    /// Opens the serial port at `path` with the settings the SPS30 needs
+    /// (115200 baud, 8 data bits, 1 stop bit, no parity) then resets the
+    /// device and starts measuring.
+    ///
+    /// # Errors
+    /// Opening the port or initializing the device can fail.
+    pub fn open(path: &str) -> Result<Self, OpenError> {
+        let port = serialport::new(path, 115_200)
+            .data_bits(DataBits::Eight)
+            .stop_bits(StopBits::One)
+            .parity(Parity::None)
+            .flow_control(FlowControl::None)
+            .timeout(READ_TIMEOUT)
+            .open()
+            .map_err(OpenError::Port)?;
+        Self::from_port(port)
+    }
+
+    /// Constructs the driver from an already configured port then resets
+    /// the device and starts measuring.
+    ///
+    /// # Errors
+    /// Cloning the port handle or initializing the device can fail.
+    pub fn from_port(port: Box<dyn SerialPort>) -> Result<Self, OpenError> {
+        let rx = port.try_clone().map_err(OpenError::Port)?;
+        let builder = crate::Sps30::builder(Port(port), Port(rx), Delay);
+        let inner = block_on(builder.build_diagnosed()).map_err(OpenError::Init)?;
+        Ok(Self { inner })
+    }
+
+    /// The async driver this wraps, see [`block_on`]
+    pub fn inner_mut(&mut self) -> &mut crate::Sps30<Port, Port, Delay> {
+        &mut self.inner
+    }
+
+    /// See [`crate::Sps30::start_measurement`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::start_measurement`]
+    pub fn start_measurement(&mut self) -> Result<(), Error<IoError, IoError>> {
+        block_on(self.inner.start_measurement())
+    }
+
+    /// See [`crate::Sps30::stop_measurement`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::stop_measurement`]
+    pub fn stop_measurement(&mut self) -> Result<(), Error<IoError, IoError>> {
+        block_on(self.inner.stop_measurement())
+    }
+
+    /// See [`crate::Sps30::read_measurement`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::read_measurement`]
+    pub fn read_measurement(&mut self) -> Result<Measurement, Error<IoError, IoError>> {
+        block_on(self.inner.read_measurement())
+    }
+
+    /// See [`crate::Sps30::read_measurement_raw`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::read_measurement_raw`]
+    pub fn read_measurement_raw(
+        &mut self,
+    ) -> Result<heapless::Vec<u8, MAX_MEASUREMENT_LEN>, Error<IoError, IoError>> {
+        block_on(self.inner.read_measurement_raw())
+    }
+
+    /// See [`crate::Sps30::read_measurement_bits`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::read_measurement_bits`]
+    pub fn read_measurement_bits(&mut self) -> Result<MeasurementBits, Error<IoError, IoError>> {
+        block_on(self.inner.read_measurement_bits())
+    }
+
+    /// See [`crate::Sps30::start_measurement_and_wait_ready`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::start_measurement_and_wait_ready`]
+    pub fn start_measurement_and_wait_ready(
+        &mut self,
+    ) -> Result<Measurement, Error<IoError, IoError>> {
+        block_on(self.inner.start_measurement_and_wait_ready())
+    }
+
+    /// See [`crate::Sps30::read_cleaning_interval`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::read_cleaning_interval`]
+    pub fn read_cleaning_interval(&mut self) -> Result<CleaningInterval, Error<IoError, IoError>> {
+        block_on(self.inner.read_cleaning_interval())
+    }
+
+    /// See [`crate::Sps30::write_cleaning_interval`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::write_cleaning_interval`]
+    pub fn write_cleaning_interval(
+        &mut self,
+        interval: CleaningInterval,
+    ) -> Result<(), Error<IoError, IoError>> {
+        block_on(self.inner.write_cleaning_interval(interval))
+    }
+
+    /// See [`crate::Sps30::write_cleaning_interval_verified`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::write_cleaning_interval_verified`]
+    pub fn write_cleaning_interval_verified(
+        &mut self,
+        interval: CleaningInterval,
+        reset: bool,
+    ) -> Result<(), VerifyError<IoError, IoError>> {
+        block_on(self.inner.write_cleaning_interval_verified(interval, reset))
+    }
+
+    /// See [`crate::Sps30::apply_config`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::apply_config`]
+    pub fn apply_config(&mut self, config: Config) -> Result<(), Error<IoError, IoError>> {
+        block_on(self.inner.apply_config(config))
+    }
+
+    /// See [`crate::Sps30::read_config`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::read_config`]
+    pub fn read_config(&mut self) -> Result<Config, Error<IoError, IoError>> {
+        block_on(self.inner.read_config())
+    }
+
+    /// See [`crate::Sps30::snapshot`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::snapshot`]
+    pub fn snapshot(&mut self) -> Result<Snapshot, Error<IoError, IoError>> {
+        block_on(self.inner.snapshot())
+    }
+
+    /// See [`crate::Sps30::read_cleaning_interval_duration`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::read_cleaning_interval_duration`]
+    pub fn read_cleaning_interval_duration(
+        &mut self,
+    ) -> Result<Option<Duration>, Error<IoError, IoError>> {
+        block_on(self.inner.read_cleaning_interval_duration())
+    }
+
+    /// See [`crate::Sps30::write_cleaning_interval_duration`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::write_cleaning_interval_duration`]
+    pub fn write_cleaning_interval_duration(
+        &mut self,
+        interval: Duration,
+    ) -> Result<(), Error<IoError, IoError>> {
+        block_on(self.inner.write_cleaning_interval_duration(interval))
+    }
+
+    /// See [`crate::Sps30::start_fan_cleaning`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::start_fan_cleaning`]
+    pub fn start_fan_cleaning(&mut self) -> Result<(), Error<IoError, IoError>> {
+        block_on(self.inner.start_fan_cleaning())
+    }
+
+    /// See [`crate::Sps30::serial_number`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::serial_number`]
+    pub fn serial_number(&mut self) -> Result<heapless::String<32>, Error<IoError, IoError>> {
+        block_on(self.inner.serial_number())
+    }
+
+    /// See [`crate::Sps30::read_device_status_register`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::read_device_status_register`]
+    pub fn read_device_status_register(
+        &mut self,
+        clear: bool,
+    ) -> Result<crate::DeviceStatus, Error<IoError, IoError>> {
+        block_on(self.inner.read_device_status_register(clear))
+    }
+
+    /// See [`crate::Sps30::reset`]
+    ///
+    /// # Errors
+    /// See [`crate::Sps30::reset`]
+    pub fn reset(&mut self) -> Result<(), Error<IoError, IoError>> {
+        block_on(self.inner.reset())
+    }
+}
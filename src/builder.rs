@@ -0,0 +1,329 @@
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, ReadReady, Write};
+
+use crate::calibration::Calibration;
+use crate::shdlc::device::{read_ready, ReadReadyFn};
+use crate::shdlc::{
+    FrameObserver, ProtocolLogger, ShdlcDevice, DEFAULT_ADDRESS, DEFAULT_RESYNC_LIMIT,
+};
+use crate::{Diagnosis, Error, InitError, MaybeFormat, MeasurementFormat, Sps30, FAN_CLEANING_MS};
+
+/// Configures and initializes an [`Sps30`], create one using
+/// [`Sps30::builder`].
+///
+/// By default the device is reset then started measuring in the
+/// [`MeasurementFormat::Float`] format. Requests are not retried.
+///
+/// ```no_run
+/// use embedded_hal_async::delay::DelayNs;
+/// use embedded_io_async::{Read, Write};
+/// use sps30_async::{Error, MaybeFormat, MeasurementFormat, Sps30};
+///
+/// async fn init<Tx, Rx, D>(tx: Tx, rx: Rx, delay: D) -> Result<Sps30<Tx, Rx, D>, Error<Tx::Error, Rx::Error>>
+/// where
+///     Tx: Write,
+///     Tx::Error: MaybeFormat,
+///     Rx: Read,
+///     Rx::Error: MaybeFormat,
+///     D: DelayNs,
+/// {
+///     Sps30::builder(tx, rx, delay)
+///         .address(2)
+///         .measurement_format(MeasurementFormat::Integer)
+///         .retries(3)
+///         .build()
+///         .await
+/// }
+/// ```
+pub struct Sps30Builder<Tx, Rx, D, O = (), L = ()> {
+    uart_tx: Tx,
+    uart_rx: Rx,
+    delay: D,
+    observer: O,
+    logger: L,
+    reset: bool,
+    start_measurement: bool,
+    clean_fan: bool,
+    format: MeasurementFormat,
+    calibration: Calibration,
+    retries: u8,
+    resync_limit: usize,
+    timeout_ms: Option<u32>,
+    address: u8,
+    drain_before_send: Option<ReadReadyFn<Rx>>,
+}
+
+impl<Tx, Rx, D> Sps30Builder<Tx, Rx, D> {
+    pub(crate) fn new(uart_tx: Tx, uart_rx: Rx, delay: D) -> Self {
+        Self {
+            uart_tx,
+            uart_rx,
+            delay,
+            observer: (),
+            logger: (),
+            reset: true,
+            start_measurement: true,
+            clean_fan: false,
+            format: MeasurementFormat::Float,
+            calibration: Calibration::IDENTITY,
+            retries: 0,
+            resync_limit: DEFAULT_RESYNC_LIMIT,
+            timeout_ms: None,
+            address: DEFAULT_ADDRESS,
+            drain_before_send: None,
+        }
+    }
+}
+
+impl<Tx, Rx, D, O, L> Sps30Builder<Tx, Rx, D, O, L>
+where
+    Tx: Write,
+    Tx::Error: MaybeFormat,
+    Rx: Read,
+    Rx::Error: MaybeFormat,
+    D: DelayNs,
+    O: FrameObserver,
+    L: ProtocolLogger,
+{
+    /// Do not reset the device during [`build`](Self::build), for example
+    /// when it is already measuring.
+    #[must_use]
+    pub fn skip_reset(mut self) -> Self {
+        self.reset = false;
+        self
+    }
+
+    /// Do not start measuring during [`build`](Self::build), call
+    /// [`Sps30::start_measurement`] later.
+    #[must_use]
+    pub fn skip_start_measurement(mut self) -> Self {
+        self.start_measurement = false;
+        self
+    }
+
+    /// Run a fan cleaning after measuring starts and wait the 10 seconds it
+    /// takes to finish. The datasheet recommends this after long storage.
+    /// Devices that are powered down between measurements reset their
+    /// cleaning interval counter, they never clean without this.
+    ///
+    /// Has no effect with [`skip_start_measurement`](Self::skip_start_measurement)
+    /// as cleaning requires measurement mode.
+    #[must_use]
+    pub fn clean_fan_on_start(mut self) -> Self {
+        self.clean_fan = true;
+        self
+    }
+
+    /// The format the sensor sends measurements in, used whenever
+    /// measuring is started.
+    #[must_use]
+    pub fn measurement_format(mut self, format: MeasurementFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Correct every measurement using `calibration`, see
+    /// [`Sps30::set_calibration`]
+    #[must_use]
+    pub fn calibration(mut self, calibration: Calibration) -> Self {
+        self.calibration = calibration;
+        self
+    }
+
+    /// Send a request again up to `retries` times when it fails with a
+    /// [recoverable](Error::is_recoverable) error. Also applies to the
+    /// requests made during [`build`](Self::build).
+    #[must_use]
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Give up on a response after reading this many bytes without finding
+    /// it, see [`ShdlcDevice::set_resync_limit`]. This bounds how long a
+    /// request waits on a noisy line.
+    #[must_use]
+    pub fn resync_limit(mut self, bytes: usize) -> Self {
+        self.resync_limit = bytes;
+        self
+    }
+
+    /// Give up on a response after `ms` milliseconds, see
+    /// [`Sps30::with_timeout`]. Also applies to the requests made during
+    /// [`build`](Self::build).
+    #[must_use]
+    pub fn timeout(mut self, ms: u32) -> Self {
+        self.timeout_ms = Some(ms);
+        self
+    }
+
+    /// Discard pending received bytes before every command, including
+    /// those sent during [`build`](Self::build). See
+    /// [`ShdlcDevice::set_drain_before_send`].
+    #[must_use]
+    pub fn drain_before_command(mut self) -> Self
+    where
+        Rx: ReadReady,
+    {
+        self.drain_before_send = Some(read_ready::<Rx>);
+        self
+    }
+
+    /// The SHDLC address of the sensor, see [`Sps30::with_address`]
+    #[must_use]
+    pub fn address(mut self, address: u8) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Passes every frame, including those sent during
+    /// [`build`](Self::build), to `observer`. See [`Sps30::with_observer`].
+    pub fn observer<O2: FrameObserver>(self, observer: O2) -> Sps30Builder<Tx, Rx, D, O2, L> {
+        Sps30Builder {
+            uart_tx: self.uart_tx,
+            uart_rx: self.uart_rx,
+            delay: self.delay,
+            observer,
+            logger: self.logger,
+            reset: self.reset,
+            start_measurement: self.start_measurement,
+            clean_fan: self.clean_fan,
+            format: self.format,
+            calibration: self.calibration,
+            retries: self.retries,
+            resync_limit: self.resync_limit,
+            timeout_ms: self.timeout_ms,
+            address: self.address,
+            drain_before_send: self.drain_before_send,
+        }
+    }
+
+    /// Reports every command, response and error, including those during
+    /// [`build`](Self::build), to `logger`. See [`Sps30::with_logger`].
+    pub fn logger<L2: ProtocolLogger>(self, logger: L2) -> Sps30Builder<Tx, Rx, D, O, L2> {
+        Sps30Builder {
+            uart_tx: self.uart_tx,
+            uart_rx: self.uart_rx,
+            delay: self.delay,
+            observer: self.observer,
+            logger,
+            reset: self.reset,
+            start_measurement: self.start_measurement,
+            clean_fan: self.clean_fan,
+            format: self.format,
+            calibration: self.calibration,
+            retries: self.retries,
+            resync_limit: self.resync_limit,
+            timeout_ms: self.timeout_ms,
+            address: self.address,
+            drain_before_send: self.drain_before_send,
+        }
+    }
+
+    /// Constructs the driver then resets the device and starts measuring
+    /// unless skipped.
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    pub async fn build(self) -> Result<Sps30<Tx, Rx, D, O, L>, Error<Tx::Error, Rx::Error>> {
+        self.build_diagnosed().await.map_err(|e| e.error)
+    }
+
+    /// Like [`Self::build`] but on failure also reports the likely cause,
+    /// based on the bytes seen on the uart. Tells apart swapped RX and TX
+    /// or an unpowered sensor, a wrong baud rate and a noisy line.
+    ///
+    /// # Errors
+    /// See [`Self::build`], the error is wrapped in an [`InitError`].
+    pub async fn build_diagnosed(
+        self,
+    ) -> Result<Sps30<Tx, Rx, D, O, L>, InitError<Tx::Error, Rx::Error>> {
+        let mut device = ShdlcDevice::new(self.uart_tx, self.uart_rx, self.delay)
+            .with_observer(self.observer)
+            .with_logger(self.logger);
+        device.set_address(self.address);
+        device.set_resync_limit(self.resync_limit);
+        device.set_timeout(self.timeout_ms);
+        device.set_retries(self.retries);
+        device.set_drain_before_send_with(self.drain_before_send);
+
+        let mut sensor = Sps30 {
+            device,
+            format: self.format,
+            calibration: self.calibration,
+            mode: None,
+            identity: None,
+        };
+        let result = async {
+            if self.reset {
+                sensor.reset().await?;
+            }
+            if self.start_measurement {
+                sensor.start_measurement().await?;
+                if self.clean_fan {
+                    sensor.start_fan_cleaning().await?;
+                    sensor.device.delay().delay_ms(FAN_CLEANING_MS).await;
+                }
+            }
+            Ok(())
+        }
+        .await;
+        match result {
+            Ok(()) => Ok(sensor),
+            Err(error) => Err(InitError {
+                diagnosis: Diagnosis::new(&error, &sensor.stats()),
+                error,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mock::{MockSps30, NoDelay};
+    use crate::{MeasurementFormat, Sps30};
+    use futures::executor::block_on;
+
+    #[test]
+    fn builder_options() {
+        let mock = MockSps30::new().with_address(3);
+        block_on(Sps30::builder(&mock, &mock, NoDelay).address(3).build()).unwrap();
+        // starting again would fail as the sensor is already measuring
+        let mut sensor = block_on(
+            Sps30::builder(&mock, &mock, NoDelay)
+                .address(3)
+                .skip_reset()
+                .skip_start_measurement()
+                .build(),
+        )
+        .unwrap();
+        assert_eq!(sensor.stats().frames_received, 0);
+
+        block_on(sensor.stop_measurement()).unwrap();
+        let mut sensor = block_on(
+            Sps30::builder(&mock, &mock, NoDelay)
+                .address(3)
+                .measurement_format(MeasurementFormat::Integer)
+                .build(),
+        )
+        .unwrap();
+        let measurement = block_on(sensor.read_measurement()).unwrap();
+        assert_eq!(measurement.mass_pm2_5, 5.0);
+        assert_eq!(measurement.typical_particle_size, 0.55);
+    }
+
+    #[test]
+    fn clean_fan_on_start() {
+        let mock = MockSps30::new();
+        block_on(
+            Sps30::builder(&mock, &mock, NoDelay)
+                .clean_fan_on_start()
+                .build(),
+        )
+        .unwrap();
+        assert!(mock.is_measuring());
+        assert_eq!(mock.commands_received(), 3);
+    }
+}
@@ -0,0 +1,403 @@
+//! Corrections applied to measurements, for example the linear fit from a
+//! co-location study against a reference instrument or a correction for
+//! humidity.
+//!
+//! Corrections implement [`Correction`], chain them using a tuple:
+//!
+//! ```
+//! use sps30_async::calibration::{Calibration, Correction, HumidityCorrection, Smoothing};
+//! use sps30_async::Measurement;
+//!
+//! let mut chain = (
+//!     HumidityCorrection::default().at(60.0),
+//!     Calibration::IDENTITY,
+//!     Smoothing::new(0.2),
+//! );
+//! let mut measurement = Measurement::default();
+//! chain.apply(&mut measurement);
+//! ```
+
+use crate::context::Context;
+use crate::Measurement;
+
+/// Post processing step for measurements, applied in place. Steps can keep
+/// state, for example to smooth successive measurements.
+///
+/// See [`Sps30::read_measurement_corrected`](crate::Sps30::read_measurement_corrected).
+pub trait Correction {
+    fn apply(&mut self, measurement: &mut Measurement);
+
+    /// Called with the current conditions before [`apply`](Self::apply)
+    /// when they are known. Does nothing by default.
+    fn set_context(&mut self, context: &Context) {
+        let _ = context;
+    }
+}
+
+/// Leaves measurements unchanged
+impl Correction for () {
+    fn apply(&mut self, _: &mut Measurement) {}
+}
+
+impl<C: Correction + ?Sized> Correction for &mut C {
+    fn apply(&mut self, measurement: &mut Measurement) {
+        C::apply(self, measurement);
+    }
+
+    fn set_context(&mut self, context: &Context) {
+        C::set_context(self, context);
+    }
+}
+
+/// Applies the corrections from left to right
+macro_rules! chain {
+    ($($name:ident),+) => {
+        impl<$($name: Correction),+> Correction for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn apply(&mut self, measurement: &mut Measurement) {
+                let ($($name,)+) = self;
+                $($name.apply(measurement);)+
+            }
+
+            #[allow(non_snake_case)]
+            fn set_context(&mut self, context: &Context) {
+                let ($($name,)+) = self;
+                $($name.set_context(context);)+
+            }
+        }
+    };
+}
+
+chain!(A);
+chain!(A, B);
+chain!(A, B, C);
+chain!(A, B, C, D);
+chain!(A, B, C, D, E);
+
+/// `corrected = gain * raw + offset`
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Linear {
+    pub gain: f32,
+    /// In µg/m³
+    pub offset: f32,
+}
+
+impl Linear {
+    /// Leaves values unchanged
+    pub const IDENTITY: Self = Self {
+        gain: 1.0,
+        offset: 0.0,
+    };
+
+    /// The corrected value, never negative
+    #[must_use]
+    pub fn apply(&self, raw: f32) -> f32 {
+        (self.gain * raw + self.offset).max(0.0)
+    }
+}
+
+impl Default for Linear {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Linear corrections for the mass concentrations. Attach it to the driver
+/// with [`Sps30Builder::calibration`](crate::Sps30Builder::calibration) or
+/// [`Sps30::set_calibration`](crate::Sps30::set_calibration) to correct
+/// every measurement it returns.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Calibration {
+    pub pm1_0: Linear,
+    pub pm2_5: Linear,
+    pub pm4_0: Linear,
+    pub pm10: Linear,
+}
+
+impl Calibration {
+    /// Leaves measurements unchanged
+    pub const IDENTITY: Self = Self {
+        pm1_0: Linear::IDENTITY,
+        pm2_5: Linear::IDENTITY,
+        pm4_0: Linear::IDENTITY,
+        pm10: Linear::IDENTITY,
+    };
+
+    /// The same correction for all mass concentrations
+    #[must_use]
+    pub fn uniform(correction: Linear) -> Self {
+        Self {
+            pm1_0: correction,
+            pm2_5: correction,
+            pm4_0: correction,
+            pm10: correction,
+        }
+    }
+
+    /// Corrects the mass concentrations, other fields are unchanged
+    #[must_use]
+    pub fn apply(&self, raw: Measurement) -> Measurement {
+        Measurement {
+            mass_pm1_0: self.pm1_0.apply(raw.mass_pm1_0),
+            mass_pm2_5: self.pm2_5.apply(raw.mass_pm2_5),
+            mass_pm4_0: self.pm4_0.apply(raw.mass_pm4_0),
+            mass_pm10: self.pm10.apply(raw.mass_pm10),
+            ..raw
+        }
+    }
+}
+
+impl Correction for Calibration {
+    fn apply(&mut self, measurement: &mut Measurement) {
+        *measurement = Calibration::apply(self, *measurement);
+    }
+}
+
+/// Relative humidity above which the correction is capped, the growth
+/// model diverges towards 100%
+const MAX_RELATIVE_HUMIDITY: f32 = 95.0;
+/// Density of water relative to dry particles, as used by Crilley et al.
+const DENSITY_RATIO: f32 = 1.65;
+
+/// Corrects mass concentrations for water taken up by particles at high
+/// humidity, which optical sensors count as particle mass. Uses the
+/// single parameter κ-Köhler growth model:
+///
+/// `corrected = raw / (1 + (κ / 1.65) / (100 / RH - 1))`
+///
+/// Temperature hardly affects the growth in this model, only the relative
+/// humidity (for example from an SHT sensor) is needed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct HumidityCorrection {
+    /// Hygroscopicity of the aerosol, from 0 (no growth) to about 1.2 for
+    /// sea salt
+    pub kappa: f32,
+}
+
+impl Default for HumidityCorrection {
+    /// κ = 0.4, typical for mixed urban aerosol
+    fn default() -> Self {
+        Self { kappa: 0.4 }
+    }
+}
+
+/// A measurement before and after a [`HumidityCorrection`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct HumidityCorrected {
+    pub raw: Measurement,
+    pub corrected: Measurement,
+}
+
+impl HumidityCorrection {
+    /// Corrects the mass concentrations in `raw` measured at
+    /// `relative_humidity` (0 to 100%), other fields are unchanged.
+    /// Humidity above 95% is treated as 95%.
+    #[must_use]
+    pub fn apply(&self, raw: Measurement, relative_humidity: f32) -> HumidityCorrected {
+        let rh = relative_humidity.clamp(0.0, MAX_RELATIVE_HUMIDITY);
+        let growth = if rh == 0.0 {
+            1.0
+        } else {
+            1.0 + (self.kappa / DENSITY_RATIO) / (100.0 / rh - 1.0)
+        };
+        let corrected = Measurement {
+            mass_pm1_0: raw.mass_pm1_0 / growth,
+            mass_pm2_5: raw.mass_pm2_5 / growth,
+            mass_pm4_0: raw.mass_pm4_0 / growth,
+            mass_pm10: raw.mass_pm10 / growth,
+            ..raw
+        };
+        HumidityCorrected { raw, corrected }
+    }
+
+    /// This correction at a fixed humidity, to use it as a [`Correction`].
+    /// Update the humidity with [`AtHumidity::set`].
+    #[must_use]
+    pub fn at(self, relative_humidity: f32) -> AtHumidity {
+        AtHumidity {
+            correction: self,
+            relative_humidity,
+        }
+    }
+}
+
+/// A [`HumidityCorrection`] for the last known humidity
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AtHumidity {
+    correction: HumidityCorrection,
+    relative_humidity: f32,
+}
+
+impl AtHumidity {
+    /// Update the relative humidity (0 to 100%)
+    pub fn set(&mut self, relative_humidity: f32) {
+        self.relative_humidity = relative_humidity;
+    }
+}
+
+impl Correction for AtHumidity {
+    fn apply(&mut self, measurement: &mut Measurement) {
+        *measurement = self
+            .correction
+            .apply(*measurement, self.relative_humidity)
+            .corrected;
+    }
+
+    /// Uses the relative humidity if known
+    fn set_context(&mut self, context: &Context) {
+        if let Some(relative_humidity) = context.relative_humidity {
+            self.relative_humidity = relative_humidity;
+        }
+    }
+}
+
+/// Exponential moving average of all fields, evens out the noise of
+/// successive measurements.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Smoothing {
+    alpha: f32,
+    average: Option<Measurement>,
+}
+
+impl Smoothing {
+    /// `alpha` is the weight of a new measurement, between 0 and 1.
+    /// Smaller values smooth more but respond slower.
+    #[must_use]
+    pub fn new(alpha: f32) -> Self {
+        Self {
+            alpha: alpha.clamp(0.0, 1.0),
+            average: None,
+        }
+    }
+}
+
+impl Correction for Smoothing {
+    fn apply(&mut self, measurement: &mut Measurement) {
+        let average = match self.average {
+            None => *measurement,
+            Some(average) => average + (*measurement - average) * self.alpha,
+        };
+        self.average = Some(average);
+        *measurement = average;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Calibration, Correction, HumidityCorrection, Linear, Smoothing};
+    use crate::context::Context;
+    use crate::mock::{MockSps30, NoDelay};
+    use crate::{Measurement, Sps30};
+    use futures::executor::block_on;
+
+    #[test]
+    fn corrects_mass_only() {
+        let calibration = Calibration {
+            pm2_5: Linear {
+                gain: 0.5,
+                offset: -1.0,
+            },
+            ..Calibration::IDENTITY
+        };
+        let raw = Measurement {
+            mass_pm2_5: 10.0,
+            mass_pm10: 12.0,
+            number_pm2_5: 30.0,
+            ..Measurement::default()
+        };
+        let corrected = calibration.apply(raw);
+        assert_eq!(corrected.mass_pm2_5, 4.0);
+        assert_eq!(corrected.mass_pm10, 12.0);
+        assert_eq!(corrected.number_pm2_5, 30.0);
+        assert_eq!(calibration.apply(Measurement::default()).mass_pm2_5, 0.0);
+    }
+
+    #[test]
+    fn humidity_growth() {
+        let raw = Measurement {
+            mass_pm2_5: 20.0,
+            number_pm2_5: 30.0,
+            ..Measurement::default()
+        };
+        let correction = HumidityCorrection { kappa: 0.33 };
+        let dry = correction.apply(raw, 0.0);
+        assert_eq!(dry.corrected, raw);
+
+        // growth factor 1 + 0.2 / (100 / 50 - 1) = 1.2
+        let humid = correction.apply(raw, 50.0);
+        assert!((humid.corrected.mass_pm2_5 - 20.0 / 1.2).abs() < 1e-4);
+        assert_eq!(humid.corrected.number_pm2_5, 30.0);
+        assert_eq!(humid.raw, raw);
+
+        let saturated = correction.apply(raw, 100.0).corrected.mass_pm2_5;
+        assert_eq!(saturated, correction.apply(raw, 95.0).corrected.mass_pm2_5);
+    }
+
+    #[test]
+    fn chained_corrections() {
+        let calibration = Calibration::uniform(Linear {
+            gain: 1.0,
+            offset: 10.0,
+        });
+        let mut chain = (calibration, Smoothing::new(0.5));
+        let mut first = Measurement::default();
+        chain.apply(&mut first);
+        assert_eq!(first.mass_pm2_5, 10.0);
+
+        let mut second = Measurement {
+            mass_pm2_5: 20.0,
+            ..Measurement::default()
+        };
+        chain.apply(&mut second);
+        assert_eq!(second.mass_pm2_5, 20.0);
+    }
+
+    #[test]
+    fn context_sets_humidity() {
+        let mut chain = (HumidityCorrection { kappa: 0.33 }.at(0.0),);
+        chain.set_context(&Context {
+            relative_humidity: Some(50.0),
+            ..Context::default()
+        });
+        let mut measurement = Measurement {
+            mass_pm2_5: 12.0,
+            ..Measurement::default()
+        };
+        chain.apply(&mut measurement);
+        assert!((measurement.mass_pm2_5 - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn calibrated_measurements() {
+        let mock = MockSps30::new();
+        let calibration = Calibration::uniform(Linear {
+            gain: 2.0,
+            offset: 1.0,
+        });
+        let mut sensor = block_on(
+            Sps30::builder(&mock, &mock, NoDelay)
+                .calibration(calibration)
+                .build(),
+        )
+        .unwrap();
+        let measurement = block_on(sensor.read_measurement()).unwrap();
+        assert_eq!(
+            measurement.mass_pm10,
+            mock.measurement().mass_pm10 * 2.0 + 1.0
+        );
+
+        sensor.set_calibration(Calibration::IDENTITY);
+        let measurement = block_on(sensor.read_measurement()).unwrap();
+        assert_eq!(measurement.mass_pm10, mock.measurement().mass_pm10);
+    }
+}
@@ -0,0 +1,137 @@
+//! The interval of the automatic fan cleaning, see [`CleaningInterval`].
+
+use core::fmt;
+use core::time::Duration;
+
+/// How often the sensor cleans its fan by itself, stored in the
+/// non-volatile memory of the sensor. The sensor takes zero to mean
+/// disabled, that is only ever written if asked for explicitly with
+/// [`DISABLED`](Self::DISABLED).
+///
+/// ```
+/// use core::time::Duration;
+/// use sps30_async::CleaningInterval;
+///
+/// let daily = CleaningInterval::from_secs(24 * 60 * 60).unwrap();
+/// assert_eq!(daily.duration(), Some(Duration::from_secs(86_400)));
+/// assert_eq!(CleaningInterval::from_secs(0), None);
+/// assert!(CleaningInterval::DISABLED.is_disabled());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(transparent)
+)]
+#[cfg_attr(
+    feature = "postcard",
+    derive(postcard::experimental::max_size::MaxSize)
+)]
+pub struct CleaningInterval(u32);
+
+impl CleaningInterval {
+    /// No automatic cleaning, clean using
+    /// [`start_fan_cleaning`](crate::Sps30::start_fan_cleaning) instead
+    pub const DISABLED: Self = Self(0);
+    /// Once a week, the factory setting
+    pub const DEFAULT: Self = Self(7 * 24 * 60 * 60);
+
+    /// Clean every `secs` seconds, `None` if `secs` is zero. Use
+    /// [`DISABLED`](Self::DISABLED) to disable the cleaning.
+    #[must_use]
+    pub const fn from_secs(secs: u32) -> Option<Self> {
+        if secs == 0 {
+            None
+        } else {
+            Some(Self(secs))
+        }
+    }
+
+    /// Clean every `interval`, parts of a second are rounded down. `None`
+    /// if that is less than a second or more than `u32::MAX` seconds.
+    #[must_use]
+    pub fn from_duration(interval: Duration) -> Option<Self> {
+        u32::try_from(interval.as_secs())
+            .ok()
+            .and_then(Self::from_secs)
+    }
+
+    /// The value the sensor stores, zero if disabled
+    #[must_use]
+    pub const fn from_raw(secs: u32) -> Self {
+        Self(secs)
+    }
+
+    /// The value the sensor stores, zero if disabled
+    #[must_use]
+    pub const fn raw(self) -> u32 {
+        self.0
+    }
+
+    /// Whether the automatic cleaning is off
+    #[must_use]
+    pub const fn is_disabled(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Seconds between cleanings, `None` if disabled
+    #[must_use]
+    pub const fn secs(self) -> Option<u32> {
+        if self.is_disabled() {
+            None
+        } else {
+            Some(self.0)
+        }
+    }
+
+    /// Time between cleanings, `None` if disabled
+    #[must_use]
+    pub fn duration(self) -> Option<Duration> {
+        self.secs().map(|secs| Duration::from_secs(u64::from(secs)))
+    }
+}
+
+impl Default for CleaningInterval {
+    /// [`CleaningInterval::DEFAULT`]
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// `disabled` or the interval in seconds, e.g. `604800 s`
+impl fmt::Display for CleaningInterval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.secs() {
+            None => f.write_str("disabled"),
+            Some(secs) => write!(f, "{secs} s"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::time::Duration;
+
+    use super::CleaningInterval;
+
+    #[test]
+    fn validates_range() {
+        assert_eq!(
+            CleaningInterval::from_duration(Duration::from_millis(999)),
+            None
+        );
+        assert_eq!(
+            CleaningInterval::from_duration(Duration::from_secs(1 << 32)),
+            None
+        );
+        let interval = CleaningInterval::from_duration(Duration::from_millis(1_500)).unwrap();
+        assert_eq!(interval.secs(), Some(1));
+
+        let read_back = CleaningInterval::from_raw(0);
+        assert!(read_back.is_disabled());
+        assert_eq!(read_back.duration(), None);
+        assert_eq!(read_back.to_string(), "disabled");
+        assert_eq!(CleaningInterval::default().to_string(), "604800 s");
+    }
+}
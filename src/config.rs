@@ -0,0 +1,114 @@
+//! Everything configurable about a sensor in one value, see [`Config`],
+//! and everything that can be read about it, see [`Snapshot`].
+
+use crate::shdlc::DEFAULT_ADDRESS;
+use crate::{CleaningInterval, DeviceInfo, DeviceStatus, MeasurementFormat};
+
+/// A known configuration to push to a sensor with
+/// [`Sps30::apply_config`](crate::Sps30::apply_config), for example the
+/// same one to every sensor of a fleet. Read it back with
+/// [`Sps30::read_config`](crate::Sps30::read_config).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Config {
+    /// SHDLC address of the sensor, only used by the driver. Can not be the
+    /// [`BROADCAST_ADDRESS`](crate::shdlc::BROADCAST_ADDRESS).
+    pub address: u8,
+    /// Encoding of the measurements, used from the next start of the
+    /// measurement
+    pub format: MeasurementFormat,
+    /// Interval of the automatic fan cleaning, stored on the sensor
+    pub cleaning_interval: CleaningInterval,
+    /// Run a fan cleaning once the configuration is applied. Not stored,
+    /// always `false` when read back.
+    pub clean_fan: bool,
+}
+
+impl Default for Config {
+    /// The factory configuration
+    fn default() -> Self {
+        Self {
+            address: DEFAULT_ADDRESS,
+            format: MeasurementFormat::Float,
+            cleaning_interval: CleaningInterval::DEFAULT,
+            clean_fan: false,
+        }
+    }
+}
+
+/// Everything configurable or identifying read from a sensor in one go,
+/// see [`Sps30::snapshot`](crate::Sps30::snapshot). For audit logs and
+/// fleet inventories.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Snapshot {
+    pub config: Config,
+    pub info: DeviceInfo,
+    /// The status register, not cleared by reading it
+    pub status: DeviceStatus,
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+    use crate::mock::{MockSps30, NoDelay};
+    use crate::shdlc::BROADCAST_ADDRESS;
+    use crate::{CleaningInterval, DeviceStatus, Error, MeasurementFormat, Sps30};
+    use futures::executor::block_on;
+
+    #[test]
+    fn apply_config() {
+        let mock = MockSps30::new();
+        let mut sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+        let config = Config {
+            format: MeasurementFormat::Integer,
+            cleaning_interval: CleaningInterval::DISABLED,
+            clean_fan: true,
+            ..Config::default()
+        };
+        block_on(sensor.apply_config(config)).unwrap();
+        let applied = Config {
+            clean_fan: false,
+            ..config
+        };
+        assert_eq!(block_on(sensor.read_config()), Ok(applied));
+        assert!(mock.is_measuring());
+        let measurement = block_on(sensor.read_measurement()).unwrap();
+        assert!((measurement.mass_pm10 - mock.measurement().mass_pm10).abs() < 0.01);
+
+        let sent = mock.commands_received();
+        block_on(sensor.apply_config(applied)).unwrap();
+        assert_eq!(
+            mock.commands_received(),
+            sent + 1,
+            "only reads the interval"
+        );
+
+        let broadcast = Config {
+            address: BROADCAST_ADDRESS,
+            ..applied
+        };
+        let sent = mock.commands_received();
+        assert_eq!(
+            block_on(sensor.apply_config(broadcast)),
+            Err(Error::ArgumentOutOfRange)
+        );
+        assert_eq!(sensor.address(), applied.address);
+        assert_eq!(mock.commands_received(), sent);
+    }
+
+    #[test]
+    fn snapshot() {
+        let mock = MockSps30::new()
+            .with_serial_number("SNAPSHOT")
+            .with_status_register(DeviceStatus::LASER_FAILURE.bits());
+        let mut sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+        let snapshot = block_on(sensor.snapshot()).unwrap();
+        assert_eq!(snapshot.config, Config::default());
+        assert_eq!(snapshot.info.serial_number, "SNAPSHOT");
+        assert_eq!(snapshot.status, DeviceStatus::LASER_FAILURE);
+        assert_eq!(block_on(sensor.snapshot()).unwrap(), snapshot);
+    }
+}
@@ -0,0 +1,224 @@
+//! Golden frames replayed through the whole driver: encoding, the uart
+//! read loop, decoding and parsing. These lock in the bytes on the wire
+//! across refactors.
+//!
+//! The requests and the empty responses are the examples from the SPS30
+//! datasheet (section 5). Responses carrying data follow the same layout
+//! with values chosen to need escaping.
+
+use core::cell::{Cell, RefCell};
+use core::convert::Infallible;
+
+use embedded_io_async::{ErrorType, Read, Write};
+use futures::executor::block_on;
+use heapless::Vec;
+
+use crate::mock::NoDelay;
+use crate::{
+    CleaningInterval, DeviceError, DeviceStatus, Error, Measurement, ProtocolError, Sps30, Version,
+    Versions,
+};
+
+/// A request and the response the device sends to it
+struct Exchange {
+    /// Bytes sent to the device
+    mosi: &'static [u8],
+    /// Bytes the device sends back
+    miso: &'static [u8],
+}
+
+const START_MEASUREMENT: Exchange = Exchange {
+    mosi: &[0x7E, 0x00, 0x00, 0x02, 0x01, 0x03, 0xF9, 0x7E],
+    miso: &[0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E],
+};
+
+const STOP_MEASUREMENT: Exchange = Exchange {
+    mosi: &[0x7E, 0x00, 0x01, 0x00, 0xFE, 0x7E],
+    miso: &[0x7E, 0x00, 0x01, 0x00, 0x00, 0xFE, 0x7E],
+};
+
+/// No new measurement available yet
+const READ_MEASUREMENT_EMPTY: Exchange = Exchange {
+    mosi: &[0x7E, 0x00, 0x03, 0x00, 0xFC, 0x7E],
+    miso: &[0x7E, 0x00, 0x03, 0x00, 0x00, 0xFC, 0x7E],
+};
+
+/// Mass 2.5, 3.0, 3.5 and 4.0, number 10.0, 12.0, 13.0, 13.5 and 14.0,
+/// typical size 0.5
+const READ_MEASUREMENT: Exchange = Exchange {
+    mosi: &[0x7E, 0x00, 0x03, 0x00, 0xFC, 0x7E],
+    miso: &[
+        0x7E, 0x00, 0x03, 0x00, 0x28, 0x40, 0x20, 0x00, 0x00, 0x40, 0x40, 0x00, 0x00, 0x40, 0x60,
+        0x00, 0x00, 0x40, 0x80, 0x00, 0x00, 0x41, 0x20, 0x00, 0x00, 0x41, 0x40, 0x00, 0x00, 0x41,
+        0x50, 0x00, 0x00, 0x41, 0x58, 0x00, 0x00, 0x41, 0x60, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00,
+        0xA8, 0x7E,
+    ],
+};
+
+const SLEEP: Exchange = Exchange {
+    mosi: &[0x7E, 0x00, 0x10, 0x00, 0xEF, 0x7E],
+    miso: &[0x7E, 0x00, 0x10, 0x00, 0x00, 0xEF, 0x7E],
+};
+
+/// Preceded by a 0xFF byte to wake the uart, the command (0x11) is escaped
+const WAKE_UP: Exchange = Exchange {
+    mosi: &[0xFF, 0x7E, 0x00, 0x7D, 0x31, 0x00, 0xEE, 0x7E],
+    miso: &[0x7E, 0x00, 0x7D, 0x31, 0x00, 0x00, 0xEE, 0x7E],
+};
+
+const START_FAN_CLEANING: Exchange = Exchange {
+    mosi: &[0x7E, 0x00, 0x56, 0x00, 0xA9, 0x7E],
+    miso: &[0x7E, 0x00, 0x56, 0x00, 0x00, 0xA9, 0x7E],
+};
+
+/// Fan cleaning outside measurement mode, state 0x43
+const START_FAN_CLEANING_IDLE: Exchange = Exchange {
+    mosi: &[0x7E, 0x00, 0x56, 0x00, 0xA9, 0x7E],
+    miso: &[0x7E, 0x00, 0x56, 0x43, 0x00, 0x66, 0x7E],
+};
+
+/// 604800 seconds (one week), the checksum of the request is escaped
+const READ_CLEANING_INTERVAL: Exchange = Exchange {
+    mosi: &[0x7E, 0x00, 0x80, 0x01, 0x00, 0x7D, 0x5E, 0x7E],
+    miso: &[
+        0x7E, 0x00, 0x80, 0x00, 0x04, 0x00, 0x09, 0x3A, 0x80, 0xB8, 0x7E,
+    ],
+};
+
+/// Firmware 2.2, hardware 7, SHDLC 2.0
+const READ_VERSION: Exchange = Exchange {
+    mosi: &[0x7E, 0x00, 0xD1, 0x00, 0x2E, 0x7E],
+    miso: &[
+        0x7E, 0x00, 0xD1, 0x00, 0x07, 0x02, 0x02, 0x00, 0x07, 0x00, 0x02, 0x00, 0x1A, 0x7E,
+    ],
+};
+
+/// Fan speed warning (bit 21) and fan failure (bit 4)
+const READ_STATUS_REGISTER: Exchange = Exchange {
+    mosi: &[0x7E, 0x00, 0xD2, 0x01, 0x00, 0x2C, 0x7E],
+    miso: &[
+        0x7E, 0x00, 0xD2, 0x00, 0x05, 0x00, 0x20, 0x00, 0x10, 0x00, 0xF8, 0x7E,
+    ],
+};
+
+const RESET: Exchange = Exchange {
+    mosi: &[0x7E, 0x00, 0xD3, 0x00, 0x2C, 0x7E],
+    miso: &[0x7E, 0x00, 0xD3, 0x00, 0x00, 0x2C, 0x7E],
+};
+
+/// Uart recording what is sent and answering with a fixed response
+struct Replay {
+    sent: RefCell<Vec<u8, 64>>,
+    miso: &'static [u8],
+    read: Cell<usize>,
+}
+
+impl ErrorType for &Replay {
+    type Error = Infallible;
+}
+
+impl Write for &Replay {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.sent
+            .borrow_mut()
+            .extend_from_slice(buf)
+            .expect("requests are short");
+        Ok(buf.len())
+    }
+}
+
+impl Read for &Replay {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let remaining = &self.miso[self.read.get()..];
+        let n = remaining.len().min(buf.len());
+        buf[..n].copy_from_slice(&remaining[..n]);
+        self.read.set(self.read.get() + n);
+        Ok(n)
+    }
+}
+
+/// Runs `request` against a device answering with `exchange.miso`, checks
+/// it sent `exchange.mosi`
+fn replay<T>(
+    exchange: &Exchange,
+    request: impl FnOnce(&mut Sps30<&Replay, &Replay, NoDelay>) -> T,
+) -> T {
+    let uart = Replay {
+        sent: RefCell::new(Vec::new()),
+        miso: exchange.miso,
+        read: Cell::new(0),
+    };
+    let mut sensor = Sps30::from_tx_rx_uninit(&uart, &uart, NoDelay);
+    let result = request(&mut sensor);
+    assert_eq!(uart.sent.borrow().as_slice(), exchange.mosi);
+    assert_eq!(uart.read.get(), exchange.miso.len(), "response not read");
+    result
+}
+
+#[test]
+fn measurement_cycle() {
+    replay(&START_MEASUREMENT, |s| block_on(s.start_measurement())).unwrap();
+    assert_eq!(
+        replay(&READ_MEASUREMENT_EMPTY, |s| block_on(s.read_measurement())),
+        Err(Error::Protocol(ProtocolError::EmptyResult))
+    );
+    let measurement = replay(&READ_MEASUREMENT, |s| block_on(s.read_measurement())).unwrap();
+    assert_eq!(
+        measurement,
+        Measurement {
+            mass_pm1_0: 2.5,
+            mass_pm2_5: 3.0,
+            mass_pm4_0: 3.5,
+            mass_pm10: 4.0,
+            mass_pm0_5: 10.0,
+            number_pm1_0: 12.0,
+            number_pm2_5: 13.0,
+            number_pm4_0: 13.5,
+            number_pm10: 14.0,
+            typical_particle_size: 0.5,
+        }
+    );
+    replay(&STOP_MEASUREMENT, |s| block_on(s.stop_measurement())).unwrap();
+}
+
+#[test]
+fn sleep_and_wake_up() {
+    replay(&SLEEP, |s| block_on(s.sleep())).unwrap();
+    replay(&WAKE_UP, |s| block_on(s.wake_up())).unwrap();
+}
+
+#[test]
+fn maintenance() {
+    replay(&START_FAN_CLEANING, |s| block_on(s.start_fan_cleaning())).unwrap();
+    assert_eq!(
+        replay(&START_FAN_CLEANING_IDLE, |s| block_on(
+            s.start_fan_cleaning()
+        )),
+        Err(Error::Device(DeviceError::InvalidStateForCommand))
+    );
+    let interval = replay(&READ_CLEANING_INTERVAL, |s| {
+        block_on(s.read_cleaning_interval())
+    });
+    assert_eq!(interval, Ok(CleaningInterval::DEFAULT));
+    replay(&RESET, |s| block_on(s.reset())).unwrap();
+}
+
+#[test]
+fn identification() {
+    let versions = replay(&READ_VERSION, |s| block_on(s.versions())).unwrap();
+    assert_eq!(
+        versions,
+        Versions {
+            firmware: Version { major: 2, minor: 2 },
+            hardware_revision: 7,
+            shdlc: Version { major: 2, minor: 0 },
+        }
+    );
+    let status = replay(&READ_STATUS_REGISTER, |s| {
+        block_on(s.read_device_status_register(false))
+    });
+    assert_eq!(
+        status,
+        Ok(DeviceStatus::FAN_SPEED_WARNING | DeviceStatus::FAN_FAILURE)
+    );
+}
@@ -0,0 +1,29 @@
+//! Environmental conditions measured next to the sensor. Particulate
+//! matter readings are hard to interpret without them, and some
+//! [corrections](crate::calibration::Correction) need them.
+
+use crate::Measurement;
+
+/// Conditions at the time of a measurement, fields are `None` when not
+/// measured
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Context {
+    /// In °C
+    pub temperature_c: Option<f32>,
+    /// In %, 0 to 100
+    pub relative_humidity: Option<f32>,
+    /// In hPa
+    pub pressure_hpa: Option<f32>,
+}
+
+/// A measurement with the conditions it was taken in, see
+/// [`Sps30::read_measurement_with_context`](crate::Sps30::read_measurement_with_context)
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct WithContext {
+    pub measurement: Measurement,
+    pub context: Context,
+}
@@ -0,0 +1,106 @@
+//! Ready made loop for embassy users: read the sensor every second and
+//! publish the measurements to a channel.
+//!
+//! ```ignore
+//! static MEASUREMENTS: Channel<CriticalSectionRawMutex, Measurement, 4> = Channel::new();
+//!
+//! #[embassy_executor::task]
+//! async fn sensor_task(mut sensor: Sps30<UartTx<'static>, UartRx<'static>, Delay>) -> ! {
+//!     sps30_async::embassy::publish_measurements(&mut sensor, MEASUREMENTS.sender()).await
+//! }
+//! ```
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::channel::Sender;
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+use crate::shdlc::{FrameObserver, ProtocolLogger};
+use crate::{MaybeFormat, Measurement, Sps30};
+
+/// Time between measurements, the sensor updates once a second
+const INTERVAL_MS: u32 = 1_000;
+/// Reset the sensor after this many failed reads in a row
+const MAX_FAILURES: u32 = 3;
+
+/// Reads a measurement every second and sends it to `sender`. Recoverable
+/// errors are retried on the next read. After repeated or unrecoverable
+/// errors the sensor is reset and measuring restarted. Errors are logged
+/// at debug level using defmt, if the `defmt` feature is enabled.
+///
+/// The sensor should be initialized, for example using
+/// [`Sps30::from_tx_rx`].
+pub async fn publish_measurements<Tx, Rx, D, O, L, M, const N: usize>(
+    sensor: &mut Sps30<Tx, Rx, D, O, L>,
+    sender: Sender<'_, M, Measurement, N>,
+) -> !
+where
+    Tx: Write,
+    Tx::Error: MaybeFormat,
+    Rx: Read,
+    Rx::Error: MaybeFormat,
+    D: DelayNs,
+    O: FrameObserver,
+    L: ProtocolLogger,
+    M: RawMutex,
+{
+    let mut failures = 0;
+    loop {
+        sensor.device.delay().delay_ms(INTERVAL_MS).await;
+        match sensor.read_measurement().await {
+            Ok(measurement) => {
+                failures = 0;
+                sender.send(measurement).await;
+                continue;
+            }
+            Err(e) if e.is_recoverable() && failures + 1 < MAX_FAILURES => {
+                debug!("Could not read sps30, retrying: {}", e);
+                failures += 1;
+                continue;
+            }
+            Err(e) => debug!("Could not read sps30, resetting: {}", e),
+        }
+
+        failures = 0;
+        let restarted = match sensor.reset().await {
+            Ok(()) => sensor.start_measurement().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = restarted {
+            debug!("Could not restart sps30: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::publish_measurements;
+    use crate::mock::{MockSps30, NoDelay};
+    use crate::Sps30;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use embassy_sync::channel::Channel;
+    use futures::executor::block_on;
+    use futures::future::{select, Either};
+    use futures::pin_mut;
+
+    #[test]
+    fn publishes_and_recovers() {
+        let mock = MockSps30::new();
+        let channel = Channel::<NoopRawMutex, _, 1>::new();
+        let mut sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+
+        let publisher = publish_measurements(&mut sensor, channel.sender());
+        let receiver = async {
+            let first = channel.receive().await;
+            mock.fail_next(0x43); // device stopped measuring
+            let second = channel.receive().await;
+            (first, second)
+        };
+        pin_mut!(publisher, receiver);
+        // the publisher never returns
+        let Either::Right(((first, second), _)) = block_on(select(publisher, receiver));
+        assert_eq!(first.mass_pm1_0, mock.measurement().mass_pm1_0);
+        assert_eq!(second.mass_pm1_0, mock.measurement().mass_pm1_0);
+        assert!(mock.is_measuring());
+    }
+}
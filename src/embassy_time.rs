@@ -0,0 +1,50 @@
+//! Constructors for embassy firmware that wait using the embassy-time
+//! timer, so no delay has to be passed around and the driver type has one
+//! generic parameter less.
+//!
+//! ```ignore
+//! use sps30_async::embassy_time::Sps30;
+//!
+//! let (tx, rx) = uart.split();
+//! let mut sensor: Sps30<_, _> = Sps30::from_uart(tx, rx).await?;
+//! let measurement = sensor.read_measurement().await?;
+//! ```
+
+use embassy_time::Delay;
+use embedded_io_async::{Read, Write};
+
+use crate::{Error, MaybeFormat};
+
+/// The driver waiting using [`embassy_time::Timer`]
+pub type Sps30<Tx, Rx> = crate::Sps30<Tx, Rx, Delay>;
+/// Configures an [`Sps30`] waiting using [`embassy_time::Timer`]
+pub type Sps30Builder<Tx, Rx> = crate::Sps30Builder<Tx, Rx, Delay>;
+
+impl<Tx, Rx> Sps30<Tx, Rx>
+where
+    Tx: Write,
+    Tx::Error: MaybeFormat,
+    Rx: Read,
+    Rx::Error: MaybeFormat,
+{
+    /// Constructs and initializes the driver, see
+    /// [`from_tx_rx`](crate::Sps30::from_tx_rx).
+    ///
+    /// # Errors
+    /// Resetting the device or starting the measurement can fail.
+    pub async fn from_uart(uart_tx: Tx, uart_rx: Rx) -> Result<Self, Error<Tx::Error, Rx::Error>> {
+        Self::from_tx_rx(uart_tx, uart_rx, Delay).await
+    }
+
+    /// Configure the driver before constructing it, see
+    /// [`builder`](crate::Sps30::builder).
+    pub fn uart_builder(uart_tx: Tx, uart_rx: Rx) -> Sps30Builder<Tx, Rx> {
+        Self::builder(uart_tx, uart_rx, Delay)
+    }
+
+    /// Constructs the driver without initializing the device, see
+    /// [`from_tx_rx_uninit`](crate::Sps30::from_tx_rx_uninit).
+    pub fn from_uart_uninit(uart_tx: Tx, uart_rx: Rx) -> Self {
+        Self::from_tx_rx_uninit(uart_tx, uart_rx, Delay)
+    }
+}
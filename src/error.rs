@@ -1,10 +1,16 @@
 #![allow(clippy::module_name_repetitions)]
 use core::fmt;
 
+use crate::{CleaningInterval, MaybeFormat, Mode, Stats};
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "thiserror", derive(thiserror::Error))]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
-#[derive(defmt::Format)]
+#[cfg_attr(
+    feature = "postcard",
+    derive(postcard::experimental::max_size::MaxSize)
+)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum DeviceError {
     /// Wrong data length for last command (too much or little data)
     #[cfg_attr(
@@ -33,9 +39,9 @@ pub enum DeviceError {
     /// Command not allowed in current state
     #[cfg_attr(feature = "thiserror", error("Command not allowed in current state"))]
     InvalidStateForCommand,
-    /// Undocumented error code
-    #[cfg_attr(feature = "thiserror", error("Undocumented error code"))]
-    Unknown,
+    /// Undocumented error code, carries the state byte the device sent
+    #[cfg_attr(feature = "thiserror", error("Undocumented error code: {0:#04x}"))]
+    Unknown(u8),
 }
 
 impl From<u8> for DeviceError {
@@ -47,54 +53,70 @@ impl From<u8> for DeviceError {
             4 => Self::InvalidParam,
             40 => Self::InternalOutOfRange,
             67 => Self::InvalidStateForCommand,
-            _ => Self::Unknown,
+            code => Self::Unknown(code),
         }
     }
 }
 
-#[derive(Debug)]
+/// Failure of the uart the sensor is connected through
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "thiserror", derive(thiserror::Error))]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
-#[derive(defmt::Format)]
-pub enum Error<TxError, RxError>
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum TransportError<TxError, RxError>
 where
-    TxError: defmt::Format + fmt::Debug,
-    RxError: defmt::Format + fmt::Debug,
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
 {
     /// Serial bus read error
-    #[cfg_attr(feature = "thiserror", error("Serial bus read error"))]
-    SerialR(RxError),
+    #[cfg_attr(feature = "thiserror", error("Serial bus read error: {0:?}"))]
+    Read(RxError),
     /// Serial bus write error
-    #[cfg_attr(feature = "thiserror", error("Serial bus write error"))]
-    SerialW(TxError),
+    #[cfg_attr(feature = "thiserror", error("Serial bus write error: {0:?}"))]
+    Write(TxError),
+    /// Unexpected EOF is uart disconnected?
+    #[cfg_attr(feature = "thiserror", error("Unexpected EOF is uart disconnected?"))]
+    Eof,
+}
+
+/// A response was malformed, missing or did not match the request
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "thiserror", derive(thiserror::Error))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(
+    feature = "postcard",
+    derive(postcard::experimental::max_size::MaxSize)
+)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum ProtocolError {
     /// SHDLC decode error
     #[cfg_attr(feature = "thiserror", error("SHDLC decode error"))]
-    SHDLC(crate::hldc::Error),
-    /// No valid frame read. Input function read more than twice the max bytes
-    /// in a frame without seeing frame markers
+    Shdlc(crate::shdlc::Error),
+    /// No valid frame read. More bytes than the resync limit were read
+    /// without finding a frame, the line might be noisy or the baud rate
+    /// wrong
     #[cfg_attr(
         feature = "thiserror",
-        error(
-            "No valid frame read. Input function read more than twice the max bytes
-in a frame without seeing frame markers"
-        )
+        error("No valid frame read within the resync limit, is the line noisy or the baud rate wrong?")
     )]
     InvalidFrame,
-    /// Result is empty
+    /// Result is empty, for example no measurement became available in time
     #[cfg_attr(feature = "thiserror", error("Result is empty"))]
     EmptyResult,
-    /// Checksum failed, after shdlc decode
+    /// Checksum failed, after shdlc decode. Carries the start of the frame
+    /// if the `frame-diagnostics` feature is enabled.
     #[cfg_attr(feature = "thiserror", error("Checksum failed, after shdlc decode"))]
-    ChecksumFailed,
-    /// Response is for another command then what we send
+    ChecksumFailed(RawFrame),
+    /// Response is for another command then what we send or is malformed.
+    /// Carries the start of the frame if the `frame-diagnostics` feature is
+    /// enabled.
     #[cfg_attr(
         feature = "thiserror",
         error("Response is for another Command then what we send")
     )]
-    InvalidResponse,
-    /// Device returned an error
-    #[cfg_attr(feature = "thiserror", error("Device returned error: {0}"))]
-    DeviceError(DeviceError),
+    InvalidResponse(RawFrame),
     /// The data send in response to read measurement was too short
     #[cfg_attr(
         feature = "thiserror",
@@ -113,72 +135,434 @@ in a frame without seeing frame markers"
         error("Serial number should be a utf8 string it is not")
     )]
     SerialInvalidUtf8,
-    /// Unexpected EOF is uart disconnected?
-    #[cfg_attr(feature = "thiserror", error("Unexpected EOF is uart disconnected?"))]
-    ReadingEOF,
     /// Frame is too large, either a bug or something went wrong with uart.
     #[cfg_attr(
         feature = "thiserror",
         error("Frame is too large, either a bug or something went wrong with uart.")
     )]
     FrameTooLarge,
+    /// No response to `command` arrived within the timeout of
+    /// `elapsed_ms`, see [`ShdlcDevice::set_timeout`](crate::shdlc::ShdlcDevice::set_timeout)
+    #[cfg_attr(
+        feature = "thiserror",
+        error("No response to command {command:#04x} within {elapsed_ms} ms")
+    )]
+    Timeout { command: u8, elapsed_ms: u32 },
+}
+
+/// Everything that can go wrong talking to the sensor, split by the layer
+/// it went wrong in. Use [`Error::kind`] to decide how to handle an error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "thiserror", derive(thiserror::Error))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<TxError, RxError>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    /// The uart failed or was closed
+    #[cfg_attr(feature = "thiserror", error("{0}"))]
+    Transport(TransportError<TxError, RxError>),
+    /// A response was malformed, missing or did not match the request
+    #[cfg_attr(feature = "thiserror", error("{0}"))]
+    Protocol(ProtocolError),
+    /// The device returned an error
+    #[cfg_attr(feature = "thiserror", error("Device returned error: {0}"))]
+    Device(DeviceError),
+    /// The driver believes the sensor is in `actual` mode while the
+    /// command needs `expected` mode. Nothing was sent. See
+    /// [`Sps30::current_mode`](crate::Sps30::current_mode).
+    #[cfg_attr(
+        feature = "thiserror",
+        error("Sensor is in {actual:?} mode, the command needs {expected:?} mode")
+    )]
+    WrongDriverState { expected: Mode, actual: Mode },
+    /// An argument is outside the range the sensor accepts. Nothing was
+    /// sent.
+    #[cfg_attr(feature = "thiserror", error("Argument out of range"))]
+    ArgumentOutOfRange,
+}
+
+impl<TxError, RxError> From<TransportError<TxError, RxError>> for Error<TxError, RxError>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    fn from(error: TransportError<TxError, RxError>) -> Self {
+        Error::Transport(error)
+    }
+}
+
+impl<TxError, RxError> From<ProtocolError> for Error<TxError, RxError>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    fn from(error: ProtocolError) -> Self {
+        Error::Protocol(error)
+    }
+}
+
+impl<TxError, RxError> From<DeviceError> for Error<TxError, RxError>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    fn from(error: DeviceError) -> Self {
+        Error::Device(error)
+    }
+}
+
+/// Maximum number of bytes of a rejected frame kept in a [`RawFrame`]
+pub const RAW_FRAME_LEN: usize = 16;
+
+/// Copy of the start of a rejected decoded frame, for diagnosing failures in
+/// the field. Only recorded if the `frame-diagnostics` feature is enabled,
+/// otherwise it is always empty and takes no space.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct RawFrame {
+    #[cfg(feature = "frame-diagnostics")]
+    bytes: heapless::Vec<u8, RAW_FRAME_LEN>,
+}
+
+impl RawFrame {
+    /// Copies up to [`RAW_FRAME_LEN`] bytes from the start of `frame`
+    #[cfg_attr(not(feature = "frame-diagnostics"), allow(unused_variables))]
+    pub(crate) fn new(frame: &[u8]) -> Self {
+        Self {
+            #[cfg(feature = "frame-diagnostics")]
+            bytes: heapless::Vec::from_slice(&frame[..frame.len().min(RAW_FRAME_LEN)])
+                .expect("length is limited to capacity"),
+        }
+    }
+
+    /// The recorded bytes, empty without the `frame-diagnostics` feature
+    #[must_use]
+    pub fn bytes(&self) -> &[u8] {
+        #[cfg(feature = "frame-diagnostics")]
+        return &self.bytes;
+        #[cfg(not(feature = "frame-diagnostics"))]
+        return &[];
+    }
 }
 
-impl<TxError, RxError> Clone for Error<TxError, RxError>
+#[cfg(feature = "defmt")]
+impl defmt::Format for RawFrame {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=[u8]:x}", self.bytes());
+    }
+}
+
+#[cfg(feature = "postcard")]
+impl postcard::experimental::max_size::MaxSize for RawFrame {
+    #[cfg(feature = "frame-diagnostics")]
+    const POSTCARD_MAX_SIZE: usize = 1 + RAW_FRAME_LEN;
+    #[cfg(not(feature = "frame-diagnostics"))]
+    const POSTCARD_MAX_SIZE: usize = 0;
+}
+
+/// Broad category of an [`Error`], see [`Error::kind`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ErrorKind {
+    /// The uart failed or was closed
+    Transport,
+    /// A response was malformed or did not match the request
+    Protocol,
+    /// The device reported an error
+    Device,
+    /// The driver refused the command in the mode the sensor is in
+    State,
+    /// The driver refused an argument the sensor would not accept
+    InvalidArgument,
+    /// No valid response arrived
+    Timeout,
+}
+
+impl<TxError, RxError> Error<TxError, RxError>
 where
-    TxError: defmt::Format + fmt::Debug + Clone,
-    RxError: defmt::Format + fmt::Debug + Clone,
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
 {
-    fn clone(&self) -> Self {
+    /// The category of this error. Use this to decide how to handle an
+    /// error instead of matching on every variant, new variants can be
+    /// added in any release.
+    pub fn kind(&self) -> ErrorKind {
         match self {
-            Error::SerialR(e) => Error::SerialR(e.clone()),
-            Error::SerialW(e) => Error::SerialW(e.clone()),
-            Error::SHDLC(e) => Error::SHDLC(e.clone()),
-            Error::InvalidFrame => Error::InvalidFrame,
-            Error::EmptyResult => Error::EmptyResult,
-            Error::ChecksumFailed => Error::ChecksumFailed,
-            Error::InvalidResponse => Error::InvalidResponse,
-            Error::DeviceError(s) => Error::DeviceError(s.clone()),
-            Error::MeasurementDataTooShort => Error::MeasurementDataTooShort,
-            Error::CleaningIntervalDataTooShort => Error::CleaningIntervalDataTooShort,
-            Error::SerialInvalidUtf8 => Error::SerialInvalidUtf8,
-            Error::ReadingEOF => Error::ReadingEOF,
-            Error::FrameTooLarge => Error::FrameTooLarge,
+            Error::Transport(_) => ErrorKind::Transport,
+            Error::Protocol(
+                ProtocolError::InvalidFrame
+                | ProtocolError::EmptyResult
+                | ProtocolError::Timeout { .. },
+            ) => ErrorKind::Timeout,
+            Error::Protocol(_) => ErrorKind::Protocol,
+            Error::Device(_) => ErrorKind::Device,
+            Error::WrongDriverState { .. } => ErrorKind::State,
+            Error::ArgumentOutOfRange => ErrorKind::InvalidArgument,
+        }
+    }
+
+    /// Whether retrying the request might succeed. True for corrupted or
+    /// missing responses, these are usually caused by line noise. False
+    /// if the uart failed or the device or driver rejected the request.
+    pub fn is_recoverable(&self) -> bool {
+        match self.kind() {
+            ErrorKind::Protocol | ErrorKind::Timeout => true,
+            ErrorKind::Transport
+            | ErrorKind::Device
+            | ErrorKind::State
+            | ErrorKind::InvalidArgument => false,
         }
     }
 }
 
-impl<TxError, RxError> Eq for Error<TxError, RxError>
+impl<TxError, RxError> embedded_io_async::Error for Error<TxError, RxError>
 where
-    TxError: defmt::Format + fmt::Debug + Eq,
-    RxError: defmt::Format + fmt::Debug + Eq,
+    TxError: MaybeFormat + fmt::Debug + embedded_io_async::Error,
+    RxError: MaybeFormat + fmt::Debug + embedded_io_async::Error,
 {
+    fn kind(&self) -> embedded_io_async::ErrorKind {
+        use embedded_io_async::ErrorKind as Kind;
+        match self {
+            Error::Transport(TransportError::Read(e)) => e.kind(),
+            Error::Transport(TransportError::Write(e)) => e.kind(),
+            Error::Transport(TransportError::Eof) => Kind::BrokenPipe,
+            Error::Protocol(
+                ProtocolError::InvalidFrame
+                | ProtocolError::EmptyResult
+                | ProtocolError::Timeout { .. },
+            ) => Kind::TimedOut,
+            Error::Protocol(_) => Kind::InvalidData,
+            Error::Device(DeviceError::UnknownCmd) => Kind::Unsupported,
+            Error::Device(DeviceError::NoAccess) => Kind::PermissionDenied,
+            Error::Device(DeviceError::WrongDataLen | DeviceError::InvalidParam) => {
+                Kind::InvalidInput
+            }
+            Error::Device(_) => Kind::Other,
+            Error::WrongDriverState { .. } | Error::ArgumentOutOfRange => Kind::InvalidInput,
+        }
+    }
+}
+
+/// Kind of a serial bus error, the bus agnostic part of an
+/// [`embedded_io_async::ErrorKind`]. See [`StaticError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(
+    feature = "postcard",
+    derive(postcard::experimental::max_size::MaxSize)
+)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BusErrorKind {
+    NotFound,
+    PermissionDenied,
+    BrokenPipe,
+    InvalidInput,
+    InvalidData,
+    TimedOut,
+    Interrupted,
+    Unsupported,
+    OutOfMemory,
+    /// Any other kind
+    Other,
 }
 
-impl<TxError, RxError> PartialEq for Error<TxError, RxError>
+impl From<embedded_io_async::ErrorKind> for BusErrorKind {
+    fn from(kind: embedded_io_async::ErrorKind) -> Self {
+        use embedded_io_async::ErrorKind as Kind;
+        match kind {
+            Kind::NotFound => Self::NotFound,
+            Kind::PermissionDenied => Self::PermissionDenied,
+            Kind::BrokenPipe => Self::BrokenPipe,
+            Kind::InvalidInput => Self::InvalidInput,
+            Kind::InvalidData => Self::InvalidData,
+            Kind::TimedOut => Self::TimedOut,
+            Kind::Interrupted => Self::Interrupted,
+            Kind::Unsupported => Self::Unsupported,
+            Kind::OutOfMemory => Self::OutOfMemory,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// An [`Error`] without generic parameters, the serial bus errors are
+/// reduced to their [`BusErrorKind`]. Use this to send errors off the
+/// device, for example to a backend collecting them from many sensors.
+/// Created by [`Error::into_static`].
+pub type StaticError = Error<BusErrorKind, BusErrorKind>;
+
+impl<TxError, RxError> Error<TxError, RxError>
 where
-    TxError: defmt::Format + fmt::Debug + PartialEq,
-    RxError: defmt::Format + fmt::Debug + PartialEq,
+    TxError: MaybeFormat + fmt::Debug + embedded_io_async::Error,
+    RxError: MaybeFormat + fmt::Debug + embedded_io_async::Error,
 {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Error::SerialR(e), Error::SerialR(e2)) => e == e2,
-            (Error::SerialW(e), Error::SerialW(e2)) => e == e2,
-            (Error::SHDLC(e), Error::SHDLC(e2)) => e == e2,
-            (Error::DeviceError(s1), Error::DeviceError(s2)) => s1 == s2,
-            (Error::InvalidFrame, Error::InvalidFrame)
-            | (Error::FrameTooLarge, Error::FrameTooLarge)
-            | (Error::ReadingEOF, Error::ReadingEOF)
-            | (Error::EmptyResult, Error::EmptyResult)
-            | (Error::ChecksumFailed, Error::ChecksumFailed)
-            | (Error::CleaningIntervalDataTooShort, Error::CleaningIntervalDataTooShort)
-            | (Error::SerialInvalidUtf8, Error::SerialInvalidUtf8)
-            | (Error::MeasurementDataTooShort, Error::MeasurementDataTooShort) => true,
-            (_, _) => false,
+    /// Owned copy of this error without the bus error types, see
+    /// [`StaticError`]
+    pub fn into_static(self) -> StaticError {
+        match self {
+            Error::Transport(TransportError::Read(e)) => {
+                Error::Transport(TransportError::Read(e.kind().into()))
+            }
+            Error::Transport(TransportError::Write(e)) => {
+                Error::Transport(TransportError::Write(e.kind().into()))
+            }
+            Error::Transport(TransportError::Eof) => Error::Transport(TransportError::Eof),
+            Error::Protocol(e) => Error::Protocol(e),
+            Error::Device(e) => Error::Device(e),
+            Error::WrongDriverState { expected, actual } => {
+                Error::WrongDriverState { expected, actual }
+            }
+            Error::ArgumentOutOfRange => Error::ArgumentOutOfRange,
+        }
+    }
+}
+
+/// Likely cause of a failed initialization, see [`InitError`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Diagnosis {
+    /// Not a single byte arrived. RX and TX may be swapped, the sensor
+    /// unpowered or not connected.
+    NothingReceived,
+    /// Bytes arrived but never a frame, usually a wrong baud rate
+    NoFrames,
+    /// Frames arrived but were corrupt. The baud rate, parity or stop bits
+    /// may be wrong or the line is very noisy.
+    CorruptFrames,
+    /// The uart reported framing or parity errors, check its settings
+    FramingErrors,
+    /// The uart failed or was closed
+    UartFailure,
+    /// The sensor answered correctly so the wiring is fine, see the error
+    /// for why initialization failed
+    Responding,
+}
+
+impl Diagnosis {
+    /// Classifies `error` using the traffic seen while initializing
+    pub fn new<TxError, RxError>(error: &Error<TxError, RxError>, stats: &Stats) -> Self
+    where
+        TxError: MaybeFormat + fmt::Debug + embedded_io_async::Error,
+        RxError: MaybeFormat + fmt::Debug + embedded_io_async::Error,
+    {
+        use embedded_io_async::ErrorKind as Kind;
+        match error {
+            Error::Transport(TransportError::Read(e)) if stats.frames_received == 0 => {
+                match e.kind() {
+                    Kind::InvalidData | Kind::Other => Diagnosis::FramingErrors,
+                    Kind::TimedOut if stats.bytes_in == 0 => Diagnosis::NothingReceived,
+                    _ => Diagnosis::UartFailure,
+                }
+            }
+            Error::Transport(TransportError::Read(_) | TransportError::Write(_)) => {
+                Diagnosis::UartFailure
+            }
+            _ if stats.frames_received > 0 => Diagnosis::Responding,
+            _ if stats.bytes_in == 0 => Diagnosis::NothingReceived,
+            _ if stats.checksum_failures == 0 && stats.resyncs == 0 => Diagnosis::NoFrames,
+            _ => Diagnosis::CorruptFrames,
         }
     }
 }
 
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hint = match self {
+            Diagnosis::NothingReceived => {
+                "nothing received, are RX and TX swapped or is the sensor unpowered?"
+            }
+            Diagnosis::NoFrames => "received bytes but no frames, is the baud rate 115200?",
+            Diagnosis::CorruptFrames => {
+                "received corrupt frames, check the baud rate, parity and stop bits"
+            }
+            Diagnosis::FramingErrors => "the uart reported framing errors, check its settings",
+            Diagnosis::UartFailure => "the uart failed",
+            Diagnosis::Responding => "the sensor responds, the wiring is fine",
+        };
+        f.write_str(hint)
+    }
+}
+
+/// Initialization failed, returned by
+/// [`Sps30Builder::build_diagnosed`](crate::Sps30Builder::build_diagnosed).
+/// Contains the likely cause next to the error.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InitError<TxError, RxError>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    pub diagnosis: Diagnosis,
+    pub error: Error<TxError, RxError>,
+}
+
+impl<TxError, RxError> fmt::Display for InitError<TxError, RxError>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.error, self.diagnosis)
+    }
+}
+
+#[cfg(feature = "thiserror")]
+impl<TxError, RxError> std::error::Error for InitError<TxError, RxError>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+}
+
+impl<TxError, RxError> From<InitError<TxError, RxError>> for Error<TxError, RxError>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    fn from(error: InitError<TxError, RxError>) -> Self {
+        error.error
+    }
+}
+
+/// Why [`Sps30::write_cleaning_interval_verified`](crate::Sps30::write_cleaning_interval_verified)
+/// failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "thiserror", derive(thiserror::Error))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum VerifyError<TxError, RxError>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    /// Writing, resetting or reading back failed
+    #[cfg_attr(feature = "thiserror", error("{0}"))]
+    Command(Error<TxError, RxError>),
+    /// The sensor reports a different interval than was written
+    #[cfg_attr(
+        feature = "thiserror",
+        error("Wrote cleaning interval {written} but read back {read}")
+    )]
+    Mismatch {
+        written: CleaningInterval,
+        read: CleaningInterval,
+    },
+}
+
+impl<TxError, RxError> From<Error<TxError, RxError>> for VerifyError<TxError, RxError>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    fn from(error: Error<TxError, RxError>) -> Self {
+        VerifyError::Command(error)
+    }
+}
+
 /// very ugly, at the time of writing still needed unfortunately
 /// const cmp tracking issue: https://github.com/rust-lang/rust/issues/92391
 /// workaround credits: https://stackoverflow.com/questions/53619695/
@@ -189,11 +573,147 @@ const fn max(a: usize, b: usize) -> usize {
 }
 
 #[cfg(feature = "postcard")]
-impl<TxError, RxError> postcard::experimental::max_size::MaxSize for Error<TxError, RxError>
+impl<TxError, RxError> postcard::experimental::max_size::MaxSize
+    for TransportError<TxError, RxError>
 where
-    TxError: postcard::experimental::max_size::MaxSize + core::fmt::Debug + defmt::Format,
-    RxError: postcard::experimental::max_size::MaxSize + core::fmt::Debug + defmt::Format,
+    TxError: postcard::experimental::max_size::MaxSize + core::fmt::Debug + MaybeFormat,
+    RxError: postcard::experimental::max_size::MaxSize + core::fmt::Debug + MaybeFormat,
 {
     const POSTCARD_MAX_SIZE: usize =
         1 + max(TxError::POSTCARD_MAX_SIZE, RxError::POSTCARD_MAX_SIZE);
 }
+
+#[cfg(feature = "postcard")]
+impl<TxError, RxError> postcard::experimental::max_size::MaxSize for Error<TxError, RxError>
+where
+    TxError: postcard::experimental::max_size::MaxSize + core::fmt::Debug + MaybeFormat,
+    RxError: postcard::experimental::max_size::MaxSize + core::fmt::Debug + MaybeFormat,
+{
+    const POSTCARD_MAX_SIZE: usize = 1 + max(
+        TransportError::<TxError, RxError>::POSTCARD_MAX_SIZE,
+        max(
+            ProtocolError::POSTCARD_MAX_SIZE,
+            max(DeviceError::POSTCARD_MAX_SIZE, 2 * Mode::POSTCARD_MAX_SIZE),
+        ),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        BusErrorKind, DeviceError, Diagnosis, Error, ProtocolError, StaticError, TransportError,
+    };
+    use crate::Stats;
+    use core::convert::Infallible;
+    use embedded_io_async::ErrorKind;
+
+    #[test]
+    fn embedded_io_kind() {
+        let kind = |e: Error<Infallible, Infallible>| embedded_io_async::Error::kind(&e);
+        assert_eq!(
+            kind(Error::Transport(TransportError::Eof)),
+            ErrorKind::BrokenPipe
+        );
+        assert_eq!(
+            kind(Error::Protocol(ProtocolError::InvalidFrame)),
+            ErrorKind::TimedOut
+        );
+        assert_eq!(
+            kind(Error::Device(DeviceError::UnknownCmd)),
+            ErrorKind::Unsupported
+        );
+    }
+
+    #[test]
+    fn into_static() {
+        let error: Error<ErrorKind, ErrorKind> =
+            Error::Transport(TransportError::Read(ErrorKind::TimedOut));
+        assert_eq!(
+            error.into_static(),
+            StaticError::Transport(TransportError::Read(BusErrorKind::TimedOut))
+        );
+        let error: Error<ErrorKind, ErrorKind> = Error::Device(DeviceError::NoAccess);
+        assert_eq!(
+            error.into_static(),
+            StaticError::Device(DeviceError::NoAccess)
+        );
+    }
+
+    #[test]
+    fn diagnosis() {
+        let diagnose =
+            |error: Error<ErrorKind, ErrorKind>, stats: Stats| Diagnosis::new(&error, &stats);
+        let stats = Stats {
+            bytes_out: 7,
+            ..Stats::default()
+        };
+        assert_eq!(
+            diagnose(
+                Error::Transport(TransportError::Read(ErrorKind::TimedOut)),
+                stats
+            ),
+            Diagnosis::NothingReceived
+        );
+        assert_eq!(
+            diagnose(
+                Error::Transport(TransportError::Read(ErrorKind::Other)),
+                stats
+            ),
+            Diagnosis::FramingErrors
+        );
+        assert_eq!(
+            diagnose(Error::Transport(TransportError::Eof), stats),
+            Diagnosis::NothingReceived
+        );
+
+        let garbage = Stats {
+            bytes_in: 300,
+            ..stats
+        };
+        assert_eq!(
+            diagnose(Error::Protocol(ProtocolError::InvalidFrame), garbage),
+            Diagnosis::NoFrames
+        );
+        let corrupt = Stats {
+            checksum_failures: 1,
+            ..garbage
+        };
+        assert_eq!(
+            diagnose(Error::Protocol(ProtocolError::InvalidFrame), corrupt),
+            Diagnosis::CorruptFrames
+        );
+        let responding = Stats {
+            frames_received: 1,
+            ..garbage
+        };
+        assert_eq!(
+            diagnose(Error::Device(DeviceError::NoAccess), responding),
+            Diagnosis::Responding
+        );
+    }
+
+    #[cfg(all(feature = "postcard", feature = "serde"))]
+    #[test]
+    fn telemetry_packet_fits() {
+        use crate::{Measurement, RawFrame};
+        use postcard::experimental::max_size::MaxSize;
+
+        #[derive(serde::Serialize, MaxSize)]
+        enum Packet {
+            Reading(Measurement),
+            Error(StaticError),
+        }
+
+        let mut buf = [0u8; Packet::POSTCARD_MAX_SIZE];
+        let packets = [
+            Packet::Reading(Measurement::default()),
+            Packet::Error(StaticError::Protocol(ProtocolError::InvalidResponse(
+                RawFrame::new(&[0xff; 32]),
+            ))),
+            Packet::Error(StaticError::Device(DeviceError::Unknown(0xff))),
+        ];
+        for packet in packets {
+            postcard::to_slice(&packet, &mut buf).unwrap();
+        }
+    }
+}
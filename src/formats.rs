@@ -0,0 +1,39 @@
+//! Helpers that render a [`Measurement`](crate::Measurement) for logging or
+//! publishing without needing an allocator.
+
+use core::fmt;
+
+#[cfg(feature = "serde")]
+pub mod compact;
+pub mod csv;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+
+/// [`fmt::Write`] adapter writing into a byte slice, fails once the slice
+/// is full.
+pub(crate) struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> SliceWriter<'a> {
+    pub(crate) fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    pub(crate) fn written(&self) -> usize {
+        self.pos
+    }
+}
+
+impl fmt::Write for SliceWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.pos + s.len();
+        let target = self.buf.get_mut(self.pos..end).ok_or(fmt::Error)?;
+        target.copy_from_slice(s.as_bytes());
+        self.pos = end;
+        Ok(())
+    }
+}
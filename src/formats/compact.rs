@@ -0,0 +1,86 @@
+//! Short field names for bandwidth constrained uplinks such as LoRa, where
+//! the full field names would make up most of the payload.
+//!
+//! Wrap a measurement in [`Compact`] to serialize it with names like `pm25`
+//! and `n10`:
+//!
+//! | field                   | name   |
+//! |-------------------------|--------|
+//! | `mass_pm1_0`            | `pm1`  |
+//! | `mass_pm2_5`            | `pm25` |
+//! | `mass_pm4_0`            | `pm4`  |
+//! | `mass_pm10`             | `pm10` |
+//! | `mass_pm0_5`            | `n05`  |
+//! | `number_pm1_0`          | `n1`   |
+//! | `number_pm2_5`          | `n25`  |
+//! | `number_pm4_0`          | `n4`   |
+//! | `number_pm10`           | `n10`  |
+//! | `typical_particle_size` | `tps`  |
+
+use serde::{Deserialize, Serialize};
+
+use crate::Measurement;
+
+/// Serializes (and deserializes) the wrapped measurement using the short
+/// field names listed in the [module docs](self).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Compact(#[serde(with = "CompactDef")] pub Measurement);
+
+impl From<Measurement> for Compact {
+    fn from(measurement: Measurement) -> Self {
+        Self(measurement)
+    }
+}
+
+impl From<Compact> for Measurement {
+    fn from(compact: Compact) -> Self {
+        compact.0
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Measurement")]
+struct CompactDef {
+    #[serde(rename = "pm1")]
+    mass_pm1_0: f32,
+    #[serde(rename = "pm25")]
+    mass_pm2_5: f32,
+    #[serde(rename = "pm4")]
+    mass_pm4_0: f32,
+    #[serde(rename = "pm10")]
+    mass_pm10: f32,
+    #[serde(rename = "n05")]
+    mass_pm0_5: f32,
+    #[serde(rename = "n1")]
+    number_pm1_0: f32,
+    #[serde(rename = "n25")]
+    number_pm2_5: f32,
+    #[serde(rename = "n4")]
+    number_pm4_0: f32,
+    #[serde(rename = "n10")]
+    number_pm10: f32,
+    #[serde(rename = "tps")]
+    typical_particle_size: f32,
+}
+
+#[cfg(all(test, feature = "json"))]
+mod test {
+    use super::Compact;
+    use crate::Measurement;
+
+    #[test]
+    fn short_names_roundtrip() {
+        let measurement = Measurement {
+            mass_pm2_5: 4.5,
+            ..Measurement::default()
+        };
+        let mut buf = [0u8; 128];
+        let n = serde_json_core::to_slice(&Compact(measurement), &mut buf).unwrap();
+        let json = core::str::from_utf8(&buf[..n]).unwrap();
+        assert!(json.starts_with(r#"{"pm1":0.0,"pm25":4.5,"#), "{json}");
+
+        let (parsed, _): (Compact, _) = serde_json_core::from_slice(&buf[..n]).unwrap();
+        assert_eq!(Measurement::from(parsed), measurement);
+    }
+}
@@ -0,0 +1,219 @@
+//! Comma (or otherwise) separated rows for SD-card and serial logging.
+//!
+//! ```
+//! use sps30_async::formats::csv::Csv;
+//!
+//! let csv = Csv::new().with_separator(';').with_timestamp();
+//! let mut line = [0u8; 256];
+//! let n = csv.header_to_slice(&mut line).unwrap();
+//! assert!(line[..n].starts_with(b"timestamp;mass_pm1_0;"));
+//! ```
+
+use core::fmt::{self, Write};
+
+use super::SliceWriter;
+use crate::Measurement;
+
+const TIMESTAMP_COLUMN: &str = "timestamp";
+const COLUMNS: [&str; 10] = [
+    "mass_pm1_0",
+    "mass_pm2_5",
+    "mass_pm4_0",
+    "mass_pm10",
+    "mass_pm0_5",
+    "number_pm1_0",
+    "number_pm2_5",
+    "number_pm4_0",
+    "number_pm10",
+    "typical_particle_size",
+];
+
+/// Formats measurements as CSV lines, each terminated by a newline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Csv {
+    separator: char,
+    timestamp: bool,
+}
+
+impl Default for Csv {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Csv {
+    /// Comma separated without a timestamp column
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            separator: ',',
+            timestamp: false,
+        }
+    }
+
+    /// Use `separator` between columns instead of a comma
+    #[must_use]
+    pub const fn with_separator(mut self, separator: char) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    /// Prepend a timestamp column. The unit of the timestamp is up to the
+    /// caller.
+    #[must_use]
+    pub const fn with_timestamp(mut self) -> Self {
+        self.timestamp = true;
+        self
+    }
+
+    /// Writes the header row naming every column.
+    ///
+    /// # Errors
+    /// Returns an error if the sink does.
+    pub fn write_header(&self, sink: &mut impl Write) -> fmt::Result {
+        if self.timestamp {
+            sink.write_str(TIMESTAMP_COLUMN)?;
+            sink.write_char(self.separator)?;
+        }
+        for (i, column) in COLUMNS.iter().enumerate() {
+            if i > 0 {
+                sink.write_char(self.separator)?;
+            }
+            sink.write_str(column)?;
+        }
+        sink.write_char('\n')
+    }
+
+    /// Writes a row for `measurement`. If a timestamp column is configured
+    /// but `timestamp` is `None` the column is left empty.
+    ///
+    /// # Errors
+    /// Returns an error if the sink does.
+    pub fn write_row(
+        &self,
+        sink: &mut impl Write,
+        measurement: &Measurement,
+        timestamp: Option<u64>,
+    ) -> fmt::Result {
+        if self.timestamp {
+            if let Some(timestamp) = timestamp {
+                write!(sink, "{timestamp}")?;
+            }
+            sink.write_char(self.separator)?;
+        }
+        let Measurement {
+            mass_pm1_0,
+            mass_pm2_5,
+            mass_pm4_0,
+            mass_pm10,
+            mass_pm0_5,
+            number_pm1_0,
+            number_pm2_5,
+            number_pm4_0,
+            number_pm10,
+            typical_particle_size,
+        } = measurement;
+        let values = [
+            mass_pm1_0,
+            mass_pm2_5,
+            mass_pm4_0,
+            mass_pm10,
+            mass_pm0_5,
+            number_pm1_0,
+            number_pm2_5,
+            number_pm4_0,
+            number_pm10,
+            typical_particle_size,
+        ];
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                sink.write_char(self.separator)?;
+            }
+            write!(sink, "{value}")?;
+        }
+        sink.write_char('\n')
+    }
+
+    /// Writes the header row into `buf`. Returns the number of bytes written.
+    ///
+    /// # Errors
+    /// Returns an error if `buf` is too small.
+    pub fn header_to_slice(&self, buf: &mut [u8]) -> Result<usize, fmt::Error> {
+        let mut writer = SliceWriter::new(buf);
+        self.write_header(&mut writer)?;
+        Ok(writer.written())
+    }
+
+    /// Writes a row for `measurement` into `buf`. Returns the number of
+    /// bytes written.
+    ///
+    /// # Errors
+    /// Returns an error if `buf` is too small.
+    pub fn row_to_slice(
+        &self,
+        buf: &mut [u8],
+        measurement: &Measurement,
+        timestamp: Option<u64>,
+    ) -> Result<usize, fmt::Error> {
+        let mut writer = SliceWriter::new(buf);
+        self.write_row(&mut writer, measurement, timestamp)?;
+        Ok(writer.written())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Csv;
+    use crate::Measurement;
+
+    fn measurement() -> Measurement {
+        Measurement {
+            mass_pm1_0: 1.0,
+            mass_pm2_5: 2.5,
+            mass_pm4_0: 4.0,
+            mass_pm10: 10.0,
+            mass_pm0_5: 0.5,
+            number_pm1_0: 1.0,
+            number_pm2_5: 2.5,
+            number_pm4_0: 4.0,
+            number_pm10: 10.0,
+            typical_particle_size: 0.25,
+        }
+    }
+
+    #[test]
+    fn header_with_timestamp() {
+        let mut buf = [0u8; 256];
+        let n = Csv::new()
+            .with_separator(';')
+            .with_timestamp()
+            .header_to_slice(&mut buf)
+            .unwrap();
+        assert_eq!(
+            core::str::from_utf8(&buf[..n]).unwrap(),
+            "timestamp;mass_pm1_0;mass_pm2_5;mass_pm4_0;mass_pm10;mass_pm0_5;\
+            number_pm1_0;number_pm2_5;number_pm4_0;number_pm10;typical_particle_size\n"
+        );
+    }
+
+    #[test]
+    fn row() {
+        let mut buf = [0u8; 256];
+        let n = Csv::new()
+            .with_timestamp()
+            .row_to_slice(&mut buf, &measurement(), Some(42))
+            .unwrap();
+        assert_eq!(
+            core::str::from_utf8(&buf[..n]).unwrap(),
+            "42,1,2.5,4,10,0.5,1,2.5,4,10,0.25\n"
+        );
+    }
+
+    #[test]
+    fn row_does_not_fit() {
+        let mut buf = [0u8; 8];
+        assert!(Csv::new()
+            .row_to_slice(&mut buf, &measurement(), None)
+            .is_err());
+    }
+}
@@ -0,0 +1,12 @@
+use crate::Measurement;
+
+pub use serde_json_core::ser::Error;
+
+/// Serializes a measurement as a JSON object into `buf`. Returns the number
+/// of bytes written.
+///
+/// # Errors
+/// Returns [`Error::BufferFull`] if `buf` is too small to hold the object.
+pub fn to_slice(measurement: &Measurement, buf: &mut [u8]) -> Result<usize, Error> {
+    serde_json_core::to_slice(measurement, buf)
+}
@@ -0,0 +1,85 @@
+//! The flat JSON object most MQTT air quality integrations (Home Assistant
+//! templates, Tasmota and ESPHome style dashboards) expect:
+//!
+//! ```json
+//! {"pm1":1.0,"pm25":2.5,"pm4":4.0,"pm10":10.0,"nc05":0.5,"nc1":1.0,
+//!  "nc25":2.5,"nc4":4.0,"nc10":10.0,"tps":0.25}
+//! ```
+//!
+//! Mass concentrations are in μg/m³, number concentrations in #/cm³ and
+//! the typical particle size in μm.
+
+use serde::Serialize;
+
+use crate::Measurement;
+
+pub use serde_json_core::ser::Error;
+
+#[derive(Serialize)]
+struct Payload {
+    pm1: f32,
+    pm25: f32,
+    pm4: f32,
+    pm10: f32,
+    nc05: f32,
+    nc1: f32,
+    nc25: f32,
+    nc4: f32,
+    nc10: f32,
+    tps: f32,
+}
+
+impl From<&Measurement> for Payload {
+    fn from(m: &Measurement) -> Self {
+        Self {
+            pm1: m.mass_pm1_0,
+            pm25: m.mass_pm2_5,
+            pm4: m.mass_pm4_0,
+            pm10: m.mass_pm10,
+            nc05: m.mass_pm0_5,
+            nc1: m.number_pm1_0,
+            nc25: m.number_pm2_5,
+            nc4: m.number_pm4_0,
+            nc10: m.number_pm10,
+            tps: m.typical_particle_size,
+        }
+    }
+}
+
+/// Serializes a measurement as a flat MQTT payload into `buf`. Returns the
+/// number of bytes written, publish `&buf[..n]`.
+///
+/// # Errors
+/// Returns [`Error::BufferFull`] if `buf` is too small to hold the object.
+pub fn to_slice(measurement: &Measurement, buf: &mut [u8]) -> Result<usize, Error> {
+    serde_json_core::to_slice(&Payload::from(measurement), buf)
+}
+
+#[cfg(test)]
+mod test {
+    use super::to_slice;
+    use crate::Measurement;
+
+    #[test]
+    fn flat_keys() {
+        let measurement = Measurement {
+            mass_pm1_0: 1.0,
+            mass_pm2_5: 2.5,
+            mass_pm4_0: 4.0,
+            mass_pm10: 10.0,
+            mass_pm0_5: 0.5,
+            number_pm1_0: 1.0,
+            number_pm2_5: 2.5,
+            number_pm4_0: 4.0,
+            number_pm10: 10.0,
+            typical_particle_size: 0.25,
+        };
+        let mut buf = [0u8; 256];
+        let n = to_slice(&measurement, &mut buf).unwrap();
+        assert_eq!(
+            core::str::from_utf8(&buf[..n]).unwrap(),
+            r#"{"pm1":1.0,"pm25":2.5,"pm4":4.0,"pm10":10.0,"nc05":0.5,"nc1":1.0,"nc25":2.5,"nc4":4.0,"nc10":10.0,"tps":0.25}"#
+        );
+        assert!(to_slice(&measurement, &mut buf[..16]).is_err());
+    }
+}
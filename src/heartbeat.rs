@@ -0,0 +1,156 @@
+//! Check whether communication with the sensor still works without
+//! sending anything, for health endpoints and watchdog feeders. Set a
+//! [`Heartbeat`] as the logger of the driver, it notes when the last valid
+//! response arrived.
+//!
+//! ```ignore
+//! let mut sensor = sensor.with_logger(Heartbeat::new(|| Instant::now().as_millis()));
+//! // in the health check
+//! match sensor.last_success_age() {
+//!     Some(age) if age < 10_000 => watchdog.feed(),
+//!     _ => warn!("sensor silent"),
+//! }
+//! ```
+
+use core::fmt;
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+use crate::power::Clock;
+use crate::shdlc::{FrameObserver, ProtocolLogger};
+use crate::watchdog::WatchdogEvent;
+use crate::{Error, MaybeFormat, Mode, Sps30};
+
+/// Notes the time of every successful command, see the
+/// [module](self) documentation. Passes everything on to another logger,
+/// set with [`with_logger`](Self::with_logger).
+#[derive(Debug, Clone)]
+pub struct Heartbeat<C, L = ()> {
+    clock: C,
+    inner: L,
+    last_success_ms: Option<u64>,
+}
+
+impl<C: Clock> Heartbeat<C> {
+    /// Reads the time from `clock`, in milliseconds
+    pub fn new(clock: C) -> Self {
+        Self {
+            clock,
+            inner: (),
+            last_success_ms: None,
+        }
+    }
+}
+
+impl<C: Clock, L: ProtocolLogger> Heartbeat<C, L> {
+    /// Pass everything logged on to `logger`
+    pub fn with_logger<L2: ProtocolLogger>(self, logger: L2) -> Heartbeat<C, L2> {
+        Heartbeat {
+            clock: self.clock,
+            inner: logger,
+            last_success_ms: self.last_success_ms,
+        }
+    }
+
+    /// The logger everything is passed on to
+    pub fn logger(&mut self) -> &mut L {
+        &mut self.inner
+    }
+
+    /// Time of the last valid response in ms, `None` if there was none
+    #[must_use]
+    pub fn last_success_ms(&self) -> Option<u64> {
+        self.last_success_ms
+    }
+
+    /// Milliseconds since the last valid response, `None` if there was
+    /// none
+    #[must_use]
+    pub fn last_success_age(&self) -> Option<u64> {
+        let now = self.clock.now_ms();
+        self.last_success_ms.map(|last| now.saturating_sub(last))
+    }
+}
+
+impl<C: Clock, L: ProtocolLogger> ProtocolLogger for Heartbeat<C, L> {
+    fn on_command(&mut self, address: u8, command: u8, data: &[u8]) {
+        self.inner.on_command(address, command, data);
+    }
+
+    fn on_response(&mut self, command: u8, data: &[u8]) {
+        self.last_success_ms = Some(self.clock.now_ms());
+        self.inner.on_response(command, data);
+    }
+
+    fn on_resync(&mut self, command: u8) {
+        self.inner.on_resync(command);
+    }
+
+    fn on_error<TxError, RxError>(&mut self, command: u8, error: &Error<TxError, RxError>)
+    where
+        TxError: MaybeFormat + fmt::Debug,
+        RxError: MaybeFormat + fmt::Debug,
+    {
+        self.inner.on_error(command, error);
+    }
+
+    fn on_watchdog(&mut self, event: WatchdogEvent) {
+        self.inner.on_watchdog(event);
+    }
+
+    fn on_mode_change(&mut self, mode: Option<Mode>) {
+        self.inner.on_mode_change(mode);
+    }
+}
+
+impl<Tx, Rx, D, O, C, L> Sps30<Tx, Rx, D, O, Heartbeat<C, L>>
+where
+    Tx: Write,
+    Tx::Error: MaybeFormat,
+    Rx: Read,
+    Rx::Error: MaybeFormat,
+    D: DelayNs,
+    O: FrameObserver,
+    C: Clock,
+    L: ProtocolLogger,
+{
+    /// Milliseconds since the sensor last sent a valid response, `None`
+    /// if it never did. See [`Heartbeat`].
+    #[must_use]
+    pub fn last_success_age(&self) -> Option<u64> {
+        self.logger_ref().last_success_age()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::cell::Cell;
+
+    use futures::executor::block_on;
+
+    use super::Heartbeat;
+    use crate::mock::{MockSps30, NoDelay};
+    use crate::Sps30;
+
+    #[test]
+    fn tracks_last_success() {
+        let mock = MockSps30::new();
+        let now = Cell::new(1_000);
+        let mut sensor = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay)
+            .with_logger(Heartbeat::new(|| now.get()));
+        assert_eq!(sensor.last_success_age(), None);
+
+        block_on(sensor.reset()).unwrap();
+        now.set(1_500);
+        assert_eq!(sensor.last_success_age(), Some(500));
+
+        mock.fail_next(0x43);
+        block_on(sensor.read_device_status_register(false)).unwrap_err();
+        now.set(2_000);
+        assert_eq!(sensor.last_success_age(), Some(1_000));
+        let sensor = &sensor;
+        assert_eq!(sensor.last_success_age(), Some(1_000));
+        assert_eq!(sensor.logger_ref().last_success_ms(), Some(1_000));
+    }
+}
@@ -0,0 +1,135 @@
+//! Keep the last few measurements on the device, for example to answer
+//! "what happened in the last 5 minutes" without a server.
+
+use heapless::HistoryBuffer;
+
+use crate::aggregate::{Aggregator, Summary};
+use crate::Measurement;
+
+/// A measurement with the time it was taken
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Entry<T> {
+    /// The time as given to [`History::push_at`], `()` when not timestamped
+    pub timestamp: T,
+    pub measurement: Measurement,
+}
+
+/// The last `N` measurements, once full the oldest are overwritten.
+///
+/// `T` is the timestamp, any type works, for example an
+/// `embassy_time::Instant` or seconds since boot. Without timestamps
+/// (the default) use [`push`](Self::push).
+pub struct History<const N: usize, T = ()> {
+    buffer: HistoryBuffer<Entry<T>, N>,
+}
+
+impl<const N: usize, T> Default for History<N, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> History<N> {
+    /// Adds a measurement, dropping the oldest if full
+    pub fn push(&mut self, measurement: Measurement) {
+        self.push_at((), measurement);
+    }
+}
+
+impl<const N: usize, T> History<N, T> {
+    /// An empty history
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            buffer: HistoryBuffer::new(),
+        }
+    }
+
+    /// Adds a measurement taken at `timestamp`, dropping the oldest if full
+    pub fn push_at(&mut self, timestamp: T, measurement: Measurement) {
+        self.buffer.write(Entry {
+            timestamp,
+            measurement,
+        });
+    }
+
+    /// The most recently added entry
+    #[must_use]
+    pub fn newest(&self) -> Option<&Entry<T>> {
+        self.buffer.recent()
+    }
+
+    /// The oldest entry still stored
+    #[must_use]
+    pub fn oldest(&self) -> Option<&Entry<T>> {
+        self.buffer.oldest_ordered().next()
+    }
+
+    /// All entries from oldest to newest
+    pub fn iter(&self) -> impl Iterator<Item = &Entry<T>> {
+        self.buffer.oldest_ordered()
+    }
+
+    /// Number of stored entries, at most `N`
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.buffer.len() == 0
+    }
+
+    /// Removes all entries
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+
+    /// Average of all stored measurements, see [`Measurement::mean`]
+    #[must_use]
+    pub fn mean(&self) -> Option<Measurement> {
+        Measurement::mean(self.iter().map(|entry| entry.measurement))
+    }
+
+    /// Statistics over all stored measurements, a rolling window
+    #[must_use]
+    pub fn summary(&self) -> Option<Summary> {
+        let mut aggregator = Aggregator::new();
+        aggregator.extend(self.iter().map(|entry| entry.measurement));
+        aggregator.summary()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::History;
+    use crate::Measurement;
+
+    fn measurement(pm10: f32) -> Measurement {
+        Measurement {
+            mass_pm10: pm10,
+            ..Measurement::default()
+        }
+    }
+
+    #[test]
+    fn keeps_newest() {
+        let mut history = History::<3, u32>::new();
+        assert!(history.oldest().is_none());
+        for second in 0..5 {
+            #[allow(clippy::cast_precision_loss)]
+            history.push_at(second, measurement(second as f32));
+        }
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.oldest().unwrap().timestamp, 2);
+        assert_eq!(history.newest().unwrap().timestamp, 4);
+        let recent: heapless::Vec<u32, 3> = history
+            .iter()
+            .filter(|entry| entry.timestamp >= 3)
+            .map(|entry| entry.timestamp)
+            .collect();
+        assert_eq!(recent, [3, 4]);
+        assert_eq!(history.mean().unwrap().mass_pm10, 3.0);
+    }
+}
@@ -10,48 +10,187 @@
 //! [1]: https://www.sensirion.com/fileadmin/user_upload/customers/sensirion/Dokumente/0_Datasheets/Particulate_Matter/Sensirion_PM_Sensors_SPS30_Datasheet.pdf
 
 #![deny(unsafe_code)]
-#![cfg_attr(not(any(target_os = "linux", feature = "thiserror")), no_std)]
+#![cfg_attr(
+    not(any(
+        target_os = "linux",
+        feature = "thiserror",
+        feature = "std",
+        feature = "serialport"
+    )),
+    no_std
+)]
 
-use core::{fmt, mem};
+use core::mem;
+use core::time::Duration;
 
 use embedded_hal_async::delay::DelayNs;
-use embedded_io_async::{Read, Write};
+use embedded_io_async::{Read, ReadReady, Write};
 use heapless::{String, Vec};
 
+#[macro_use]
+mod log;
+pub use log::MaybeFormat;
+
+pub mod aggregate;
+mod bits;
+mod builder;
+pub mod calibration;
+mod cleaning;
+mod config;
+#[cfg(test)]
+mod conformance;
+pub mod context;
 mod error;
-mod hldc;
-pub use hldc::Error as HldcError;
-mod read_frame;
-pub use error::{DeviceError, Error};
-use read_frame::read_frame;
-
-/// Max characters to read for a frame detection
-const MAX_ENCODED_FRAME_SIZE: usize = 2 * (10 * mem::size_of::<f32>() + 5 + 2);
-const MAX_DECODED_FRAME_SIZE: usize = 10 * mem::size_of::<f32>() + 5 + 2;
-const ADDR: u8 = 0;
-
-#[repr(u8)]
-enum DeviceInfo {
-    // ProductName = 1,
-    // ArticleCode = 2,
-    SerialNumber = 3,
+pub mod formats;
+pub mod shdlc;
+pub use shdlc::Error as HldcError;
+#[cfg(feature = "serialport")]
+pub mod blocking;
+#[cfg(feature = "embassy")]
+pub mod embassy;
+#[cfg(feature = "embassy-time")]
+pub mod embassy_time;
+#[cfg(feature = "std")]
+pub mod linux;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod monitor;
+pub mod mux;
+#[cfg(any(feature = "std", feature = "serialport"))]
+mod std_io;
+#[cfg(any(feature = "std", feature = "serialport"))]
+pub use std_io::IoError;
+pub mod heartbeat;
+pub mod history;
+#[cfg(feature = "nb")]
+pub mod polling;
+#[cfg(feature = "pool")]
+pub mod pool;
+pub mod power;
+pub mod protocol;
+pub mod quality;
+#[cfg(feature = "embassy")]
+pub mod queue;
+pub mod recording;
+pub mod sampling;
+pub mod schedule;
+pub mod self_test;
+#[cfg(feature = "sen5x")]
+pub mod sen5x;
+#[cfg(feature = "embassy")]
+pub mod shared;
+mod status;
+pub mod thresholds;
+pub mod trend;
+pub mod watchdog;
+pub use bits::{FloatBits, MeasurementBits};
+pub use builder::Sps30Builder;
+use calibration::Calibration;
+pub use cleaning::CleaningInterval;
+pub use config::{Config, Snapshot};
+use context::{Context, WithContext};
+pub use error::{
+    BusErrorKind, DeviceError, Diagnosis, Error, ErrorKind, InitError, ProtocolError, RawFrame,
+    StaticError, TransportError, VerifyError, RAW_FRAME_LEN,
+};
+use monitor::FanRecovery;
+#[cfg(feature = "pool")]
+pub use pool::Sps30Pool;
+use protocol::{Command, InfoField};
+pub use self_test::SelfTestReport;
+pub use shdlc::Response as RawResponse;
+pub use shdlc::Stats;
+use shdlc::{FrameObserver, ProtocolLogger, ShdlcDevice};
+pub use status::DeviceStatus;
+
+/// How long a fan cleaning takes
+pub(crate) const FAN_CLEANING_MS: u32 = 10_000;
+
+/// A major.minor version number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Version {
+    pub major: u8,
+    pub minor: u8,
+}
+
+impl core::fmt::Display for Version {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Firmware, hardware and protocol versions, see [`Sps30::versions`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Versions {
+    pub firmware: Version,
+    pub hardware_revision: u8,
+    pub shdlc: Version,
+}
+
+/// Everything needed to identify a unit, see [`Sps30::device_info`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct DeviceInfo {
+    pub serial_number: String<32>,
+    /// "00080000" for the SPS30
+    pub product_type: String<8>,
+    pub versions: Versions,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for DeviceInfo {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "DeviceInfo {{ serial_number: {=str}, product_type: {=str}, versions: {} }}",
+            self.serial_number.as_str(),
+            self.product_type.as_str(),
+            self.versions,
+        );
+    }
+}
+
+/// Result of [`Sps30::ping`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Health {
+    /// The sensor answered correctly
+    Healthy,
+    /// The sensor answered but reported an error
+    DeviceError(DeviceError),
+    /// No valid answer arrived, the sensor may be unpowered, disconnected
+    /// or the line too noisy
+    NoResponse,
 }
 
-#[repr(u8)]
-enum Command {
-    StartMeasurement = 0,
-    StopMeasurement = 1,
-    ReadMeasuredData = 3,
-    /// Read or Write Auto Cleaning Interval
-    ReadWriteAutoCleaningInterval = 0x80,
-    StartFanCleaning = 0x56,
-    DeviceInformation = 0xD0,
-    Reset = 0xD3,
+/// Operating mode of the sensor, see [`Sps30::current_mode`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(
+    feature = "postcard",
+    derive(postcard::experimental::max_size::MaxSize)
+)]
+pub enum Mode {
+    /// Powered up but not measuring, the state after a reset
+    Idle,
+    /// Measuring, measurements can be read
+    Measurement,
+    /// Low power mode, only wakes up on [`Sps30::wake_up`]
+    Sleep,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
-#[derive(defmt::Format)]
+#[cfg_attr(
+    feature = "postcard",
+    derive(postcard::experimental::max_size::MaxSize)
+)]
 pub struct Measurement {
     /// Mass Concentration PM1.0 \[μg/m³\]
     pub mass_pm1_0: f32,
@@ -75,6 +214,204 @@ pub struct Measurement {
     pub typical_particle_size: f32,
 }
 
+/// Prints every field with its unit, e.g. `PM2.5=12.3µg/m³`
+#[cfg(feature = "defmt")]
+impl defmt::Format for Measurement {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "PM1.0={}µg/m³ PM2.5={}µg/m³ PM4.0={}µg/m³ PM10={}µg/m³ \
+            NC0.5={}#/cm³ NC1.0={}#/cm³ NC2.5={}#/cm³ NC4.0={}#/cm³ NC10={}#/cm³ \
+            TPS={}µm",
+            OneDecimal(self.mass_pm1_0),
+            OneDecimal(self.mass_pm2_5),
+            OneDecimal(self.mass_pm4_0),
+            OneDecimal(self.mass_pm10),
+            OneDecimal(self.mass_pm0_5),
+            OneDecimal(self.number_pm1_0),
+            OneDecimal(self.number_pm2_5),
+            OneDecimal(self.number_pm4_0),
+            OneDecimal(self.number_pm10),
+            TwoDecimals(self.typical_particle_size),
+        );
+    }
+}
+
+impl Measurement {
+    /// Every field zero, the same as [`Default::default`] but usable in
+    /// a `const` or `static`, for example to initialize a buffer.
+    #[must_use]
+    pub const fn zeroed() -> Self {
+        Self {
+            mass_pm1_0: 0.0,
+            mass_pm2_5: 0.0,
+            mass_pm4_0: 0.0,
+            mass_pm10: 0.0,
+            mass_pm0_5: 0.0,
+            number_pm1_0: 0.0,
+            number_pm2_5: 0.0,
+            number_pm4_0: 0.0,
+            number_pm10: 0.0,
+            typical_particle_size: 0.0,
+        }
+    }
+
+    /// Applies `op` to every field
+    fn map(self, op: impl Fn(f32) -> f32) -> Self {
+        Self {
+            mass_pm1_0: op(self.mass_pm1_0),
+            mass_pm2_5: op(self.mass_pm2_5),
+            mass_pm4_0: op(self.mass_pm4_0),
+            mass_pm10: op(self.mass_pm10),
+            mass_pm0_5: op(self.mass_pm0_5),
+            number_pm1_0: op(self.number_pm1_0),
+            number_pm2_5: op(self.number_pm2_5),
+            number_pm4_0: op(self.number_pm4_0),
+            number_pm10: op(self.number_pm10),
+            typical_particle_size: op(self.typical_particle_size),
+        }
+    }
+
+    /// Applies `op` to every field of `self` and `other`
+    fn zip_with(self, other: Self, op: impl Fn(f32, f32) -> f32) -> Self {
+        Self {
+            mass_pm1_0: op(self.mass_pm1_0, other.mass_pm1_0),
+            mass_pm2_5: op(self.mass_pm2_5, other.mass_pm2_5),
+            mass_pm4_0: op(self.mass_pm4_0, other.mass_pm4_0),
+            mass_pm10: op(self.mass_pm10, other.mass_pm10),
+            mass_pm0_5: op(self.mass_pm0_5, other.mass_pm0_5),
+            number_pm1_0: op(self.number_pm1_0, other.number_pm1_0),
+            number_pm2_5: op(self.number_pm2_5, other.number_pm2_5),
+            number_pm4_0: op(self.number_pm4_0, other.number_pm4_0),
+            number_pm10: op(self.number_pm10, other.number_pm10),
+            typical_particle_size: op(self.typical_particle_size, other.typical_particle_size),
+        }
+    }
+
+    /// The field wise average of `measurements`, `None` if there are none
+    pub fn mean(measurements: impl IntoIterator<Item = Measurement>) -> Option<Self> {
+        let (sum, count) = measurements
+            .into_iter()
+            .fold((Self::zeroed(), 0u32), |(sum, count), m| {
+                (sum + m, count + 1)
+            });
+        #[allow(clippy::cast_precision_loss)] // exact up to 2^24 measurements
+        (count > 0).then(|| sum / count as f32)
+    }
+}
+
+/// Field wise sum
+impl core::ops::Add for Measurement {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        self.zip_with(rhs, |a, b| a + b)
+    }
+}
+
+/// Field wise difference
+impl core::ops::Sub for Measurement {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        self.zip_with(rhs, |a, b| a - b)
+    }
+}
+
+/// Multiplies every field by `rhs`
+impl core::ops::Mul<f32> for Measurement {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self {
+        self.zip_with(self, |a, _| a * rhs)
+    }
+}
+
+/// Divides every field by `rhs`
+impl core::ops::Div<f32> for Measurement {
+    type Output = Self;
+
+    fn div(self, rhs: f32) -> Self {
+        self.zip_with(self, |a, _| a / rhs)
+    }
+}
+
+/// defmt has no precision hint for floats, these print a rounded fixed
+/// point representation instead.
+#[cfg(feature = "defmt")]
+struct OneDecimal(f32);
+#[cfg(feature = "defmt")]
+struct TwoDecimals(f32);
+
+/// Splits `val` into sign, integer part and `scale`-ths rounded to nearest
+#[cfg(feature = "defmt")]
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+#[allow(clippy::cast_precision_loss)]
+fn fixed_point(val: f32, scale: u32) -> (&'static str, u32, u32) {
+    let scaled = (val.abs() * scale as f32 + 0.5) as u32;
+    let sign = if val < 0.0 && scaled != 0 { "-" } else { "" };
+    (sign, scaled / scale, scaled % scale)
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for OneDecimal {
+    fn format(&self, f: defmt::Formatter) {
+        let (sign, int, frac) = fixed_point(self.0, 10);
+        defmt::write!(f, "{=str}{=u32}.{=u32}", sign, int, frac);
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for TwoDecimals {
+    fn format(&self, f: defmt::Formatter) {
+        let (sign, int, frac) = fixed_point(self.0, 100);
+        defmt::write!(f, "{=str}{=u32}.{=u32:02}", sign, int, frac);
+    }
+}
+
+/// Encoding the sensor uses for measurements, set when starting a
+/// measurement. Either way they are returned as a [`Measurement`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum MeasurementFormat {
+    /// Big-endian IEEE754 floats, full resolution
+    #[default]
+    Float,
+    /// Big-endian unsigned 16 bit integers. Halves the size of each
+    /// response at the cost of resolution. Requires firmware 2.0 or newer.
+    Integer,
+}
+
+impl MeasurementFormat {
+    fn code(self) -> u8 {
+        match self {
+            MeasurementFormat::Float => 0x03,
+            MeasurementFormat::Integer => 0x05,
+        }
+    }
+
+    /// The format of a measurement payload of `len` bytes, if that is the
+    /// length of a complete measurement in either format
+    fn from_payload_len(len: usize) -> Option<Self> {
+        match len {
+            MAX_MEASUREMENT_LEN => Some(MeasurementFormat::Float),
+            l if l == MAX_MEASUREMENT_LEN / 2 => Some(MeasurementFormat::Integer),
+            _ => None,
+        }
+    }
+}
+
+/// Length in bytes of the largest measurement payload, see
+/// [`Sps30::read_measurement_raw`]
+pub const MAX_MEASUREMENT_LEN: usize = 10 * mem::size_of::<f32>();
+
+/// Strings from the device are null terminated
+fn until_nul(data: &[u8]) -> &[u8] {
+    data.split(|b| *b == 0).next().unwrap_or(data)
+}
+
 struct NotEnoughData;
 impl Measurement {
     fn from_floats(mut floats: impl Iterator<Item = f32>) -> Option<Self> {
@@ -92,7 +429,26 @@ impl Measurement {
         })
     }
 
-    pub(crate) fn from_data(data: &[u8]) -> Result<Self, NotEnoughData> {
+    /// Parses `data` in the format its length implies, falling back to
+    /// `format`. The sensor might be measuring in another format than the
+    /// driver expects, for example if the driver was constructed without
+    /// initializing the sensor.
+    pub(crate) fn from_data(data: &[u8], format: MeasurementFormat) -> Result<Self, NotEnoughData> {
+        let detected = MeasurementFormat::from_payload_len(data.len()).unwrap_or(format);
+        if detected != format {
+            debug!(
+                "measurement is in format {:?}, expected {:?}",
+                detected, format
+            );
+        }
+        match detected {
+            MeasurementFormat::Float => Self::from_float_data(data),
+            MeasurementFormat::Integer => Self::from_integer_data(data),
+        }
+        .ok_or(NotEnoughData)
+    }
+
+    fn from_float_data(data: &[u8]) -> Option<Self> {
         // array_chunks would be nice here (not yet stable)
         let floats = data
             .chunks_exact(mem::size_of::<f32>())
@@ -100,107 +456,165 @@ impl Measurement {
             .map(Result::unwrap) // chunks exact guarantees correct size
             .map(f32::from_be_bytes);
 
-        Self::from_floats(floats).ok_or(NotEnoughData)
+        Self::from_floats(floats)
     }
-}
 
-/// Checksum implemented as per section 4.1 from spec
-#[allow(clippy::cast_lossless)]
-#[allow(clippy::cast_possible_truncation)]
-fn checksum(data: &[u8]) -> u8 {
-    let mut cksum: u8 = 0;
-    for &byte in data {
-        let val: u16 = cksum as u16 + byte as u16;
-        let lsb = val % 256;
-        cksum = lsb as u8;
+    fn from_integer_data(data: &[u8]) -> Option<Self> {
+        let integers = data
+            .chunks_exact(mem::size_of::<u16>())
+            .map(<[u8; mem::size_of::<u16>()]>::try_from)
+            .map(Result::unwrap) // chunks exact guarantees correct size
+            .map(u16::from_be_bytes)
+            .map(f32::from);
+
+        let mut measurement = Self::from_floats(integers)?;
+        // sent in nm instead of µm
+        measurement.typical_particle_size /= 1000.0;
+        Some(measurement)
     }
 
-    255 - cksum
-}
+    /// Mass concentrations PM1.0, PM2.5, PM4.0 and PM10 in mg/m³
+    #[must_use]
+    pub fn mass_mg_per_m3(&self) -> [f32; 4] {
+        [
+            self.mass_pm1_0,
+            self.mass_pm2_5,
+            self.mass_pm4_0,
+            self.mass_pm10,
+        ]
+        .map(|ug| ug / 1_000.0)
+    }
 
-macro_rules! cmd {
-    ($cmd:expr$(, [$($data:expr),*])?) => {
-        {
-            let mut input = [ADDR, $cmd as u8, 0u8, $($($data),*,)? 0u8];
-            let data_length = input.len() - 4;
-            input[2] = data_length as u8;
+    /// Number concentrations PM0.5, PM1.0, PM2.5, PM4.0 and PM10 in #/m³
+    #[must_use]
+    pub fn number_per_m3(&self) -> [f32; 5] {
+        self.number_per_cm3().map(|n| n * 1_000_000.0)
+    }
 
-            let checksum = checksum(&input[..input.len() -1]);
-            input[input.len() - 1] = checksum;
-            input
-        }
-    };
-}
+    /// Number concentrations PM0.5, PM1.0, PM2.5, PM4.0 and PM10 in #/L
+    #[must_use]
+    pub fn number_per_liter(&self) -> [f32; 5] {
+        self.number_per_cm3().map(|n| n * 1_000.0)
+    }
 
-/// Perform checks on decoded MISO Frame
-///
-/// Start
-///  ADR     CMD       State    Length    RX Data          CHK     Stop      
-///  0x7E   1 Byte   1 Byte    1 Byte    0...255 bytes    1 Byte   0x7E
-fn parse_miso_frame<TxError, RxError>(
-    frame: &[u8],
-    cmd_type: Command,
-) -> Result<&[u8], Error<TxError, RxError>>
-where
-    RxError: defmt::Format + fmt::Debug,
-    TxError: defmt::Format + fmt::Debug,
-{
-    const ADDR: u8 = 0x00;
-    let [ADDR, cmd, state, length, data @ .., check_sum] = frame else {
-        return Err(Error::InvalidResponse);
-    };
-    defmt::trace!("frame: {:?}", frame);
-    defmt::trace!("cmd: {}, state: {}, length: {}", cmd, state, length);
-    defmt::trace!("data len: {}", data.len());
+    /// Differential number concentrations in #/cm³ for the size bins
+    /// 0.5–1.0µm, 1.0–2.5µm, 2.5–4.0µm and 4.0–10µm. The sensor reports
+    /// cumulative concentrations, these are their differences. Measurement
+    /// noise can make a difference negative, those are clamped to zero.
+    #[must_use]
+    pub fn number_bins_per_cm3(&self) -> [f32; 4] {
+        let [pm0_5, pm1_0, pm2_5, pm4_0, pm10] = self.number_per_cm3();
+        [pm1_0 - pm0_5, pm2_5 - pm1_0, pm4_0 - pm2_5, pm10 - pm4_0].map(|n| n.max(0.0))
+    }
 
-    let [without_checksum @ .., _] = frame else {
-        unreachable!()
-    };
-    if *check_sum != checksum(without_checksum) {
-        return Err(Error::ChecksumFailed);
+    /// Ratio of PM1.0 to PM2.5 mass concentration. Close to one for
+    /// combustion aerosol (smoke, exhaust), lower for dust. `None` if
+    /// there is no PM2.5.
+    #[must_use]
+    pub fn pm1_0_to_pm2_5_ratio(&self) -> Option<f32> {
+        Self::ratio(self.mass_pm1_0, self.mass_pm2_5)
     }
 
-    if *cmd != cmd_type as u8 {
-        return Err(Error::InvalidResponse);
+    /// Ratio of PM2.5 to PM10 mass concentration. High for combustion
+    /// aerosol, low for coarse dust such as road or construction dust.
+    /// `None` if there is no PM10.
+    #[must_use]
+    pub fn pm2_5_to_pm10_ratio(&self) -> Option<f32> {
+        Self::ratio(self.mass_pm2_5, self.mass_pm10)
     }
-    if *state != 0 {
-        let dev_err = DeviceError::from(*state);
-        return Err(Error::DeviceError(dev_err));
+
+    /// Typical particle size in nm
+    #[must_use]
+    pub fn typical_particle_size_nm(&self) -> f32 {
+        self.typical_particle_size * 1_000.0
     }
 
-    if *length as usize != data.len() {
-        return Err(Error::InvalidResponse);
+    fn number_per_cm3(&self) -> [f32; 5] {
+        [
+            self.mass_pm0_5, // is a number concentration despite the name
+            self.number_pm1_0,
+            self.number_pm2_5,
+            self.number_pm4_0,
+            self.number_pm10,
+        ]
     }
 
-    Ok(data)
-}
+    /// `part / whole`, `None` unless `whole` is positive
+    fn ratio(part: f32, whole: f32) -> Option<f32> {
+        (whole > 0.0).then(|| part / whole)
+    }
 
-fn check_miso_frame<TxError, RxError>(
-    frame: &[u8],
-    cmd_type: Command,
-) -> Result<(), Error<TxError, RxError>>
-where
-    RxError: defmt::Format + fmt::Debug,
-    TxError: defmt::Format + fmt::Debug,
-{
-    parse_miso_frame(frame, cmd_type)?;
-    Ok(())
+    /// Serializes this measurement as JSON into `buf`, for example to
+    /// publish it over MQTT. Returns the number of bytes written.
+    ///
+    /// # Errors
+    /// Returns an error if `buf` is too small to hold the JSON object.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self, buf: &mut [u8]) -> Result<usize, formats::json::Error> {
+        formats::json::to_slice(self, buf)
+    }
 }
 
 /// Sps30 driver
-pub struct Sps30<const UART_BUF: usize, Tx, Rx, D> {
-    /// The concrete Serial device implementation.
-    uart_tx: Tx,
-    uart_rx: Rx,
-    delay: D,
+///
+/// `O` is a [`FrameObserver`] which is passed every
+/// frame, see [`Self::with_observer`]. `L` is a [`ProtocolLogger`] told
+/// about every command, response and error, see [`Self::with_logger`].
+///
+/// # Sharing the uart
+/// The driver works with mutable references to the uart halves and delay.
+/// Constructing it with [`Self::from_tx_rx_uninit`] does not communicate
+/// with the device, so when the uart is shared (for example behind an
+/// embassy mutex) it is cheap to build a driver for every transaction:
+///
+/// ```no_run
+/// use embedded_hal_async::delay::DelayNs;
+/// use embedded_io_async::{Read, Write};
+/// use sps30_async::{Error, MaybeFormat, Measurement, Sps30};
+///
+/// async fn read_shared<Tx, Rx, D>(
+///     tx: &mut Tx,
+///     rx: &mut Rx,
+///     delay: &mut D,
+/// ) -> Result<Measurement, Error<Tx::Error, Rx::Error>>
+/// where
+///     Tx: Write,
+///     Tx::Error: MaybeFormat,
+///     Rx: Read,
+///     Rx::Error: MaybeFormat,
+///     D: DelayNs,
+/// {
+///     let mut sensor = Sps30::from_tx_rx_uninit(tx, rx, delay);
+///     sensor.read_measurement().await
+/// }
+/// ```
+///
+/// Settings such as the [address](Self::with_address) are not stored on the
+/// device, apply them again after constructing.
+///
+/// A driver that owns the delay (or a `&mut` to it) lends it out through
+/// [`Self::delay`], so other drivers in the same task can use it in between
+/// transactions.
+///
+/// The driver remembers which [`Mode`] it put the sensor in. Commands that
+/// can not work in that mode fail with [`Error::WrongDriverState`]
+/// without using the bus.
+pub struct Sps30<Tx, Rx, D, O = (), L = ()> {
+    device: ShdlcDevice<Tx, Rx, D, O, L>,
+    format: MeasurementFormat,
+    calibration: Calibration,
+    /// `None` until a command reveals the mode
+    mode: Option<Mode>,
+    /// Read by the first call to `identify`
+    identity: Option<DeviceInfo>,
 }
 
-impl<const UART_BUF: usize, Tx, Rx, D> Sps30<UART_BUF, Tx, Rx, D>
+impl<Tx, Rx, D> Sps30<Tx, Rx, D>
 where
     Tx: Write,
-    Tx::Error: defmt::Format,
+    Tx::Error: MaybeFormat,
     Rx: Read,
-    Rx::Error: defmt::Format,
+    Rx::Error: MaybeFormat,
     D: DelayNs,
 {
     /// Constructs the [`Sps30`] interface from 2 'halves' of UART and
@@ -212,22 +626,22 @@ where
     /// - Stop bits: 1 bit
     /// - Parity: None
     ///
-    /// # Warning
-    /// If the uart is bufferd the `UART_BUF` const generic must be
-    /// larger then the buffer provided to the uart
+    /// Use [`Self::builder`] to change how the device is initialized. To
+    /// find out why initialization failed, for example because RX and TX
+    /// are swapped, use [`Sps30Builder::build_diagnosed`].
     pub async fn from_tx_rx(
         uart_tx: Tx,
         uart_rx: Rx,
         delay: D,
-    ) -> Result<Sps30<UART_BUF, Tx, Rx, D>, Error<Tx::Error, Rx::Error>> {
-        let mut instance = Self {
-            uart_tx,
-            uart_rx,
-            delay,
-        };
-        instance.reset().await?;
-        instance.start_measurement().await?;
-        Ok(instance)
+    ) -> Result<Sps30<Tx, Rx, D>, Error<Tx::Error, Rx::Error>> {
+        Self::builder(uart_tx, uart_rx, delay).build().await
+    }
+
+    /// Configure the driver and how the device is initialized before
+    /// constructing it. Without changes this does the same as
+    /// [`Self::from_tx_rx`].
+    pub fn builder(uart_tx: Tx, uart_rx: Rx, delay: D) -> Sps30Builder<Tx, Rx, D> {
+        Sps30Builder::new(uart_tx, uart_rx, delay)
     }
 
     /// Constructs the [`Sps30`] interface from 2 'halves' of UART.
@@ -239,42 +653,191 @@ where
     /// Generally you want [`Self::from_tx_rx`] however this can be useful
     /// in case you want to handle errors by retrying while having the
     /// driver own the tx and rx.
-    pub fn from_tx_rx_uninit(uart_tx: Tx, uart_rx: Rx, delay: D) -> Sps30<UART_BUF, Tx, Rx, D> {
+    pub fn from_tx_rx_uninit(uart_tx: Tx, uart_rx: Rx, delay: D) -> Sps30<Tx, Rx, D> {
         Self {
-            uart_tx,
-            uart_rx,
-            delay,
+            device: ShdlcDevice::new(uart_tx, uart_rx, delay),
+            format: MeasurementFormat::Float,
+            calibration: Calibration::IDENTITY,
+            mode: None,
+            identity: None,
         }
     }
+}
 
-    /// Send data through serial interface
-    #[inline(always)]
-    async fn encode_and_send(&mut self, data: &[u8]) -> Result<(), Error<Tx::Error, Rx::Error>> {
-        const LARGEST_ENCODED_REQUEST_FRAME: usize = 2 * (2 + 4 + 2); // header, data, footer
-        let output = hldc::encode::<LARGEST_ENCODED_REQUEST_FRAME>(data)
-            .await
-            .unwrap();
-        self.uart_tx
-            .write_all(&output)
-            .await
-            .map_err(Error::SerialW)?;
-        self.uart_tx.flush().await.map_err(Error::SerialW)
+impl<Tx, Rx, D, O, L> Sps30<Tx, Rx, D, O, L>
+where
+    Tx: Write,
+    Tx::Error: MaybeFormat,
+    Rx: Read,
+    Rx::Error: MaybeFormat,
+    D: DelayNs,
+    O: FrameObserver,
+    L: ProtocolLogger,
+{
+    /// Passes every frame sent to and received from the sensor to
+    /// `observer`. To also observe initialization combine with
+    /// [`Sps30::from_tx_rx_uninit`] then [`Self::reset`] and
+    /// [`Self::start_measurement`].
+    pub fn with_observer<O2: FrameObserver>(self, observer: O2) -> Sps30<Tx, Rx, D, O2, L> {
+        Sps30 {
+            device: self.device.with_observer(observer),
+            format: self.format,
+            calibration: self.calibration,
+            mode: self.mode,
+            identity: self.identity,
+        }
     }
 
-    /// Reads the latest available frame from serial, decodes it and verifies the checksum
-    #[inline(always)]
-    async fn receive_and_decode(
+    /// The observer set with [`Self::with_observer`]
+    pub fn observer(&mut self) -> &mut O {
+        self.device.observer()
+    }
+
+    /// Reports every command, response, resync and error to `logger`, for
+    /// example [`DefmtLogger`](shdlc::DefmtLogger). To also log
+    /// initialization use [`Sps30Builder::logger`]. The logger is told
+    /// the [current mode](Self::current_mode) if it is known.
+    pub fn with_logger<L2: ProtocolLogger>(self, mut logger: L2) -> Sps30<Tx, Rx, D, O, L2> {
+        if self.mode.is_some() {
+            logger.on_mode_change(self.mode);
+        }
+        Sps30 {
+            device: self.device.with_logger(logger),
+            format: self.format,
+            calibration: self.calibration,
+            mode: self.mode,
+            identity: self.identity,
+        }
+    }
+
+    /// The logger set with [`Self::with_logger`]
+    pub fn logger(&mut self) -> &mut L {
+        self.device.logger()
+    }
+
+    /// Like [`Self::logger`] but only needs a shared reference
+    pub fn logger_ref(&self) -> &L {
+        self.device.logger_ref()
+    }
+
+    /// The delay provider passed in on construction, for use by other
+    /// drivers sharing it
+    pub fn delay(&mut self) -> &mut D {
+        self.device.delay()
+    }
+
+    /// Returns the uart halves and delay, dropping the driver
+    pub fn release(self) -> (Tx, Rx, D) {
+        self.device.release()
+    }
+
+    /// Address the sensor at `address` instead of the default (0). Needed
+    /// when several SHDLC devices share a bus, for example through an
+    /// RS-485 adapter. Responses from other addresses are rejected.
+    ///
+    /// Combine with [`Self::from_tx_rx_uninit`] then [`Self::reset`] and
+    /// [`Self::start_measurement`].
+    #[must_use]
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.device.set_address(address);
+        self
+    }
+
+    /// Limit the number of bytes read while waiting for a response, see
+    /// [`ShdlcDevice::set_resync_limit`]. Requests then fail with
+    /// [`ProtocolError::InvalidFrame`] instead of hanging on a noisy line.
+    #[must_use]
+    pub fn with_resync_limit(mut self, bytes: usize) -> Self {
+        self.device.set_resync_limit(bytes);
+        self
+    }
+
+    /// Give up on a response after `ms` milliseconds, see
+    /// [`ShdlcDevice::set_timeout`]. Requests then fail with
+    /// [`ProtocolError::Timeout`], reporting the command and time waited.
+    #[must_use]
+    pub fn with_timeout(mut self, ms: u32) -> Self {
+        self.device.set_timeout(Some(ms));
+        self
+    }
+
+    /// Correct every measurement returned from now on using `calibration`
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+    }
+
+    /// The calibration applied to measurements, see
+    /// [`Self::set_calibration`]
+    pub fn calibration(&self) -> &Calibration {
+        &self.calibration
+    }
+
+    /// The SHDLC address of the sensor
+    pub fn address(&self) -> u8 {
+        self.device.address()
+    }
+
+    /// Communication statistics since construction or the last
+    /// [`reset_stats`](Self::reset_stats). Useful to monitor link health
+    /// on long running deployments.
+    pub fn stats(&self) -> Stats {
+        self.device.stats()
+    }
+
+    /// Set all communication statistics to zero
+    pub fn reset_stats(&mut self) {
+        self.device.reset_stats();
+    }
+
+    /// The mode the driver believes the sensor is in. `None` until it is
+    /// known, for example right after [`Self::from_tx_rx_uninit`]. Becomes
+    /// known after a [reset](Self::reset) or a mode change. Forgotten when
+    /// the sensor rejects a command because of its mode.
+    pub fn current_mode(&self) -> Option<Mode> {
+        self.mode
+    }
+
+    /// Remembers the mode the sensor is in, telling the logger if it
+    /// changed
+    fn set_mode(&mut self, mode: Option<Mode>) {
+        if self.mode != mode {
+            self.mode = mode;
+            self.device.logger().on_mode_change(mode);
+        }
+    }
+
+    /// Updates the believed mode after a command: to `next` if it
+    /// succeeded, unknown if the sensor rejected it because of its mode.
+    fn track_mode<T>(
         &mut self,
-    ) -> Result<Vec<u8, MAX_DECODED_FRAME_SIZE>, Error<Tx::Error, Rx::Error>> {
-        let frame: Vec<u8, MAX_ENCODED_FRAME_SIZE> =
-            match read_frame::<UART_BUF, MAX_ENCODED_FRAME_SIZE, Rx>(&mut self.uart_rx).await {
-                Ok(frame) => frame,
-                Err(read_frame::Error::Eof) => return Err(Error::ReadingEOF),
-                Err(read_frame::Error::Read(e)) => return Err(Error::SerialR(e)),
-                Err(read_frame::Error::BufferOutOfSpace) => return Err(Error::FrameTooLarge),
-            };
+        result: Result<T, Error<Tx::Error, Rx::Error>>,
+        next: Option<Mode>,
+    ) -> Result<T, Error<Tx::Error, Rx::Error>> {
+        match &result {
+            Ok(_) if next.is_some() => self.set_mode(next),
+            Err(Error::Device(DeviceError::InvalidStateForCommand)) => self.set_mode(None),
+            Ok(_) | Err(_) => (),
+        }
+        result
+    }
+
+    /// Fails if the sensor is known to be in a mode other then `expected`
+    fn require(&self, expected: Mode) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        match self.mode {
+            Some(actual) if actual != expected => Err(Error::WrongDriverState { expected, actual }),
+            _ => Ok(()),
+        }
+    }
 
-        hldc::decode(&frame).await.map_err(Error::SHDLC)
+    /// Fails if the sensor is known to be asleep
+    fn require_awake(&self) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        match self.mode {
+            Some(Mode::Sleep) => Err(Error::WrongDriverState {
+                expected: Mode::Idle,
+                actual: Mode::Sleep,
+            }),
+            _ => Ok(()),
+        }
     }
 
     /// Starts the measurement. After power up, the module is in Idle-Mode.
@@ -286,15 +849,19 @@ where
     /// error or the connection could have issues leading to invalid responses.
     /// These are caught and reported as Errors.
     #[inline(always)]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub async fn start_measurement(&mut self) -> Result<(), Error<Tx::Error, Rx::Error>> {
-        const CMD: Command = Command::StartMeasurement;
         const SUBCMD: u8 = 0x01;
-        const FORMAT_FLOAT: u8 = 0x03;
-        let cmd = cmd!(CMD, [SUBCMD, FORMAT_FLOAT]);
-        self.encode_and_send(&cmd).await?;
-
-        let response = self.receive_and_decode().await?;
-        check_miso_frame(&response, CMD)
+        self.require(Mode::Idle)?;
+        let result = self
+            .device
+            .execute(
+                Command::StartMeasurement as u8,
+                &[SUBCMD, self.format.code()],
+            )
+            .await;
+        self.track_mode(result, Some(Mode::Measurement))?;
+        Ok(())
     }
 
     /// Stop measuring. Use this command to return to the initial state (Idle-Mode).
@@ -304,62 +871,243 @@ where
     /// error or the connection could have issues leading to invalid responses.
     /// These are caught and reported as Errors.
     #[inline(always)]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub async fn stop_measurement(&mut self) -> Result<(), Error<Tx::Error, Rx::Error>> {
-        const CMD: Command = Command::StopMeasurement;
-        let cmd = cmd!(CMD);
-        self.encode_and_send(&cmd).await?;
-
-        match self.receive_and_decode().await {
-            Ok(response) => check_miso_frame(&response, CMD),
-            Err(e) => Err(e),
-        }
+        self.require_awake()?;
+        let result = self
+            .device
+            .execute(Command::StopMeasurement as u8, &[])
+            .await;
+        self.track_mode(result, Some(Mode::Idle))?;
+        Ok(())
     }
 
     /// Read result. If no new measurement values are available, the module
     /// waits until one is. The measurement interval is 1 second.
     ///
-    /// This function like all in this driver is cancel safe
+    /// This function like all in this driver is cancel safe. If a request
+    /// is dropped before it finishes its response is skipped by the next
+    /// one.
     ///
     /// # Errors
+    /// Returns [`ProtocolError::EmptyResult`] if no new measurement is
+    /// available yet, for example right after starting.
     /// Reading the response can fail, the device can run into an internal
     /// error or the connection could have issues leading to invalid responses.
     /// These are caught and reported as Errors.
     #[inline(always)]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub async fn read_measurement(&mut self) -> Result<Measurement, Error<Tx::Error, Rx::Error>> {
-        const CMD: Command = Command::ReadMeasuredData;
-        let cmd = cmd!(CMD);
-        self.encode_and_send(&cmd).await?;
+        self.require(Mode::Measurement)?;
+        let result = self
+            .device
+            .execute_ref(Command::ReadMeasuredData as u8, &[])
+            .await;
+        let data = match result {
+            Ok(data) => data,
+            Err(e) => return self.track_mode(Err(e), None),
+        };
+        if data.is_empty() {
+            return Err(Error::Protocol(ProtocolError::EmptyResult));
+        }
+        Measurement::from_data(data, self.format)
+            .map(|raw| self.calibration.apply(raw))
+            .map_err(|_| Error::Protocol(ProtocolError::MeasurementDataTooShort))
+    }
+
+    /// Reads a measurement without decoding it, for gateways forwarding
+    /// the data as is. Returns the big-endian payload, 40 bytes of floats
+    /// or 20 bytes of integers depending on the
+    /// [`MeasurementFormat`]. No [calibration](Self::set_calibration) is
+    /// applied.
+    ///
+    /// # Errors
+    /// Returns [`ProtocolError::EmptyResult`] if no new measurement is
+    /// available yet and [`ProtocolError::MeasurementDataTooShort`] if the
+    /// payload is not a complete measurement in either format.
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn read_measurement_raw(
+        &mut self,
+    ) -> Result<Vec<u8, MAX_MEASUREMENT_LEN>, Error<Tx::Error, Rx::Error>> {
+        self.require(Mode::Measurement)?;
+        let result = self
+            .device
+            .execute_ref(Command::ReadMeasuredData as u8, &[])
+            .await;
+        let data = match result {
+            Ok(data) => data,
+            Err(e) => return self.track_mode(Err(e), None),
+        };
+        if data.is_empty() {
+            return Err(Error::Protocol(ProtocolError::EmptyResult));
+        }
+        MeasurementFormat::from_payload_len(data.len())
+            .and_then(|_| Vec::from_slice(data).ok())
+            .ok_or(Error::Protocol(ProtocolError::MeasurementDataTooShort))
+    }
 
-        let data = self.receive_and_decode().await?;
-        check_miso_frame(&data, CMD)?;
-        Ok(Measurement::from_data(&data).map_err(|_| Error::MeasurementDataTooShort)?)
+    /// Reads a measurement without decoding the floats, for relays on
+    /// microcontrollers without FPU. Requires the
+    /// [`MeasurementFormat::Float`] format. No
+    /// [calibration](Self::set_calibration) is applied.
+    ///
+    /// # Errors
+    /// Returns [`ProtocolError::EmptyResult`] if no new measurement is
+    /// available yet and [`ProtocolError::MeasurementDataTooShort`] if the
+    /// payload is not a complete measurement in float format.
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn read_measurement_bits(
+        &mut self,
+    ) -> Result<MeasurementBits, Error<Tx::Error, Rx::Error>> {
+        self.require(Mode::Measurement)?;
+        let result = self
+            .device
+            .execute_ref(Command::ReadMeasuredData as u8, &[])
+            .await;
+        let data = match result {
+            Ok(data) => data,
+            Err(e) => return self.track_mode(Err(e), None),
+        };
+        if data.is_empty() {
+            return Err(Error::Protocol(ProtocolError::EmptyResult));
+        }
+        MeasurementBits::from_data(data)
+            .ok_or(Error::Protocol(ProtocolError::MeasurementDataTooShort))
+    }
+
+    /// Reads a measurement and applies `correction` to it, on top of the
+    /// [calibration](Self::set_calibration). Chain several corrections
+    /// using a tuple, see [`calibration`].
+    ///
+    /// # Errors
+    /// See [`Self::read_measurement`]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn read_measurement_corrected(
+        &mut self,
+        mut correction: impl calibration::Correction,
+    ) -> Result<Measurement, Error<Tx::Error, Rx::Error>> {
+        let mut measurement = self.read_measurement().await?;
+        correction.apply(&mut measurement);
+        Ok(measurement)
+    }
+
+    /// Reads a measurement, passes `context` to the `correction` chain
+    /// then applies it. Returns the corrected measurement together with
+    /// its context. Use `()` to skip correcting.
+    ///
+    /// # Errors
+    /// See [`Self::read_measurement`]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn read_measurement_with_context(
+        &mut self,
+        context: Context,
+        mut correction: impl calibration::Correction,
+    ) -> Result<WithContext, Error<Tx::Error, Rx::Error>> {
+        correction.set_context(&context);
+        let measurement = self.read_measurement_corrected(correction).await?;
+        Ok(WithContext {
+            measurement,
+            context,
+        })
+    }
+
+    /// Reads a measurement and corrects it for humidity, see
+    /// [`HumidityCorrection`](calibration::HumidityCorrection).
+    /// `relative_humidity` (0 to 100%) should be measured next to the
+    /// sensor, for example with an SHT sensor. Returns both the
+    /// measurement as returned by [`Self::read_measurement`] and the
+    /// corrected one.
+    ///
+    /// # Errors
+    /// See [`Self::read_measurement`]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn read_measurement_with_humidity(
+        &mut self,
+        correction: &calibration::HumidityCorrection,
+        relative_humidity: f32,
+    ) -> Result<calibration::HumidityCorrected, Error<Tx::Error, Rx::Error>> {
+        let measurement = self.read_measurement().await?;
+        Ok(correction.apply(measurement, relative_humidity))
+    }
+
+    /// Starts the measurement then waits for the first measurement to
+    /// become available and returns it. Directly after starting the sensor
+    /// answers reads without data until its first sample is ready, about a
+    /// second later. Polls every 100ms for at most 3 seconds.
+    ///
+    /// # Errors
+    /// Returns [`ProtocolError::EmptyResult`] if no measurement arrived in
+    /// time.
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn start_measurement_and_wait_ready(
+        &mut self,
+    ) -> Result<Measurement, Error<Tx::Error, Rx::Error>> {
+        self.start_measurement().await?;
+        self.read_measurement_when_ready().await
     }
 
-    /// Read cleaning interval, of the periodic fan-cleaning. Interval in
-    /// seconds as big-endian unsigned 32-bit integer value.
+    /// Reads a measurement, polling every 100ms for at most 3 seconds
+    /// while the sensor has none ready yet. Returns
+    /// [`ProtocolError::EmptyResult`] if none arrived in time.
+    async fn read_measurement_when_ready(
+        &mut self,
+    ) -> Result<Measurement, Error<Tx::Error, Rx::Error>> {
+        const POLL_INTERVAL_MS: u32 = 100;
+        const DEADLINE_MS: u32 = 3_000;
+
+        for _ in 0..DEADLINE_MS / POLL_INTERVAL_MS {
+            self.device.delay().delay_ms(POLL_INTERVAL_MS).await;
+            let result = self
+                .device
+                .execute_ref(Command::ReadMeasuredData as u8, &[])
+                .await;
+            let data = match result {
+                Ok(data) => data,
+                Err(e) => return self.track_mode(Err(e), None),
+            };
+            if !data.is_empty() {
+                return Measurement::from_data(data, self.format)
+                    .map(|raw| self.calibration.apply(raw))
+                    .map_err(|_| Error::Protocol(ProtocolError::MeasurementDataTooShort));
+            }
+        }
+        Err(Error::Protocol(ProtocolError::EmptyResult))
+    }
+
+    /// Read cleaning interval, of the periodic fan-cleaning. A sensor that
+    /// does not clean automatically returns
+    /// [`CleaningInterval::DISABLED`].
     ///
     /// # Errors
     /// Reading the response can fail, the device can run into an internal
     /// error or the connection could have issues leading to invalid responses.
     /// These are caught and reported as Errors.
     #[inline(always)]
-    pub async fn read_cleaning_interval(&mut self) -> Result<u32, Error<Tx::Error, Rx::Error>> {
-        const CMD: Command = Command::ReadWriteAutoCleaningInterval;
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn read_cleaning_interval(
+        &mut self,
+    ) -> Result<CleaningInterval, Error<Tx::Error, Rx::Error>> {
         const SUB_CMD: u8 = 0x00;
-        let cmd = cmd!(CMD, [SUB_CMD]);
-        self.encode_and_send(&cmd).await?;
-
-        let response = self.receive_and_decode().await?;
-        let data = parse_miso_frame(&response, CMD)?;
-        let data: [u8; 4] = data
+        self.require_awake()?;
+        let data: [u8; 4] = self
+            .device
+            .execute_ref(Command::ReadWriteAutoCleaningInterval as u8, &[SUB_CMD])
+            .await?
             .try_into()
-            .map_err(|_| Error::CleaningIntervalDataTooShort)?;
-        let ret = u32::from_be_bytes(data);
-        Ok(ret)
+            .map_err(|_| Error::Protocol(ProtocolError::CleaningIntervalDataTooShort))?;
+        Ok(CleaningInterval::from_raw(u32::from_be_bytes(data)))
     }
 
-    /// Write cleaning interval of the periodic fan-cleaning. Interval in
-    /// seconds as big-endian unsigned 32-bit integer value. Default is 168
+    /// Write cleaning interval of the periodic fan-cleaning. Default is 168
     /// hours ±3% due to clock drift. Once set, the interval is stored
     /// permanently in the non-volatile memory. If the sensor is switched off,
     /// the time counter is reset to 0. Make sure to trigger a cleaning cycle at
@@ -367,76 +1115,497 @@ where
     /// (e.g., once per day).
     ///
     /// The cleaning procedure can also be started manually with
-    /// [`start_fan_cleaning`](Self::start_fan_cleaning).
+    /// [`start_fan_cleaning`](Self::start_fan_cleaning). Pass
+    /// [`CleaningInterval::DISABLED`] to only clean manually.
     ///
     /// # Errors
     /// Reading the response can fail, the device can run into an internal
     /// error or the connection could have issues leading to invalid responses.
     /// These are caught and reported as Errors.
     #[inline(always)]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub async fn write_cleaning_interval(
         &mut self,
-        val: u32,
+        interval: CleaningInterval,
     ) -> Result<(), Error<Tx::Error, Rx::Error>> {
-        const CMD: Command = Command::ReadWriteAutoCleaningInterval;
         // wrong in datasheet spec correct in datasheet example
         const SUB_CMD: u8 = 0x05;
+        self.require_awake()?;
 
-        let interval = val.to_be_bytes();
-        let cmd = cmd!(
-            CMD,
-            [SUB_CMD, interval[0], interval[1], interval[2], interval[3]]
-        );
-        self.encode_and_send(&cmd).await?;
-
-        let response = self.receive_and_decode().await?;
-        check_miso_frame(&response, CMD)?;
-        if response[3] != 0 {
-            Err(Error::InvalidResponse)
-        } else {
+        let interval = interval.raw().to_be_bytes();
+        let response = self
+            .device
+            .execute(
+                Command::ReadWriteAutoCleaningInterval as u8,
+                &[SUB_CMD, interval[0], interval[1], interval[2], interval[3]],
+            )
+            .await?;
+        if response.data().is_empty() {
             Ok(())
+        } else {
+            Err(Error::Protocol(ProtocolError::InvalidResponse(
+                RawFrame::new(response.frame()),
+            )))
         }
     }
 
-    /// Start fan cleaning manually. This will accelerate the fan to maximum
-    /// speed for 10 seconds in order to blow out the dust accumulated inside
-    /// the fan.
+    /// Like [`read_cleaning_interval`](Self::read_cleaning_interval) but
+    /// returns a [`Duration`], `None` if the periodic cleaning is disabled
+    ///
+    /// # Errors
+    /// See [`Self::read_cleaning_interval`]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn read_cleaning_interval_duration(
+        &mut self,
+    ) -> Result<Option<Duration>, Error<Tx::Error, Rx::Error>> {
+        Ok(self.read_cleaning_interval().await?.duration())
+    }
+
+    /// Like [`write_cleaning_interval`](Self::write_cleaning_interval) but
+    /// takes a [`Duration`] so the unit can not be mistaken. Parts of a
+    /// second are rounded down.
     ///
     /// # Errors
+    /// Returns [`Error::ArgumentOutOfRange`] without contacting the sensor
+    /// if `interval` is shorter than a second, which would disable the
+    /// periodic cleaning, or longer than `u32::MAX` seconds.
     /// Reading the response can fail, the device can run into an internal
     /// error or the connection could have issues leading to invalid responses.
     /// These are caught and reported as Errors.
-    #[inline(always)]
-    pub async fn start_fan_cleaning(&mut self) -> Result<(), Error<Tx::Error, Rx::Error>> {
-        const CMD: Command = Command::StartFanCleaning;
-        let cmd = cmd!(CMD);
-        self.encode_and_send(&cmd).await?;
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn write_cleaning_interval_duration(
+        &mut self,
+        interval: Duration,
+    ) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        let interval =
+            CleaningInterval::from_duration(interval).ok_or(Error::ArgumentOutOfRange)?;
+        self.write_cleaning_interval(interval).await
+    }
 
-        let response = self.receive_and_decode().await?;
-        check_miso_frame(&response, CMD)
+    /// Like [`write_cleaning_interval_duration`](Self::write_cleaning_interval_duration)
+    /// but takes a [`fugit`] duration of any unit. Use `.into()` to pass a
+    /// 32 bit duration.
+    ///
+    /// # Errors
+    /// See [`Self::write_cleaning_interval_duration`]
+    #[cfg(feature = "fugit")]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn write_cleaning_interval_fugit<const NOM: u32, const DENOM: u32>(
+        &mut self,
+        interval: fugit::Duration<u64, NOM, DENOM>,
+    ) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        let interval = Duration::from_secs(interval.to_secs());
+        self.write_cleaning_interval_duration(interval).await
     }
 
-    /// Gets version information about the firmware, hardware, and SHDLC protocol
+    /// Writes the cleaning interval then reads it back to make sure the
+    /// sensor stored it, for example on a provisioning line.
+    ///
+    /// The sensor keeps reporting the previous interval until it is reset
+    /// or power cycled. Pass `reset` to reset the sensor in between, a
+    /// sensor that was measuring is started again afterwards. Without it
+    /// the read back only matches if the interval did not change.
     ///
     /// # Errors
+    /// Returns [`VerifyError::Mismatch`] if the interval read back differs
+    /// from `interval`.
     /// Reading the response can fail, the device can run into an internal
     /// error or the connection could have issues leading to invalid responses.
     /// These are caught and reported as Errors.
-    #[inline(always)]
-    pub async fn serial_number(&mut self) -> Result<String<32>, Error<Tx::Error, Rx::Error>> {
-        const CMD: Command = Command::DeviceInformation;
-        const SUB_CMD: u8 = DeviceInfo::SerialNumber as u8;
-        let cmd = cmd!(CMD, [SUB_CMD]);
-        self.encode_and_send(&cmd).await?;
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn write_cleaning_interval_verified(
+        &mut self,
+        interval: CleaningInterval,
+        reset: bool,
+    ) -> Result<(), VerifyError<Tx::Error, Rx::Error>> {
+        self.write_cleaning_interval(interval).await?;
+        if reset {
+            let measuring = self.mode == Some(Mode::Measurement);
+            self.reset().await?;
+            if measuring {
+                self.start_measurement().await?;
+            }
+        }
+
+        let read = self.read_cleaning_interval().await?;
+        if read == interval {
+            Ok(())
+        } else {
+            Err(VerifyError::Mismatch {
+                written: interval,
+                read,
+            })
+        }
+    }
+
+    /// Brings the sensor and driver in line with `config`. The cleaning
+    /// interval is only written if the sensor reports a different one,
+    /// sparing its non-volatile memory. A sensor that is measuring in
+    /// another format is restarted in the new format. If asked for a fan
+    /// cleaning is run last, waiting the 10 seconds it takes, this needs
+    /// the sensor to be measuring.
+    ///
+    /// # Errors
+    /// Returns [`Error::ArgumentOutOfRange`] without applying anything if
+    /// the address is the [`BROADCAST_ADDRESS`](shdlc::BROADCAST_ADDRESS).
+    /// Otherwise returns the error of the first step that failed, steps
+    /// before it are applied.
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn apply_config(
+        &mut self,
+        config: Config,
+    ) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        if config.address == shdlc::BROADCAST_ADDRESS {
+            return Err(Error::ArgumentOutOfRange);
+        }
+        self.device.set_address(config.address);
+        if self.read_cleaning_interval().await? != config.cleaning_interval {
+            self.write_cleaning_interval(config.cleaning_interval)
+                .await?;
+        }
+        if self.format != config.format {
+            self.format = config.format;
+            if self.mode == Some(Mode::Measurement) {
+                self.stop_measurement().await?;
+                self.start_measurement().await?;
+            }
+        }
+        if config.clean_fan {
+            self.start_fan_cleaning().await?;
+            self.device.delay().delay_ms(FAN_CLEANING_MS).await;
+        }
+        Ok(())
+    }
+
+    /// The configuration of the sensor and driver, see [`Config`]
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn read_config(&mut self) -> Result<Config, Error<Tx::Error, Rx::Error>> {
+        Ok(Config {
+            address: self.address(),
+            format: self.format,
+            cleaning_interval: self.read_cleaning_interval().await?,
+            clean_fan: false,
+        })
+    }
+
+    /// Reads the configuration, identity and status of the sensor in one
+    /// call, see [`Snapshot`]. Changes nothing, the status register is
+    /// not cleared.
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn snapshot(&mut self) -> Result<Snapshot, Error<Tx::Error, Rx::Error>> {
+        Ok(Snapshot {
+            config: self.read_config().await?,
+            info: self.device_info().await?,
+            status: self.read_device_status_register(false).await?,
+        })
+    }
+
+    /// Puts the sensor in sleep mode, lowering its power draw to below
+    /// 50µA. Only works in idle mode, stop the measurement first. Use
+    /// [`Self::wake_up`] to return to idle mode.
+    ///
+    /// Requires firmware 2.0 or newer.
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn sleep(&mut self) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        self.require(Mode::Idle)?;
+        let result = self
+            .device
+            .execute_ref(Command::Sleep as u8, &[])
+            .await
+            .map(|_| ());
+        self.track_mode(result, Some(Mode::Sleep))?;
+        Ok(())
+    }
+
+    /// Wakes the sensor from sleep mode, it is then in idle mode. The uart
+    /// of a sleeping sensor is off, it is woken by a single 0xFF byte
+    /// which is sent first.
+    ///
+    /// Requires firmware 2.0 or newer.
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors. Returns a
+    /// [`DeviceError`] if the sensor was not asleep, or
+    /// [`Error::WrongDriverState`] if the driver knows it is not.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn wake_up(&mut self) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        const WAKE_UART: u8 = 0xFF;
+        self.require(Mode::Sleep)?;
+        self.device.write_raw(&[WAKE_UART]).await?;
+        let result = self
+            .device
+            .execute_ref(Command::WakeUp as u8, &[])
+            .await
+            .map(|_| ());
+        self.track_mode(result, Some(Mode::Idle))?;
+        Ok(())
+    }
 
-        let response = self.receive_and_decode().await?;
-        let data = parse_miso_frame(&response, CMD)?;
+    /// Start fan cleaning manually. This will accelerate the fan to maximum
+    /// speed for 10 seconds in order to blow out the dust accumulated inside
+    /// the fan. Measurement values are not updated while cleaning.
+    ///
+    /// Only works in measurement mode. See
+    /// [`Sps30Builder::clean_fan_on_start`] to clean when initializing.
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    #[inline(always)]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn start_fan_cleaning(&mut self) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        self.require(Mode::Measurement)?;
+        let result = self
+            .device
+            .execute(Command::StartFanCleaning as u8, &[])
+            .await;
+        self.track_mode(result, None)?;
+        Ok(())
+    }
+
+    /// Gets the serial number of the device
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    #[inline(always)]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn serial_number(&mut self) -> Result<String<32>, Error<Tx::Error, Rx::Error>> {
+        const SUB_CMD: u8 = InfoField::SerialNumber as u8;
+        self.require_awake()?;
+        let data = self
+            .device
+            .execute_ref(Command::DeviceInformation as u8, &[SUB_CMD])
+            .await?;
 
         let mut serial = Vec::new();
         serial
-            .extend_from_slice(data)
-            .map_err(|()| Error::FrameTooLarge)?;
-        String::from_utf8(serial).map_err(|_| Error::SerialInvalidUtf8)
+            .extend_from_slice(until_nul(data))
+            .map_err(|()| Error::Protocol(ProtocolError::FrameTooLarge))?;
+        String::from_utf8(serial).map_err(|_| Error::Protocol(ProtocolError::SerialInvalidUtf8))
+    }
+
+    /// Gets the product type, "00080000" for the SPS30
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn product_type(&mut self) -> Result<String<8>, Error<Tx::Error, Rx::Error>> {
+        const SUB_CMD: u8 = InfoField::ProductType as u8;
+        self.require_awake()?;
+        let data = self
+            .device
+            .execute_ref(Command::DeviceInformation as u8, &[SUB_CMD])
+            .await?;
+
+        let invalid = || Error::Protocol(ProtocolError::InvalidResponse(RawFrame::new(data)));
+        let product_type = Vec::from_slice(until_nul(data)).map_err(|()| invalid())?;
+        String::from_utf8(product_type).map_err(|_| invalid())
+    }
+
+    /// Gets version information about the firmware, hardware, and SHDLC protocol
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn versions(&mut self) -> Result<Versions, Error<Tx::Error, Rx::Error>> {
+        self.require_awake()?;
+        let data = self
+            .device
+            .execute_ref(Command::ReadVersion as u8, &[])
+            .await?;
+        let [fw_major, fw_minor, _, hardware_revision, _, shdlc_major, shdlc_minor, ..] = *data
+        else {
+            return Err(Error::Protocol(ProtocolError::InvalidResponse(
+                RawFrame::new(data),
+            )));
+        };
+        Ok(Versions {
+            firmware: Version {
+                major: fw_major,
+                minor: fw_minor,
+            },
+            hardware_revision,
+            shdlc: Version {
+                major: shdlc_major,
+                minor: shdlc_minor,
+            },
+        })
+    }
+
+    /// Identifies the unit: serial number, product type and versions in one
+    /// call. Useful for provisioning and telemetry.
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn device_info(&mut self) -> Result<DeviceInfo, Error<Tx::Error, Rx::Error>> {
+        Ok(DeviceInfo {
+            serial_number: self.serial_number().await?,
+            product_type: self.product_type().await?,
+            versions: self.versions().await?,
+        })
+    }
+
+    /// Like [`device_info`](Self::device_info) but only asks the sensor
+    /// the first time, later calls return the cached copy without using
+    /// the bus. Use it to include the identity in every telemetry payload.
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn identify(&mut self) -> Result<DeviceInfo, Error<Tx::Error, Rx::Error>> {
+        if let Some(identity) = &self.identity {
+            return Ok(identity.clone());
+        }
+        let identity = self.device_info().await?;
+        self.identity = Some(identity.clone());
+        Ok(identity)
+    }
+
+    /// The identity cached by [`Self::identify`], `None` before it
+    /// succeeded
+    pub fn identity(&self) -> Option<&DeviceInfo> {
+        self.identity.as_ref()
+    }
+
+    /// Checks whether the sensor is alive by requesting its product type.
+    /// Cheap enough to run periodically from a supervisory task, does not
+    /// touch measurements.
+    ///
+    /// A sensor put to sleep does not answer, [`Health::NoResponse`] is
+    /// returned without sending anything. A sensor that is unpowered or
+    /// disconnected is only noticed with a [timeout](Self::with_timeout),
+    /// without one the ping waits for an answer forever.
+    ///
+    /// # Errors
+    /// Only returns an error if the uart itself failed, everything else is
+    /// classified as a [`Health`].
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn ping(&mut self) -> Result<Health, Error<Tx::Error, Rx::Error>> {
+        const SUB_CMD: u8 = InfoField::ProductType as u8;
+        if self.require_awake().is_err() {
+            debug!("not pinging, the sensor is asleep");
+            return Ok(Health::NoResponse);
+        }
+        let result = self
+            .device
+            .execute_ref(Command::DeviceInformation as u8, &[SUB_CMD])
+            .await;
+        match result {
+            Ok(_) => Ok(Health::Healthy),
+            Err(Error::Device(e)) => Ok(Health::DeviceError(e)),
+            Err(
+                e @ (Error::Transport(TransportError::Read(_))
+                | Error::Transport(TransportError::Write(_))),
+            ) => Err(e),
+            Err(e) => {
+                debug!("ping failed: {}", e);
+                Ok(Health::NoResponse)
+            }
+        }
+    }
+
+    /// Checks the wiring and sensor by reading the device information,
+    /// the status register and one measurement. Run it at boot, then use
+    /// [`SelfTestReport::fault`] to decide whether to trust the sensor.
+    ///
+    /// An idle sensor is started for the measurement and stopped again
+    /// afterwards, which takes a few seconds. A measuring sensor keeps
+    /// measuring.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn self_test(&mut self) -> SelfTestReport<Tx::Error, Rx::Error> {
+        let device_info = self.device_info().await;
+        let status_register = match &device_info {
+            Ok(info) if info.versions.firmware < self_test::STATUS_REGISTER_FIRMWARE => None,
+            _ => Some(self.read_device_status_register(false).await),
+        };
+
+        let measurement = match self.read_measurement().await {
+            Err(
+                Error::Device(DeviceError::InvalidStateForCommand)
+                | Error::WrongDriverState {
+                    actual: Mode::Idle, ..
+                },
+            ) => {
+                let measurement = self.start_measurement_and_wait_ready().await;
+                let stopped = self.stop_measurement().await;
+                measurement.and_then(|m| stopped.map(|()| m))
+            }
+            // measuring but the first sample is not ready yet
+            Err(Error::Protocol(ProtocolError::EmptyResult)) => {
+                self.device.delay().delay_ms(1_000).await;
+                self.read_measurement().await
+            }
+            other => other,
+        };
+
+        SelfTestReport {
+            device_info,
+            status_register,
+            measurement,
+        }
+    }
+
+    /// Read the device status register, it flags fan speed warnings, laser
+    /// failures and fan failures. With `clear` set the register is cleared
+    /// after reading.
+    ///
+    /// Requires firmware 2.2 or newer.
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    #[inline(always)]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn read_device_status_register(
+        &mut self,
+        clear: bool,
+    ) -> Result<DeviceStatus, Error<Tx::Error, Rx::Error>> {
+        self.require_awake()?;
+        let sub_cmd = u8::from(clear);
+        let response = self
+            .device
+            .execute(Command::ReadDeviceStatusRegister as u8, &[sub_cmd])
+            .await?;
+        let Some(register) = response.data().get(..4) else {
+            return Err(Error::Protocol(ProtocolError::InvalidResponse(
+                RawFrame::new(response.frame()),
+            )));
+        };
+        let register: [u8; 4] = register.try_into().expect("slice has len 4");
+        Ok(DeviceStatus::from_bits(u32::from_be_bytes(register)))
     }
 
     /// Reset device
@@ -448,14 +1617,556 @@ where
     /// error or the connection could have issues leading to invalid responses.
     /// These are caught and reported as Errors.
     #[inline(always)]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub async fn reset(&mut self) -> Result<(), Error<Tx::Error, Rx::Error>> {
-        const CMD: Command = Command::Reset;
-        let cmd = cmd!(CMD);
-        self.encode_and_send(&cmd).await?;
+        self.device.execute(Command::Reset as u8, &[]).await?;
+        self.set_mode(Some(Mode::Idle));
+        self.device.delay().delay_ms(20).await;
+        Ok(())
+    }
+
+    /// Tries to get a failing fan running again following the datasheet:
+    /// resets the sensor, starts measuring and reads (and clears) the
+    /// status register. If the fan still fails or runs at the wrong speed
+    /// and `clean` is set a fan cleaning is run, waiting the 10 seconds
+    /// it takes, and the status is read again.
+    ///
+    /// The sensor is left measuring. Check
+    /// [`FanRecovery::recovered`] to find out whether the fan needs
+    /// replacing.
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn recover_fan_failure(
+        &mut self,
+        clean: bool,
+    ) -> Result<FanRecovery, Error<Tx::Error, Rx::Error>> {
+        self.reset().await?;
+        self.start_measurement_and_wait_ready().await?;
+        let after_restart = self.read_device_status_register(true).await?;
+        let mut recovery = FanRecovery {
+            after_restart,
+            after_cleaning: None,
+        };
+        if recovery.recovered() || !clean {
+            return Ok(recovery);
+        }
+
+        debug!("fan still failing after restart, cleaning it");
+        self.start_fan_cleaning().await?;
+        self.device.delay().delay_ms(FAN_CLEANING_MS).await;
+        recovery.after_cleaning = Some(self.read_device_status_register(true).await?);
+        Ok(recovery)
+    }
 
-        let response = self.receive_and_decode().await?;
-        check_miso_frame(&response, CMD)?;
-        self.delay.delay_ms(20).await;
+    /// Sends command `cmd` with `data` and returns the validated response
+    /// without interpreting its payload. Use this for commands the driver
+    /// does not support, such as those added in future firmware.
+    ///
+    /// The driver can not know whether the command changed the mode of the
+    /// sensor, the [current mode](Self::current_mode) is forgotten.
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors. More then
+    /// [`MAX_REQUEST_DATA_LEN`](shdlc::MAX_REQUEST_DATA_LEN) bytes of data
+    /// return [`ProtocolError::FrameTooLarge`].
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn send_raw(
+        &mut self,
+        cmd: u8,
+        data: &[u8],
+    ) -> Result<RawResponse, Error<Tx::Error, Rx::Error>> {
+        self.set_mode(None);
+        self.device.execute(cmd, data).await
+    }
+
+    /// Reads a measurement every `interval_ms` milliseconds, evenly spaced.
+    /// Give the sampler a [clock](sampling::Sampler::with_clock) to keep
+    /// the samples from drifting. The sensor must be measuring. It produces
+    /// a new measurement every second, shorter intervals return the same
+    /// one.
+    ///
+    /// ```no_run
+    /// # use sps30_async::{Error, MaybeFormat, Sps30};
+    /// # async fn log<Tx, Rx, D>(sensor: &mut Sps30<Tx, Rx, D>) -> Result<(), Error<Tx::Error, Rx::Error>>
+    /// # where
+    /// #     Tx: embedded_io_async::Write,
+    /// #     Tx::Error: MaybeFormat,
+    /// #     Rx: embedded_io_async::Read,
+    /// #     Rx::Error: MaybeFormat,
+    /// #     D: embedded_hal_async::delay::DelayNs,
+    /// # {
+    /// let mut sampler = sensor.sample_every(10_000);
+    /// loop {
+    ///     let measurement = sampler.next().await?;
+    /// }
+    /// # }
+    /// ```
+    pub fn sample_every(&mut self, interval_ms: u32) -> sampling::Sampler<'_, Tx, Rx, D, O, L> {
+        sampling::Sampler::new(self, interval_ms)
+    }
+
+    /// Starts measuring, waits for the readings to settle, reads one or
+    /// the average of several measurements then stops measuring again.
+    /// The whole measurement cycle for devices that only measure now and
+    /// then.
+    ///
+    /// If the warmup is shorter than the second the sensor needs for its
+    /// first measurement the read is retried every 100ms, for at most 3
+    /// seconds.
+    ///
+    /// Dropping the future before it finishes leaves the sensor measuring.
+    /// Stop it with [`Self::stop_measurement`], or call this again which
+    /// continues from there.
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors. The sensor is stopped if
+    /// a read fails. Returns [`ProtocolError::EmptyResult`] if no
+    /// measurement became ready.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn measure_once(
+        &mut self,
+        settings: schedule::OneShot,
+    ) -> Result<Measurement, Error<Tx::Error, Rx::Error>> {
+        const MEASUREMENT_INTERVAL_MS: u32 = 1_000;
+
+        if self.mode != Some(Mode::Measurement) {
+            self.start_measurement().await?;
+        }
+        self.device.delay().delay_ms(settings.warmup_ms).await;
+
+        let samples = settings.samples.max(1);
+        let mut sum = Measurement::default();
+        for i in 0..samples {
+            if i > 0 {
+                self.device.delay().delay_ms(MEASUREMENT_INTERVAL_MS).await;
+            }
+            let measurement = match self.read_measurement().await {
+                // the warmup was shorter than the first measurement takes
+                Err(Error::Protocol(ProtocolError::EmptyResult)) if i == 0 => {
+                    self.read_measurement_when_ready().await
+                }
+                other => other,
+            };
+            match measurement {
+                Ok(measurement) => sum = sum + measurement,
+                Err(e) => {
+                    // the read error is more useful than one from stopping
+                    let _ = self.stop_measurement().await;
+                    return Err(e);
+                }
+            }
+        }
+        self.stop_measurement().await?;
+        Ok(sum / f32::from(samples))
+    }
+
+    /// Stops measuring and puts the sensor to sleep if its firmware
+    /// supports that (2.0 or newer), then returns the uart halves and
+    /// delay. Use this before the MCU enters deep sleep or is updated.
+    ///
+    /// The peripherals are returned even if shutting down failed, together
+    /// with the error.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    #[cfg_attr(feature = "tracing", allow(clippy::type_complexity))]
+    pub async fn shutdown(mut self) -> ((Tx, Rx, D), Result<(), Error<Tx::Error, Rx::Error>>) {
+        let result = self.enter_sleep().await;
+        (self.release(), result)
+    }
+
+    async fn enter_sleep(&mut self) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        const SLEEP_FIRMWARE: Version = Version { major: 2, minor: 0 };
+        match self.mode {
+            Some(Mode::Sleep) => return Ok(()),
+            Some(Mode::Idle) => (),
+            Some(Mode::Measurement) => self.stop_measurement().await?,
+            None => match self.stop_measurement().await {
+                // was not measuring
+                Ok(()) | Err(Error::Device(DeviceError::InvalidStateForCommand)) => (),
+                Err(e) => return Err(e),
+            },
+        }
+        if self.versions().await?.firmware >= SLEEP_FIRMWARE {
+            self.sleep().await?;
+        }
         Ok(())
     }
 }
+
+impl<Tx, Rx, D, O, L> Sps30<Tx, Rx, D, O, L>
+where
+    Tx: Write,
+    Tx::Error: MaybeFormat,
+    Rx: Read + ReadReady,
+    Rx::Error: MaybeFormat,
+    D: DelayNs,
+    O: FrameObserver,
+    L: ProtocolLogger,
+{
+    /// Discards any bytes waiting in the receive buffer, use this to clear
+    /// stale or corrupted data before a critical command. Returns the
+    /// number of bytes discarded. See [`ShdlcDevice::drain_rx`].
+    ///
+    /// # Errors
+    /// Returns [`TransportError::Read`] if reading from the uart fails.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn flush_rx(&mut self) -> Result<usize, Error<Tx::Error, Rx::Error>> {
+        self.device.drain_rx().await
+    }
+
+    /// Discard pending received bytes before sending each command so a
+    /// buffered response, for example to a cancelled operation, is never
+    /// mistaken for the reply. See [`ShdlcDevice::set_drain_before_send`].
+    pub fn set_drain_before_command(&mut self, enabled: bool) {
+        self.device.set_drain_before_send(enabled);
+    }
+
+    /// Recovers from persistent failures without recreating the driver:
+    /// discards pending received bytes, wakes the sensor if it was put to
+    /// sleep, resets it and starts measuring. Returns the first
+    /// measurement, proving the sensor works again.
+    ///
+    /// Keeps the configuration, such as the address, measurement format
+    /// and calibration.
+    ///
+    /// # Errors
+    /// Returns the error of the first step that failed, see
+    /// [`Self::reset`] and [`Self::start_measurement_and_wait_ready`].
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn reinit(&mut self) -> Result<Measurement, Error<Tx::Error, Rx::Error>> {
+        self.flush_rx().await?;
+        // whatever the driver believed may be why things failed
+        if self.mode.take() == Some(Mode::Sleep) {
+            self.wake_up().await?;
+        }
+        self.reset().await?;
+        self.start_measurement_and_wait_ready().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        CleaningInterval, Command, DeviceError, Error, ErrorKind, Health, Measurement,
+        MeasurementFormat, Mode, ProtocolError, Sps30, TransportError, VerifyError, Version,
+        MAX_MEASUREMENT_LEN,
+    };
+    use crate::mock::{MockSps30, NoDelay};
+    use crate::protocol::Request;
+    use crate::shdlc::encode_to;
+    use core::time::Duration;
+    use embedded_hal_async::delay::DelayNs;
+    use embedded_io_async::Write;
+    use futures::executor::block_on;
+
+    /// A driver for `mock`, initialized and measuring
+    fn measuring(mock: &MockSps30) -> Sps30<&MockSps30, &MockSps30, NoDelay> {
+        block_on(Sps30::from_tx_rx(mock, mock, NoDelay)).unwrap()
+    }
+
+    fn measurement(number: [f32; 5]) -> Measurement {
+        let [pm0_5, pm1_0, pm2_5, pm4_0, pm10] = number;
+        Measurement {
+            mass_pm1_0: 1.0,
+            mass_pm2_5: 2.0,
+            mass_pm4_0: 3.0,
+            mass_pm10: 4.0,
+            mass_pm0_5: pm0_5,
+            number_pm1_0: pm1_0,
+            number_pm2_5: pm2_5,
+            number_pm4_0: pm4_0,
+            number_pm10: pm10,
+            typical_particle_size: 0.5,
+        }
+    }
+
+    #[test]
+    fn mean() {
+        let a = measurement([1.0, 2.0, 3.0, 4.0, 5.0]);
+        let b = measurement([3.0, 4.0, 5.0, 6.0, 7.0]);
+        let mean = Measurement::mean([a, b]).unwrap();
+        assert_eq!(mean, measurement([2.0, 3.0, 4.0, 5.0, 6.0]));
+        let diff = b - a;
+        assert_eq!((diff.mass_pm10, diff.number_pm10), (0.0, 2.0));
+        assert_eq!(Measurement::mean([]), None);
+    }
+
+    #[test]
+    fn zeroed() {
+        const BUFFER: [Measurement; 2] = [Measurement::zeroed(); 2];
+        assert_eq!(BUFFER, [Measurement::default(); 2]);
+    }
+
+    #[test]
+    fn pm_ratios() {
+        let m = measurement([0.0; 5]);
+        assert_eq!(m.pm1_0_to_pm2_5_ratio(), Some(0.5));
+        assert_eq!(m.pm2_5_to_pm10_ratio(), Some(0.5));
+        let empty = Measurement::zeroed();
+        assert_eq!(empty.pm1_0_to_pm2_5_ratio(), None);
+        assert_eq!(empty.pm2_5_to_pm10_ratio(), None);
+    }
+
+    #[test]
+    fn number_bins_are_clamped() {
+        let bins = measurement([10.0, 15.0, 14.9, 16.0, 16.5]).number_bins_per_cm3();
+        assert_eq!(bins, [5.0, 0.0, 16.0 - 14.9, 0.5]);
+    }
+
+    #[test]
+    fn raw_measurement() {
+        let mock = MockSps30::new();
+        let mut sensor = measuring(&mock);
+        let raw = block_on(sensor.read_measurement_raw()).unwrap();
+        assert_eq!(raw.len(), MAX_MEASUREMENT_LEN);
+        assert_eq!(raw[4..8], mock.measurement().mass_pm2_5.to_be_bytes());
+        let bits = block_on(sensor.read_measurement_bits()).unwrap();
+        assert_eq!(bits.to_measurement(), mock.measurement());
+    }
+
+    #[test]
+    fn detects_measurement_format() {
+        let mock = MockSps30::new();
+        block_on(
+            Sps30::builder(&mock, &mock, NoDelay)
+                .measurement_format(MeasurementFormat::Integer)
+                .build(),
+        )
+        .unwrap();
+
+        // does not know the sensor is sending integers
+        let mut sensor = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        let measurement = block_on(sensor.read_measurement()).unwrap();
+        assert_eq!(measurement.mass_pm2_5, 5.0);
+        assert_eq!(measurement.typical_particle_size, 0.55);
+    }
+
+    #[test]
+    fn cleaning_interval_verified() {
+        let mock = MockSps30::new().with_stale_cleaning_interval();
+        let mut sensor = measuring(&mock);
+        let daily = CleaningInterval::from_secs(86_400).unwrap();
+        assert_eq!(
+            block_on(sensor.write_cleaning_interval_verified(daily, false)),
+            Err(VerifyError::Mismatch {
+                written: daily,
+                read: CleaningInterval::DEFAULT,
+            })
+        );
+
+        block_on(sensor.write_cleaning_interval_verified(daily, true)).unwrap();
+        assert!(mock.is_measuring());
+        assert_eq!(block_on(sensor.read_cleaning_interval()), Ok(daily));
+    }
+
+    #[test]
+    fn cleaning_interval_duration() {
+        let mock = MockSps30::new();
+        let mut sensor = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        let day = Duration::from_secs(24 * 60 * 60);
+        block_on(sensor.write_cleaning_interval_duration(day + Duration::from_millis(999)))
+            .unwrap();
+        assert_eq!(
+            block_on(sensor.read_cleaning_interval_duration()),
+            Ok(Some(day))
+        );
+
+        let sent = mock.commands_received();
+        for invalid in [Duration::from_millis(999), Duration::from_secs(1 << 32)] {
+            let err = block_on(sensor.write_cleaning_interval_duration(invalid)).unwrap_err();
+            assert_eq!(err, Error::ArgumentOutOfRange);
+            assert_eq!(err.kind(), ErrorKind::InvalidArgument);
+        }
+        assert_eq!(mock.commands_received(), sent);
+    }
+
+    #[test]
+    fn identify_once() {
+        let mock = MockSps30::new().with_serial_number("IDENTIFY");
+        let mut sensor = measuring(&mock);
+        assert_eq!(sensor.identity(), None);
+        let identity = block_on(sensor.identify()).unwrap();
+        assert_eq!(identity.serial_number, "IDENTIFY");
+
+        let sent = mock.commands_received();
+        assert_eq!(block_on(sensor.identify()), Ok(identity.clone()));
+        assert_eq!(mock.commands_received(), sent);
+        assert_eq!(sensor.identity(), Some(&identity));
+    }
+
+    #[test]
+    fn addressed_device() {
+        let mock = MockSps30::new().with_address(7);
+        let sensor = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        let mut sensor = sensor.with_address(7);
+        block_on(sensor.start_measurement()).unwrap();
+
+        let mut other = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        assert_eq!(
+            block_on(other.start_measurement()).unwrap_err(),
+            Error::Transport(TransportError::Eof)
+        );
+    }
+
+    #[test]
+    fn mode_tracking() {
+        let mock = MockSps30::new();
+        let mut sensor = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        assert_eq!(sensor.current_mode(), None);
+        block_on(sensor.reset()).unwrap();
+        assert_eq!(sensor.current_mode(), Some(Mode::Idle));
+
+        let commands = mock.commands_received();
+        let err = block_on(sensor.read_measurement()).unwrap_err();
+        assert_eq!(
+            err,
+            Error::WrongDriverState {
+                expected: Mode::Measurement,
+                actual: Mode::Idle
+            }
+        );
+        assert_eq!(err.kind(), ErrorKind::State);
+        block_on(sensor.sleep()).unwrap();
+        assert_eq!(
+            block_on(sensor.serial_number()).unwrap_err(),
+            Error::WrongDriverState {
+                expected: Mode::Idle,
+                actual: Mode::Sleep
+            }
+        );
+        block_on(sensor.wake_up()).unwrap();
+        block_on(sensor.start_measurement()).unwrap();
+        assert_eq!(sensor.current_mode(), Some(Mode::Measurement));
+        assert_eq!(mock.commands_received(), commands + 3);
+
+        // the sensor was stopped behind the driver's back
+        let mut other = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        block_on(other.stop_measurement()).unwrap();
+        block_on(sensor.read_measurement()).unwrap_err();
+        assert_eq!(sensor.current_mode(), None);
+        block_on(sensor.start_measurement()).unwrap();
+        block_on(sensor.read_measurement()).unwrap();
+    }
+
+    #[test]
+    fn shutdown() {
+        let mock = MockSps30::new();
+        let sensor = measuring(&mock);
+        let ((tx, rx, _), result) = block_on(sensor.shutdown());
+        result.unwrap();
+        assert!(mock.is_sleeping() && !mock.is_measuring());
+        assert!(core::ptr::eq(tx, &mock) && core::ptr::eq(rx, &mock));
+
+        // an idle sensor the driver knows nothing about
+        let mock = MockSps30::new();
+        let sensor = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        let (_, result) = block_on(sensor.shutdown());
+        result.unwrap();
+        assert!(mock.is_sleeping());
+    }
+
+    #[test]
+    fn reinit() {
+        let mock = MockSps30::new().with_warmup(2);
+        let mut sensor = measuring(&mock);
+        block_on(sensor.stop_measurement()).unwrap();
+        block_on(sensor.sleep()).unwrap();
+        // leftovers from a request made outside the driver
+        let mut uart = &mock;
+        block_on(uart.write_all(&[0xFF])).unwrap();
+        block_on(encode_to(Request::read_measurement(0).frame(), &mut uart)).unwrap();
+
+        let measurement = block_on(sensor.reinit()).unwrap();
+        assert_eq!(measurement, mock.measurement());
+        assert_eq!(sensor.current_mode(), Some(Mode::Measurement));
+        assert!(mock.is_measuring());
+    }
+
+    #[test]
+    fn send_raw() {
+        let mock = MockSps30::new();
+        let mut sensor = measuring(&mock);
+        let response = block_on(sensor.send_raw(Command::DeviceInformation as u8, &[0])).unwrap();
+        assert_eq!(response.data(), b"00080000\0");
+        assert_eq!(response.command(), Command::DeviceInformation as u8);
+        assert_eq!(sensor.current_mode(), None);
+
+        assert_eq!(
+            block_on(sensor.send_raw(0x42, &[])).unwrap_err(),
+            Error::Device(DeviceError::UnknownCmd)
+        );
+    }
+
+    #[test]
+    fn device_info() {
+        let mock = MockSps30::new().with_serial_number("8C4A2B1F93D5E607");
+        let mut sensor = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        let info = block_on(sensor.device_info()).unwrap();
+        assert_eq!(info.serial_number, "8C4A2B1F93D5E607");
+        assert_eq!(info.product_type, "00080000");
+        assert_eq!(info.versions.firmware, Version { major: 2, minor: 2 });
+        assert_eq!(info.versions.hardware_revision, 7);
+        assert_eq!(info.versions.shdlc.to_string(), "2.0");
+    }
+
+    #[test]
+    fn ping() {
+        let mock = MockSps30::new();
+        let mut sensor = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        assert_eq!(block_on(sensor.ping()).unwrap(), Health::Healthy);
+
+        mock.fail_next(0x43);
+        assert_eq!(
+            block_on(sensor.ping()).unwrap(),
+            Health::DeviceError(DeviceError::InvalidStateForCommand)
+        );
+
+        block_on(sensor.sleep()).unwrap();
+        let sent = mock.commands_received();
+        assert_eq!(block_on(sensor.ping()).unwrap(), Health::NoResponse);
+        assert_eq!(mock.commands_received(), sent);
+        block_on(sensor.wake_up()).unwrap();
+
+        let mut sensor = sensor.with_address(5);
+        assert_eq!(block_on(sensor.ping()).unwrap(), Health::NoResponse);
+    }
+
+    #[test]
+    fn wait_for_first_measurement() {
+        let mock = MockSps30::new().with_warmup(3);
+        let mut sensor = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        let measurement = block_on(sensor.start_measurement_and_wait_ready()).unwrap();
+        assert_eq!(measurement.mass_pm10, mock.measurement().mass_pm10);
+        assert_eq!(mock.commands_received(), 1 + 4);
+
+        let mock = MockSps30::new().with_warmup(u32::MAX);
+        let mut sensor = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        assert_eq!(
+            block_on(sensor.start_measurement_and_wait_ready()).unwrap_err(),
+            Error::Protocol(ProtocolError::EmptyResult)
+        );
+    }
+
+    #[test]
+    fn borrowed_halves() {
+        let mock = MockSps30::new();
+        let (mut tx, mut rx, mut delay) = (&mock, &mock, NoDelay);
+        block_on(Sps30::from_tx_rx(&mut tx, &mut rx, &mut delay)).unwrap();
+
+        for _ in 0..2 {
+            let mut sensor = Sps30::from_tx_rx_uninit(&mut tx, &mut rx, &mut delay);
+            block_on(sensor.read_measurement()).unwrap();
+            // lend the delay to another driver
+            let delay: &mut NoDelay = sensor.delay();
+            block_on(delay.delay_ms(1));
+        }
+        assert_eq!(mock.commands_received(), 4);
+    }
+}
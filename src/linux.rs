@@ -0,0 +1,75 @@
+//! Ready made transport for Linux hosts such as a Raspberry Pi. Works with
+//! any tokio stream, usually a `tokio_serial::SerialStream`.
+//!
+//! Take care to open the port with the settings the SPS30 needs: 115200
+//! baud, 8 data bits, 1 stop bit and no parity.
+//!
+//! ```ignore
+//! use sps30_async::linux;
+//! use tokio_serial::SerialPortBuilderExt;
+//!
+//! let port = tokio_serial::new("/dev/ttyUSB0", 115_200).open_native_async()?;
+//! let mut sensor = linux::Sps30::from_tokio_stream(port).await?;
+//! let measurement = sensor.read_measurement().await?;
+//! ```
+
+use std::time::Duration;
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{ErrorType, Read, Write};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+
+use crate::{Error, IoError};
+
+/// The driver using the two halves of a tokio stream for transport
+pub type Sps30<S> = crate::Sps30<TokioIo<WriteHalf<S>>, TokioIo<ReadHalf<S>>, Delay>;
+
+impl<S> Sps30<S>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    /// Splits `stream` into halves then constructs and initializes the
+    /// driver, see [`from_tx_rx`](crate::Sps30::from_tx_rx).
+    ///
+    /// # Errors
+    /// Resetting the device or starting the measurement can fail.
+    pub async fn from_tokio_stream(stream: S) -> Result<Self, Error<IoError, IoError>> {
+        let (rx, tx) = tokio::io::split(stream);
+        Self::from_tx_rx(TokioIo(tx), TokioIo(rx), Delay).await
+    }
+}
+
+/// Adapts a tokio [`AsyncRead`]/[`AsyncWrite`] to the embedded-io-async
+/// traits.
+#[derive(Debug)]
+pub struct TokioIo<T>(pub T);
+
+impl<T> ErrorType for TokioIo<T> {
+    type Error = IoError;
+}
+
+impl<T: AsyncRead + Unpin> Read for TokioIo<T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Ok(self.0.read(buf).await?)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> Write for TokioIo<T> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Ok(self.0.write(buf).await?)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(self.0.flush().await?)
+    }
+}
+
+/// [`DelayNs`] implementation backed by the tokio timer
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Delay;
+
+impl DelayNs for Delay {
+    async fn delay_ns(&mut self, ns: u32) {
+        tokio::time::sleep(Duration::from_nanos(ns.into())).await;
+    }
+}
@@ -0,0 +1,53 @@
+//! Logging through defmt when the `defmt` feature is enabled, otherwise the
+//! log statements compile to nothing. Structured events for telemetry go
+//! through tracing when the `tracing` feature is enabled.
+
+#[cfg(feature = "defmt")]
+macro_rules! debug {
+    ($($arg:tt)*) => { defmt::debug!($($arg)*) };
+}
+
+#[cfg(feature = "defmt")]
+macro_rules! trace {
+    ($($arg:tt)*) => { defmt::trace!($($arg)*) };
+}
+
+#[cfg(not(feature = "defmt"))]
+macro_rules! debug {
+    ($($arg:expr),* $(,)?) => {{
+        $(let _ = &$arg;)*
+    }};
+}
+
+#[cfg(not(feature = "defmt"))]
+macro_rules! trace {
+    ($($arg:expr),* $(,)?) => {{
+        $(let _ = &$arg;)*
+    }};
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! telemetry {
+    ($level:ident, $($arg:tt)*) => {
+        tracing::event!(tracing::Level::$level, $($arg)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! telemetry {
+    ($($arg:tt)*) => {{}};
+}
+
+/// Bound on types that are logged. This is [`defmt::Format`] when the
+/// `defmt` feature is enabled, otherwise every type implements it.
+#[cfg(feature = "defmt")]
+pub trait MaybeFormat: defmt::Format {}
+#[cfg(feature = "defmt")]
+impl<T: defmt::Format + ?Sized> MaybeFormat for T {}
+
+/// Bound on types that are logged. This is `defmt::Format` when the
+/// `defmt` feature is enabled, otherwise every type implements it.
+#[cfg(not(feature = "defmt"))]
+pub trait MaybeFormat {}
+#[cfg(not(feature = "defmt"))]
+impl<T: ?Sized> MaybeFormat for T {}
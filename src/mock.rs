@@ -0,0 +1,644 @@
+//! A simulated sensor for testing code that uses this driver without
+//! hardware.
+//!
+//! [`MockSps30`] answers SHDLC requests like a real device would. It
+//! implements the embedded-io-async traits both directly and through a
+//! shared reference, so it can serve as tx and rx at the same time:
+//!
+//! ```
+//! use sps30_async::mock::{MockSps30, NoDelay};
+//! use sps30_async::Sps30;
+//!
+//! # futures::executor::block_on(async {
+//! let mock = MockSps30::new();
+//! let mut sensor = Sps30::from_tx_rx(&mock, &mock, NoDelay)
+//!     .await
+//!     .unwrap();
+//! let measurement = sensor.read_measurement().await.unwrap();
+//! assert_eq!(measurement.mass_pm2_5, mock.measurement().mass_pm2_5);
+//! # });
+//! ```
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+use core::future::poll_fn;
+use core::task::Poll;
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{ErrorType, Read, ReadReady, Write};
+use heapless::Vec;
+
+use crate::shdlc::device::{MAX_DECODED_FRAME_SIZE, MAX_ENCODED_FRAME_SIZE};
+use crate::shdlc::{self, checksum, Decoder};
+use crate::{Command, InfoField, Measurement};
+
+/// State byte the device answers with when a command is not allowed
+const INVALID_STATE_FOR_COMMAND: u8 = 0x43;
+/// State byte the device answers with for unknown commands
+const UNKNOWN_CMD: u8 = 0x02;
+
+/// [`DelayNs`] implementation that returns immediately
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    async fn delay_ns(&mut self, _ns: u32) {}
+}
+
+/// Simulated SPS30, see the [module](self) documentation.
+pub struct MockSps30 {
+    state: RefCell<State>,
+}
+
+struct State {
+    address: u8,
+    measuring: bool,
+    sleeping: bool,
+    /// A sleeping device only listens after a 0xFF byte
+    uart_awake: bool,
+    /// Format code sent with the last start measurement
+    format: u8,
+    measurement: [f32; 10],
+    cleaning_interval: u32,
+    /// Interval read back until the next reset, see
+    /// [`MockSps30::with_stale_cleaning_interval`]
+    interval_since_reset: Option<u32>,
+    serial_number: &'static str,
+    status_register: u32,
+    fail_next: Option<u8>,
+    /// Do not answer the next command, see [`MockSps30::ignore_next`]
+    ignore_next: bool,
+    /// Reads wait for data instead of returning EOF
+    blocking_reads: bool,
+    latency: u32,
+    pending_polls: u32,
+    warmup_reads: u32,
+    empty_reads_left: u32,
+    chunk_size: usize,
+    decoder: Decoder<MAX_DECODED_FRAME_SIZE>,
+    /// Responses not yet read, like a uart buffer
+    response: Vec<u8, { 2 * MAX_ENCODED_FRAME_SIZE }>,
+    response_read: usize,
+    commands_received: usize,
+}
+
+impl Default for MockSps30 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockSps30 {
+    /// An idle sensor with plausible indoor readings
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            state: RefCell::new(State {
+                address: shdlc::DEFAULT_ADDRESS,
+                measuring: false,
+                sleeping: false,
+                uart_awake: true,
+                format: 0x03,
+                measurement: [3.2, 5.1, 6.4, 7.0, 21.3, 24.9, 25.6, 25.7, 25.8, 0.55],
+                cleaning_interval: 604_800,
+                interval_since_reset: None,
+                serial_number: "MOCK0000000000000000",
+                status_register: 0,
+                fail_next: None,
+                ignore_next: false,
+                blocking_reads: false,
+                latency: 0,
+                pending_polls: 0,
+                warmup_reads: 0,
+                empty_reads_left: 0,
+                chunk_size: usize::MAX,
+                decoder: Decoder::new(),
+                response: Vec::new(),
+                response_read: 0,
+                commands_received: 0,
+            }),
+        }
+    }
+
+    /// Measurement returned by every read
+    #[must_use]
+    pub fn with_measurement(self, measurement: Measurement) -> Self {
+        self.set_measurement(measurement);
+        self
+    }
+
+    /// SHDLC address the device listens on, frames for other addresses
+    /// are ignored
+    #[must_use]
+    pub fn with_address(self, address: u8) -> Self {
+        self.state.borrow_mut().address = address;
+        self
+    }
+
+    /// Serial number reported by the device
+    #[must_use]
+    pub fn with_serial_number(self, serial_number: &'static str) -> Self {
+        self.state.borrow_mut().serial_number = serial_number;
+        self
+    }
+
+    /// Content of the device status register
+    #[must_use]
+    pub fn with_status_register(self, register: u32) -> Self {
+        self.state.borrow_mut().status_register = register;
+        self
+    }
+
+    /// Like a real device, read back the cleaning interval that was set
+    /// when the device last started until it is reset
+    #[must_use]
+    pub fn with_stale_cleaning_interval(self) -> Self {
+        let mut state = self.state.borrow_mut();
+        state.interval_since_reset = Some(state.cleaning_interval);
+        drop(state);
+        self
+    }
+
+    /// Number of times a read yields to the executor before each chunk of
+    /// a response becomes available
+    #[must_use]
+    pub fn with_latency(self, polls: u32) -> Self {
+        self.state.borrow_mut().latency = polls;
+        self
+    }
+
+    /// Reads wait for data, yielding to the executor, instead of returning
+    /// EOF when nothing was sent. Like a real uart, use a timeout.
+    #[must_use]
+    pub fn with_blocking_reads(self) -> Self {
+        self.state.borrow_mut().blocking_reads = true;
+        self
+    }
+
+    /// Number of reads answered without data after starting the
+    /// measurement, like a real device before its first sample is ready
+    #[must_use]
+    pub fn with_warmup(self, reads: u32) -> Self {
+        self.state.borrow_mut().warmup_reads = reads;
+        self
+    }
+
+    /// Maximum number of bytes made available per read, emulates a slow
+    /// or unbuffered uart
+    #[must_use]
+    pub fn with_chunk_size(self, bytes: usize) -> Self {
+        self.state.borrow_mut().chunk_size = bytes.max(1);
+        self
+    }
+
+    /// Change the measurement returned by subsequent reads
+    pub fn set_measurement(&self, measurement: Measurement) {
+        self.state.borrow_mut().measurement = [
+            measurement.mass_pm1_0,
+            measurement.mass_pm2_5,
+            measurement.mass_pm4_0,
+            measurement.mass_pm10,
+            measurement.mass_pm0_5,
+            measurement.number_pm1_0,
+            measurement.number_pm2_5,
+            measurement.number_pm4_0,
+            measurement.number_pm10,
+            measurement.typical_particle_size,
+        ];
+    }
+
+    /// Change the content of the device status register
+    pub fn set_status_register(&self, register: u32) {
+        self.state.borrow_mut().status_register = register;
+    }
+
+    /// The measurement returned by reads
+    #[must_use]
+    pub fn measurement(&self) -> Measurement {
+        let floats = self.state.borrow().measurement;
+        Measurement::from_floats(floats.into_iter()).expect("array has 10 floats")
+    }
+
+    /// Answer the next command with device error `state` (see the
+    /// datasheet for error codes)
+    pub fn fail_next(&self, state: u8) {
+        self.state.borrow_mut().fail_next = Some(state);
+    }
+
+    /// Drop the next command without answering, like a frame lost on the
+    /// line
+    pub fn ignore_next(&self) {
+        self.state.borrow_mut().ignore_next = true;
+    }
+
+    /// Whether the simulated device is in sleep mode
+    #[must_use]
+    pub fn is_sleeping(&self) -> bool {
+        self.state.borrow().sleeping
+    }
+
+    /// Whether the simulated device is in measurement mode
+    #[must_use]
+    pub fn is_measuring(&self) -> bool {
+        self.state.borrow().measuring
+    }
+
+    /// Number of valid commands the device received
+    #[must_use]
+    pub fn commands_received(&self) -> usize {
+        self.state.borrow().commands_received
+    }
+
+    fn receive(&self, mut bytes: &[u8]) {
+        {
+            let mut state = self.state.borrow_mut();
+            if !state.uart_awake {
+                let Some(wake) = bytes.iter().position(|b| *b == 0xFF) else {
+                    return;
+                };
+                bytes = &bytes[wake + 1..];
+                state.uart_awake = true;
+            }
+        }
+        while !bytes.is_empty() {
+            let decoded: Vec<u8, MAX_DECODED_FRAME_SIZE> = {
+                let mut state = self.state.borrow_mut();
+                match state.decoder.push(&mut bytes) {
+                    Some(Ok(frame)) => Vec::from_slice(frame).expect("decoder has same capacity"),
+                    Some(Err(_)) | None => continue, // noise or corrupt frame
+                }
+            };
+            self.handle_frame(&decoded);
+        }
+    }
+
+    fn handle_frame(&self, decoded: &[u8]) {
+        let [address, cmd, len, data @ .., check] = decoded else {
+            return;
+        };
+        if *len as usize != data.len() || *check != checksum(&decoded[..decoded.len() - 1]) {
+            return; // real device does not answer corrupt frames
+        }
+        if *address != self.state.borrow().address {
+            return; // meant for another device on the bus
+        }
+
+        if core::mem::take(&mut self.state.borrow_mut().ignore_next) {
+            return;
+        }
+        let mut payload: Vec<u8, MAX_DECODED_FRAME_SIZE> = Vec::new();
+        let state_byte = {
+            let mut state = self.state.borrow_mut();
+            state.commands_received += 1;
+            if let Some(state_byte) = state.fail_next.take() {
+                state_byte
+            } else {
+                state.execute(*cmd, data, &mut payload)
+            }
+        };
+
+        let mut response: Vec<u8, MAX_DECODED_FRAME_SIZE> = Vec::new();
+        #[allow(clippy::cast_possible_truncation)]
+        let header = [*address, *cmd, state_byte, payload.len() as u8];
+        response
+            .extend_from_slice(&header)
+            .expect("header fits frame");
+        response
+            .extend_from_slice(&payload)
+            .expect("payload fits frame");
+        response
+            .push(checksum(&response))
+            .expect("checksum fits frame");
+        let encoded =
+            shdlc::encode::<MAX_ENCODED_FRAME_SIZE>(&response).expect("response fits frame");
+
+        let mut state = self.state.borrow_mut();
+        let read = state.response_read;
+        state.response.rotate_left(read);
+        let unread = state.response.len() - read;
+        state.response.truncate(unread);
+        if state.response.extend_from_slice(&encoded).is_err() {
+            // uart buffer overflow, drop the older response
+            state.response.clear();
+            state
+                .response
+                .extend_from_slice(&encoded)
+                .expect("capacity fits frame");
+        }
+        state.response_read = 0;
+        state.pending_polls = state.latency;
+    }
+
+    async fn send(&self, buf: &mut [u8]) -> usize {
+        loop {
+            {
+                let mut state = self.state.borrow_mut();
+                let empty = state.response_read == state.response.len();
+                if state.pending_polls > 0 {
+                    state.pending_polls -= 1;
+                } else if !(empty && state.blocking_reads) {
+                    break;
+                }
+            }
+            yield_now().await;
+        }
+
+        let mut state = self.state.borrow_mut();
+        let remaining = &state.response[state.response_read..];
+        let n = remaining.len().min(buf.len()).min(state.chunk_size);
+        buf[..n].copy_from_slice(&remaining[..n]);
+        state.response_read += n;
+        if state.response_read < state.response.len() {
+            state.pending_polls = state.latency;
+        }
+        n
+    }
+}
+
+impl State {
+    /// Performs a command, returns the state byte for the response
+    fn execute(
+        &mut self,
+        cmd: u8,
+        data: &[u8],
+        payload: &mut Vec<u8, MAX_DECODED_FRAME_SIZE>,
+    ) -> u8 {
+        const START: u8 = Command::StartMeasurement as u8;
+        const STOP: u8 = Command::StopMeasurement as u8;
+        const READ: u8 = Command::ReadMeasuredData as u8;
+        const SLEEP: u8 = Command::Sleep as u8;
+        const WAKE_UP: u8 = Command::WakeUp as u8;
+        const CLEANING_INTERVAL: u8 = Command::ReadWriteAutoCleaningInterval as u8;
+        const FAN_CLEANING: u8 = Command::StartFanCleaning as u8;
+        const DEVICE_INFO: u8 = Command::DeviceInformation as u8;
+        const VERSION: u8 = Command::ReadVersion as u8;
+        const STATUS: u8 = Command::ReadDeviceStatusRegister as u8;
+        const RESET: u8 = Command::Reset as u8;
+        const PRODUCT_TYPE: u8 = InfoField::ProductType as u8;
+        const SERIAL_NUMBER: u8 = InfoField::SerialNumber as u8;
+
+        match (cmd, data) {
+            (WAKE_UP, _) if self.sleeping => {
+                self.sleeping = false;
+                0
+            }
+            (_, _) if self.sleeping => INVALID_STATE_FOR_COMMAND,
+            (WAKE_UP, _) => INVALID_STATE_FOR_COMMAND,
+            (SLEEP, _) if self.measuring => INVALID_STATE_FOR_COMMAND,
+            (SLEEP, _) => {
+                self.sleeping = true;
+                self.uart_awake = false;
+                0
+            }
+            (START, _) if self.measuring => INVALID_STATE_FOR_COMMAND,
+            (START, [_, format @ (0x03 | 0x05)]) => {
+                self.measuring = true;
+                self.format = *format;
+                self.empty_reads_left = self.warmup_reads;
+                0
+            }
+            (STOP, _) => {
+                self.measuring = false;
+                0
+            }
+            (READ, _) if !self.measuring => INVALID_STATE_FOR_COMMAND,
+            (READ, _) if self.empty_reads_left > 0 => {
+                self.empty_reads_left -= 1;
+                0 // no new measurement yet
+            }
+            (READ, _) if self.format == 0x05 => {
+                let (typical_size, concentrations) =
+                    self.measurement.split_last().expect("not empty");
+                #[allow(clippy::cast_possible_truncation)]
+                #[allow(clippy::cast_sign_loss)]
+                let integers = concentrations
+                    .iter()
+                    .map(|val| val.round() as u16)
+                    .chain([(typical_size * 1000.0).round() as u16]);
+                for integer in integers {
+                    payload
+                        .extend_from_slice(&integer.to_be_bytes())
+                        .expect("measurement fits frame");
+                }
+                0
+            }
+            (READ, _) => {
+                for float in self.measurement {
+                    payload
+                        .extend_from_slice(&float.to_be_bytes())
+                        .expect("measurement fits frame");
+                }
+                0
+            }
+            (CLEANING_INTERVAL, [0x00]) => {
+                let interval = self.interval_since_reset.unwrap_or(self.cleaning_interval);
+                payload
+                    .extend_from_slice(&interval.to_be_bytes())
+                    .expect("interval fits frame");
+                0
+            }
+            (CLEANING_INTERVAL, [_, a, b, c, d]) => {
+                self.cleaning_interval = u32::from_be_bytes([*a, *b, *c, *d]);
+                0
+            }
+            (FAN_CLEANING, _) if !self.measuring => INVALID_STATE_FOR_COMMAND,
+            (FAN_CLEANING, _) => 0,
+            (DEVICE_INFO, [PRODUCT_TYPE]) => {
+                payload
+                    .extend_from_slice(b"00080000\0")
+                    .expect("product type fits frame");
+                0
+            }
+            (DEVICE_INFO, [SERIAL_NUMBER]) => {
+                payload
+                    .extend_from_slice(self.serial_number.as_bytes())
+                    .expect("serial number fits frame");
+                0
+            }
+            (VERSION, []) => {
+                // firmware 2.2, hardware 7, SHDLC 2.0
+                payload
+                    .extend_from_slice(&[2, 2, 0, 7, 0, 2, 0])
+                    .expect("version fits frame");
+                0
+            }
+            (STATUS, [clear]) => {
+                payload
+                    .extend_from_slice(&self.status_register.to_be_bytes())
+                    .expect("register fits frame");
+                payload.push(0).expect("reserved byte fits frame");
+                if *clear != 0 {
+                    self.status_register = 0;
+                }
+                0
+            }
+            (RESET, _) => {
+                self.measuring = false;
+                if let Some(interval) = &mut self.interval_since_reset {
+                    *interval = self.cleaning_interval;
+                }
+                0
+            }
+            _ => UNKNOWN_CMD,
+        }
+    }
+}
+
+async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await;
+}
+
+impl ErrorType for MockSps30 {
+    type Error = Infallible;
+}
+
+impl ErrorType for &MockSps30 {
+    type Error = Infallible;
+}
+
+/// Reads return 0 (EOF) if the device has nothing to send.
+impl Read for &MockSps30 {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Ok(self.send(buf).await)
+    }
+}
+
+impl ReadReady for &MockSps30 {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        let state = self.state.borrow();
+        Ok(state.pending_polls == 0 && state.response_read < state.response.len())
+    }
+}
+
+impl Write for &MockSps30 {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.receive(buf);
+        Ok(buf.len())
+    }
+}
+
+impl Read for MockSps30 {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Read::read(&mut &*self, buf).await
+    }
+}
+
+impl ReadReady for MockSps30 {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        ReadReady::read_ready(&mut &*self)
+    }
+}
+
+impl Write for MockSps30 {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Write::write(&mut &*self, buf).await
+    }
+}
+
+#[cfg(feature = "nb")]
+impl embedded_hal_nb::serial::ErrorType for &MockSps30 {
+    type Error = Infallible;
+}
+
+#[cfg(feature = "nb")]
+impl embedded_hal_nb::serial::Read<u8> for &MockSps30 {
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        let mut state = self.state.borrow_mut();
+        if state.pending_polls > 0 {
+            state.pending_polls -= 1;
+            return Err(nb::Error::WouldBlock);
+        }
+        let byte = *state
+            .response
+            .get(state.response_read)
+            .ok_or(nb::Error::WouldBlock)?;
+        state.response_read += 1;
+        Ok(byte)
+    }
+}
+
+#[cfg(feature = "nb")]
+impl embedded_hal_nb::serial::Write<u8> for &MockSps30 {
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        self.receive(&[word]);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MockSps30, NoDelay};
+    use crate::{CleaningInterval, DeviceError, Error, ErrorKind, Sps30, Stats};
+    use futures::executor::block_on;
+
+    #[test]
+    fn init_and_read() {
+        let mock = MockSps30::new().with_latency(3).with_chunk_size(5);
+        let mut sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+        assert!(mock.is_measuring());
+
+        let measurement = block_on(sensor.read_measurement()).unwrap();
+        let expected = mock.measurement();
+        assert_eq!(measurement.mass_pm2_5, expected.mass_pm2_5);
+        assert_eq!(
+            measurement.typical_particle_size,
+            expected.typical_particle_size
+        );
+
+        let stats = sensor.stats();
+        assert_eq!(stats.frames_received, 3);
+        assert_eq!(stats.checksum_failures, 0);
+        assert!(stats.bytes_in > 40 && stats.bytes_out > 0);
+        sensor.reset_stats();
+        assert_eq!(sensor.stats(), Stats::default());
+    }
+
+    #[test]
+    fn cleaning_interval_roundtrip() {
+        let mock = MockSps30::new();
+        let mut sensor = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        // 0x7e and 0x7d need escaping
+        let interval = CleaningInterval::from_secs(0x7e7d_1113).unwrap();
+        block_on(sensor.write_cleaning_interval(interval)).unwrap();
+        assert_eq!(block_on(sensor.read_cleaning_interval()), Ok(interval));
+
+        block_on(sensor.write_cleaning_interval(CleaningInterval::DISABLED)).unwrap();
+        assert_eq!(block_on(sensor.read_cleaning_interval_duration()), Ok(None));
+    }
+
+    #[test]
+    fn device_errors() {
+        let mock = MockSps30::new();
+        let mut sensor = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        assert_eq!(
+            block_on(sensor.read_measurement()).unwrap_err(),
+            Error::Device(DeviceError::InvalidStateForCommand)
+        );
+
+        mock.fail_next(0x28);
+        let err = block_on(sensor.start_measurement()).unwrap_err();
+        assert_eq!(err, Error::Device(DeviceError::InternalOutOfRange));
+        assert_eq!(err.kind(), ErrorKind::Device);
+        assert!(!err.is_recoverable());
+
+        mock.fail_next(0x7f);
+        assert_eq!(
+            block_on(sensor.start_measurement()).unwrap_err(),
+            Error::Device(DeviceError::Unknown(0x7f))
+        );
+    }
+}
@@ -0,0 +1,343 @@
+//! Supervises a long running sensor by polling its status register, see
+//! [`StatusMonitor`].
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+use crate::shdlc::{FrameObserver, ProtocolLogger};
+use crate::{DeviceStatus, Error, MaybeFormat, Sps30};
+
+/// Polls between status checks unless configured otherwise
+pub const DEFAULT_PERIOD_MS: u32 = 60_000;
+/// Consecutive polls with a fan speed warning before it is reported,
+/// unless configured otherwise
+pub const DEFAULT_DEBOUNCE: u8 = 3;
+
+/// When to clean the fan automatically, see
+/// [`StatusMonitor::with_auto_clean`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct AutoClean {
+    /// Clean once a fan speed warning persisted this long
+    pub after_ms: u32,
+    /// Never clean more often than this, a fan that stays too slow after
+    /// cleaning needs a human
+    pub min_interval_ms: u32,
+}
+
+impl Default for AutoClean {
+    /// After 10 minutes of warnings, at most once a day
+    fn default() -> Self {
+        Self {
+            after_ms: 10 * 60 * 1000,
+            min_interval_ms: 24 * 60 * 60 * 1000,
+        }
+    }
+}
+
+/// Outcome of [`Sps30::recover_fan_failure`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct FanRecovery {
+    /// Status read after the reset and restarting the measurement
+    pub after_restart: DeviceStatus,
+    /// Status read after the fan cleaning, `None` if no cleaning was run
+    pub after_cleaning: Option<DeviceStatus>,
+}
+
+impl FanRecovery {
+    /// The status at the end of the recovery
+    #[must_use]
+    pub fn status(&self) -> DeviceStatus {
+        self.after_cleaning.unwrap_or(self.after_restart)
+    }
+
+    /// Whether the fan ended up running at the right speed
+    #[must_use]
+    pub fn recovered(&self) -> bool {
+        let status = self.status();
+        !status.fan_failure() && !status.fan_speed_warning()
+    }
+}
+
+/// Periodically reads (and clears) the device status register and tells
+/// `on_change` whenever the persistent faults change, including when they
+/// clear. Fan speed warnings are common during fan cleaning or after a
+/// bump, they are only reported once seen in several polls in a row.
+/// Laser and fan failures are reported right away.
+///
+/// To forward faults to a channel send from the callback, for example
+/// `|status| { let _ = sender.try_send(status); }`.
+///
+/// A persistent fan speed warning usually means dust slows the fan down,
+/// [`with_auto_clean`](Self::with_auto_clean) starts a fan cleaning when
+/// that happens.
+///
+/// Waits using the delay of the driver. The sensor must be awake, a
+/// sleeping sensor fails the check with
+/// [`Error::WrongDriverState`].
+/// Time is counted in periods: every check counts as one, also when
+/// [`check`](Self::check) is called directly. Call it once per period.
+pub struct StatusMonitor<F> {
+    period_ms: u32,
+    debounce: u8,
+    /// Polls in a row that had a fan speed warning
+    fan_speed_polls: u32,
+    /// Status last passed to `on_change`
+    reported: DeviceStatus,
+    on_change: F,
+    auto_clean: Option<AutoClean>,
+    /// Time since the last automatic cleaning, `None` if there was none
+    since_clean_ms: Option<u32>,
+    auto_cleanings: u32,
+}
+
+impl<F: FnMut(DeviceStatus)> StatusMonitor<F> {
+    /// Checks every [`DEFAULT_PERIOD_MS`] and reports changes to
+    /// `on_change`
+    pub fn new(on_change: F) -> Self {
+        Self {
+            period_ms: DEFAULT_PERIOD_MS,
+            debounce: DEFAULT_DEBOUNCE,
+            fan_speed_polls: 0,
+            reported: DeviceStatus::empty(),
+            on_change,
+            auto_clean: None,
+            since_clean_ms: None,
+            auto_cleanings: 0,
+        }
+    }
+
+    /// Wait `ms` between checks instead of [`DEFAULT_PERIOD_MS`]
+    #[must_use]
+    pub fn with_period(mut self, ms: u32) -> Self {
+        self.period_ms = ms;
+        self
+    }
+
+    /// Report a fan speed warning once seen in `polls` checks in a row
+    /// instead of [`DEFAULT_DEBOUNCE`]. Zero and one report it right away.
+    #[must_use]
+    pub fn with_debounce(mut self, polls: u8) -> Self {
+        self.debounce = polls;
+        self
+    }
+
+    /// Start a fan cleaning when a fan speed warning persists, as
+    /// configured by `auto_clean`. The sensor must be measuring for the
+    /// cleaning to start.
+    #[must_use]
+    pub fn with_auto_clean(mut self, auto_clean: AutoClean) -> Self {
+        self.auto_clean = Some(auto_clean);
+        self
+    }
+
+    /// Number of fan cleanings started automatically
+    pub fn auto_cleanings(&self) -> u32 {
+        self.auto_cleanings
+    }
+
+    /// The faults last reported, empty if the sensor is healthy
+    pub fn status(&self) -> DeviceStatus {
+        self.reported
+    }
+
+    /// Reads the status register once without waiting. Calls `on_change`
+    /// if the debounced status differs from the last one reported, then
+    /// returns it. Starts a fan cleaning if configured and due.
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    pub async fn check<Tx, Rx, D, O, L>(
+        &mut self,
+        sensor: &mut Sps30<Tx, Rx, D, O, L>,
+    ) -> Result<DeviceStatus, Error<Tx::Error, Rx::Error>>
+    where
+        Tx: Write,
+        Tx::Error: MaybeFormat,
+        Rx: Read,
+        Rx::Error: MaybeFormat,
+        D: DelayNs,
+        O: FrameObserver,
+        L: ProtocolLogger,
+    {
+        if let Some(ms) = &mut self.since_clean_ms {
+            *ms = ms.saturating_add(self.period_ms);
+        }
+        let read = sensor.read_device_status_register(true).await?;
+        let status = self.update(read);
+        if self.clean_due() {
+            debug!("fan speed warning persists, starting fan cleaning");
+            sensor.start_fan_cleaning().await?;
+            self.since_clean_ms = Some(0);
+            self.auto_cleanings = self.auto_cleanings.wrapping_add(1);
+        }
+        Ok(status)
+    }
+
+    /// Waits for the period then does a [`check`](Self::check)
+    ///
+    /// # Errors
+    /// See [`Self::check`]
+    pub async fn next<Tx, Rx, D, O, L>(
+        &mut self,
+        sensor: &mut Sps30<Tx, Rx, D, O, L>,
+    ) -> Result<DeviceStatus, Error<Tx::Error, Rx::Error>>
+    where
+        Tx: Write,
+        Tx::Error: MaybeFormat,
+        Rx: Read,
+        Rx::Error: MaybeFormat,
+        D: DelayNs,
+        O: FrameObserver,
+        L: ProtocolLogger,
+    {
+        sensor.delay().delay_ms(self.period_ms).await;
+        self.check(sensor).await
+    }
+
+    /// Checks every period forever. Failed checks are logged at debug
+    /// level using defmt, if the `defmt` feature is enabled, and otherwise
+    /// ignored.
+    pub async fn run<Tx, Rx, D, O, L>(&mut self, sensor: &mut Sps30<Tx, Rx, D, O, L>) -> !
+    where
+        Tx: Write,
+        Tx::Error: MaybeFormat,
+        Rx: Read,
+        Rx::Error: MaybeFormat,
+        D: DelayNs,
+        O: FrameObserver,
+        L: ProtocolLogger,
+    {
+        loop {
+            if let Err(e) = self.next(sensor).await {
+                debug!("could not check device status: {}", e);
+            }
+        }
+    }
+
+    fn update(&mut self, read: DeviceStatus) -> DeviceStatus {
+        let mut status = read & !DeviceStatus::FAN_SPEED_WARNING;
+        if read.fan_speed_warning() {
+            self.fan_speed_polls = self.fan_speed_polls.saturating_add(1);
+        } else {
+            self.fan_speed_polls = 0;
+        }
+        if self.fan_speed_polls >= u32::from(self.debounce) {
+            status |= DeviceStatus::FAN_SPEED_WARNING;
+        }
+
+        if status != self.reported {
+            self.reported = status;
+            (self.on_change)(status);
+        }
+        status
+    }
+
+    fn clean_due(&self) -> bool {
+        let Some(auto_clean) = self.auto_clean else {
+            return false;
+        };
+        let warned_ms = self.fan_speed_polls.saturating_mul(self.period_ms);
+        let rested = self
+            .since_clean_ms
+            .is_none_or(|ms| ms >= auto_clean.min_interval_ms);
+        self.fan_speed_polls > 0 && warned_ms >= auto_clean.after_ms && rested
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::cell::RefCell;
+
+    use futures::executor::block_on;
+
+    use super::{AutoClean, FanRecovery, StatusMonitor};
+    use crate::mock::{MockSps30, NoDelay};
+    use crate::{DeviceStatus, Sps30};
+
+    #[test]
+    fn debounces_fan_speed_warning() {
+        let mock = MockSps30::new();
+        let mut sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+        let reports = RefCell::new(Vec::new());
+        let mut monitor =
+            StatusMonitor::new(|status| reports.borrow_mut().push(status)).with_debounce(2);
+
+        let warning = DeviceStatus::FAN_SPEED_WARNING.bits();
+        mock.set_status_register(warning);
+        let status = block_on(monitor.next(&mut sensor)).unwrap();
+        assert!(status.is_empty(), "a single warning is transient");
+        mock.set_status_register(warning);
+        assert!(block_on(monitor.next(&mut sensor))
+            .unwrap()
+            .fan_speed_warning());
+        mock.set_status_register(warning);
+        block_on(monitor.next(&mut sensor)).unwrap();
+
+        mock.set_status_register(DeviceStatus::FAN_FAILURE.bits());
+        block_on(monitor.next(&mut sensor)).unwrap();
+        assert_eq!(
+            reports.into_inner(),
+            [DeviceStatus::FAN_SPEED_WARNING, DeviceStatus::FAN_FAILURE]
+        );
+    }
+
+    #[test]
+    fn cleans_dusty_fan() {
+        let mock = MockSps30::new();
+        let mut sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+        let mut monitor = StatusMonitor::new(|_| ())
+            .with_period(1_000)
+            .with_auto_clean(AutoClean {
+                after_ms: 2_000,
+                min_interval_ms: 3_000,
+            });
+
+        let mut cleanings = [0; 6];
+        for cleaned in &mut cleanings {
+            mock.set_status_register(DeviceStatus::FAN_SPEED_WARNING.bits());
+            block_on(monitor.next(&mut sensor)).unwrap();
+            *cleaned = monitor.auto_cleanings();
+        }
+        assert_eq!(cleanings, [0, 1, 1, 1, 2, 2]);
+
+        // driven by the caller instead of waiting in `next`
+        for cleaned in &mut cleanings {
+            mock.set_status_register(DeviceStatus::FAN_SPEED_WARNING.bits());
+            block_on(monitor.check(&mut sensor)).unwrap();
+            *cleaned = monitor.auto_cleanings();
+        }
+        assert_eq!(cleanings, [2, 3, 3, 3, 4, 4]);
+    }
+
+    #[test]
+    fn recovers_fan_failure() {
+        let mock = MockSps30::new();
+        let mut sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+
+        mock.set_status_register(DeviceStatus::FAN_FAILURE.bits());
+        let recovery = block_on(sensor.recover_fan_failure(false)).unwrap();
+        assert_eq!(
+            recovery,
+            FanRecovery {
+                after_restart: DeviceStatus::FAN_FAILURE,
+                after_cleaning: None,
+            }
+        );
+        assert!(!recovery.recovered());
+
+        mock.set_status_register(DeviceStatus::FAN_FAILURE.bits());
+        let recovery = block_on(sensor.recover_fan_failure(true)).unwrap();
+        assert_eq!(recovery.after_cleaning, Some(DeviceStatus::empty()));
+        assert!(recovery.recovered());
+
+        mock.set_status_register(0);
+        let recovery = block_on(sensor.recover_fan_failure(true)).unwrap();
+        assert_eq!(recovery.after_cleaning, None, "healthy fan is not cleaned");
+    }
+}
@@ -0,0 +1,286 @@
+//! Several sensors sharing one uart through an analog multiplexer, see
+//! [`MuxedSps30`].
+//!
+//! ```ignore
+//! struct GpioMux<'a>([Output<'a>; 2]);
+//!
+//! impl MuxControl for GpioMux<'_> {
+//!     async fn select(&mut self, channel: u8) {
+//!         for (bit, pin) in self.0.iter_mut().enumerate() {
+//!             pin.set_level(Level::from(channel & (1 << bit) != 0));
+//!         }
+//!     }
+//! }
+//!
+//! let sensor = Sps30::from_tx_rx_uninit(tx, rx, Delay);
+//! let mut sensors: MuxedSps30<_, _, _, _, 4> = MuxedSps30::new(sensor, GpioMux(pins));
+//! for channel in 0..4 {
+//!     sensors.channel(channel).await?.start_measurement().await?;
+//! }
+//! ```
+
+use core::future::Future;
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, ReadReady, Write};
+
+use crate::shdlc::{FrameObserver, ProtocolLogger};
+use crate::{Error, MaybeFormat, Mode, Sps30};
+
+/// Time waited after switching channels unless the [`MuxControl`] says
+/// otherwise
+pub const DEFAULT_SETTLE_US: u32 = 100;
+
+/// Switches the multiplexer routing the uart, usually a few GPIO pins
+/// driving the select inputs of an analog mux.
+pub trait MuxControl {
+    /// Routes the uart to the sensor on `channel`
+    fn select(&mut self, channel: u8) -> impl Future<Output = ()>;
+
+    /// Time the mux needs before the newly selected line carries clean
+    /// data
+    fn settle_us(&self) -> u32 {
+        DEFAULT_SETTLE_US
+    }
+}
+
+impl<M: MuxControl> MuxControl for &mut M {
+    fn select(&mut self, channel: u8) -> impl Future<Output = ()> {
+        M::select(self, channel)
+    }
+
+    fn settle_us(&self) -> u32 {
+        M::settle_us(self)
+    }
+}
+
+/// One driver serving up to `N` sensors behind a multiplexer. Select a
+/// sensor with [`channel`](Self::channel), then send it commands through
+/// the returned driver.
+///
+/// Switching channels waits for the mux to settle and discards anything
+/// received so a byte from the previous sensor, or a glitch caused by
+/// the switch, is never mistaken for a response. A request to the previous
+/// sensor that was cancelled is forgotten. The driver remembers the
+/// [mode](Sps30::current_mode) of each sensor separately and forgets the
+/// [identity](Sps30::identity) on every switch. Everything else, like the
+/// calibration, [stats](Sps30::stats) and address, is shared.
+pub struct MuxedSps30<Tx, Rx, D, M, const N: usize, O = (), L = ()> {
+    sensor: Sps30<Tx, Rx, D, O, L>,
+    mux: M,
+    /// The channel the mux is switched to, `None` before the first switch
+    selected: Option<u8>,
+    /// Mode of every sensor not currently selected
+    modes: [Option<Mode>; N],
+}
+
+impl<Tx, Rx, D, M, const N: usize, O, L> MuxedSps30<Tx, Rx, D, M, N, O, L>
+where
+    Tx: Write,
+    Tx::Error: MaybeFormat,
+    Rx: Read + ReadReady,
+    Rx::Error: MaybeFormat,
+    D: DelayNs,
+    M: MuxControl,
+    O: FrameObserver,
+    L: ProtocolLogger,
+{
+    /// Does not communicate with the sensors or switch the mux. Create
+    /// `sensor` using [`Sps30::from_tx_rx_uninit`] then initialize every
+    /// channel.
+    pub fn new(sensor: Sps30<Tx, Rx, D, O, L>, mux: M) -> Self {
+        Self {
+            sensor,
+            mux,
+            selected: None,
+            modes: [None; N],
+        }
+    }
+
+    /// Switches the mux to `channel`, if it is not already, and returns
+    /// the driver to talk to the sensor there.
+    ///
+    /// # Errors
+    /// Returns [`TransportError::Read`](crate::TransportError::Read) if
+    /// discarding the received bytes fails. The mux is switched regardless.
+    ///
+    /// # Panics
+    /// If `channel` is `N` or larger
+    pub async fn channel(
+        &mut self,
+        channel: u8,
+    ) -> Result<&mut Sps30<Tx, Rx, D, O, L>, Error<Tx::Error, Rx::Error>> {
+        assert!(
+            usize::from(channel) < N,
+            "channel {channel} does not exist, the mux has {N}"
+        );
+        if self.selected == Some(channel) {
+            return Ok(&mut self.sensor);
+        }
+
+        if let Some(previous) = self.selected {
+            self.modes[usize::from(previous)] = self.sensor.mode;
+        }
+        self.sensor.mode = self.modes[usize::from(channel)];
+        // belongs to the previous sensor
+        self.sensor.identity = None;
+        self.selected = Some(channel);
+
+        // a response to a cancelled request will not come from this sensor
+        self.sensor.device.forget_pending();
+
+        debug!("switching mux to channel {}", channel);
+        self.mux.select(channel).await;
+        let settle_us = self.mux.settle_us();
+        self.sensor.delay().delay_us(settle_us).await;
+        self.sensor.flush_rx().await?;
+        Ok(&mut self.sensor)
+    }
+
+    /// The channel the mux is switched to, `None` before the first call to
+    /// [`Self::channel`]
+    #[must_use]
+    pub fn selected(&self) -> Option<u8> {
+        self.selected
+    }
+
+    /// The multiplexer, changing its channel without going through
+    /// [`Self::channel`] confuses the driver
+    pub fn mux(&mut self) -> &mut M {
+        &mut self.mux
+    }
+
+    /// Returns the driver and the mux
+    pub fn into_parts(self) -> (Sps30<Tx, Rx, D, O, L>, M) {
+        (self.sensor, self.mux)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::cell::Cell;
+    use core::convert::Infallible;
+    use core::future::Future;
+    use core::task::Context;
+
+    use embedded_io_async::{ErrorType, Read, ReadReady, Write};
+    use futures::executor::block_on;
+    use futures::pin_mut;
+
+    use super::{MuxControl, MuxedSps30};
+    use crate::mock::{MockSps30, NoDelay};
+    use crate::protocol::Request;
+    use crate::shdlc::encode_to;
+    use crate::{Mode, Sps30};
+
+    /// Uart routed to one of the mocks, and the mux switching it
+    #[derive(Clone, Copy)]
+    struct Mux<'a> {
+        mocks: &'a [MockSps30; 2],
+        channel: &'a Cell<u8>,
+    }
+
+    impl Mux<'_> {
+        fn selected(&self) -> &MockSps30 {
+            &self.mocks[usize::from(self.channel.get())]
+        }
+    }
+
+    impl MuxControl for Mux<'_> {
+        async fn select(&mut self, channel: u8) {
+            self.channel.set(channel);
+        }
+    }
+
+    impl ErrorType for Mux<'_> {
+        type Error = Infallible;
+    }
+
+    impl Read for Mux<'_> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            self.selected().read(buf).await
+        }
+    }
+
+    impl ReadReady for Mux<'_> {
+        fn read_ready(&mut self) -> Result<bool, Self::Error> {
+            self.selected().read_ready()
+        }
+    }
+
+    impl Write for Mux<'_> {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.selected().write(buf).await
+        }
+    }
+
+    #[test]
+    fn switches_between_sensors() {
+        let mocks = [MockSps30::new(), MockSps30::new()];
+        let mut second = mocks[1].measurement();
+        second.mass_pm10 += 1.0;
+        mocks[1].set_measurement(second);
+        let mux = Mux {
+            mocks: &mocks,
+            channel: &Cell::new(0),
+        };
+        let sensor = Sps30::from_tx_rx_uninit(mux, mux, NoDelay);
+        let mut sensors: MuxedSps30<_, _, _, _, 2> = MuxedSps30::new(sensor, mux);
+
+        let first = block_on(sensors.channel(0)).unwrap();
+        block_on(first.start_measurement()).unwrap();
+        assert!(mocks[0].is_measuring() && !mocks[1].is_measuring());
+
+        // a response nobody asked for, left on the second line
+        let mut uart = &mocks[1];
+        block_on(encode_to(Request::read_measurement(0).frame(), &mut uart)).unwrap();
+        let second_sensor = block_on(sensors.channel(1)).unwrap();
+        assert_eq!(second_sensor.current_mode(), None);
+        block_on(second_sensor.start_measurement()).unwrap();
+        assert_eq!(block_on(second_sensor.read_measurement()).unwrap(), second);
+
+        let first = block_on(sensors.channel(0)).unwrap();
+        assert_eq!(first.current_mode(), Some(Mode::Measurement));
+        assert_eq!(
+            block_on(first.read_measurement()).unwrap(),
+            mocks[0].measurement()
+        );
+        assert_eq!(sensors.selected(), Some(0));
+    }
+
+    #[test]
+    fn forgets_cancelled_request() {
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mocks = [MockSps30::new().with_latency(1), MockSps30::new()];
+        let mux = Mux {
+            mocks: &mocks,
+            channel: &Cell::new(0),
+        };
+        let sensor = Sps30::from_tx_rx_uninit(mux, mux, NoDelay);
+        let mut sensors: MuxedSps30<_, _, _, _, 2> = MuxedSps30::new(sensor, mux);
+        for channel in 0..2 {
+            let sensor = block_on(sensors.channel(channel)).unwrap();
+            block_on(sensor.start_measurement()).unwrap();
+        }
+
+        // sent to the first sensor but cancelled before the response arrived
+        let first = block_on(sensors.channel(0)).unwrap();
+        {
+            let read = first.read_measurement();
+            pin_mut!(read);
+            assert!(read.as_mut().poll(&mut cx).is_pending());
+        }
+
+        let second = block_on(sensors.channel(1)).unwrap();
+        assert_eq!(
+            block_on(second.read_measurement()).unwrap(),
+            mocks[1].measurement()
+        );
+        let first = block_on(sensors.channel(0)).unwrap();
+        assert_eq!(
+            block_on(first.read_measurement()).unwrap(),
+            mocks[0].measurement()
+        );
+    }
+}
@@ -0,0 +1,284 @@
+//! Driver for firmware without an async executor, for example RTIC apps or
+//! a superloop. Built on [`embedded_hal_nb`] serial ports and the
+//! [`protocol`](crate::protocol) core: start a request then call
+//! [`poll`](Sps30::poll) until it no longer returns
+//! [`WouldBlock`](nb::Error::WouldBlock).
+//!
+//! ```ignore
+//! let mut sensor = polling::Sps30::new(serial);
+//! nb::block!(sensor.start_measurement())?;
+//! loop {
+//!     // somewhere in the superloop
+//!     match sensor.read_measurement() {
+//!         Ok(measurement) => radio.send(&measurement),
+//!         Err(nb::Error::WouldBlock) => (),
+//!         Err(nb::Error::Other(e)) => log_error(e),
+//!     }
+//! }
+//! ```
+
+use core::fmt;
+
+use embedded_hal_nb::serial::{Read, Write};
+
+use crate::protocol::{Request, ResponseReader};
+use crate::shdlc::{Response, DEFAULT_ADDRESS, DEFAULT_RESYNC_LIMIT};
+use crate::{
+    Command, Error, MaybeFormat, Measurement, MeasurementFormat, ProtocolError, TransportError,
+};
+
+/// Progress of the current request
+#[derive(Debug)]
+enum State {
+    Idle,
+    Sending {
+        request: Request,
+        written: usize,
+    },
+    Flushing(ResponseReader),
+    Receiving {
+        reader: ResponseReader,
+        scanned: usize,
+    },
+}
+
+/// Sps30 driver that never blocks, see the [module](self) documentation.
+///
+/// One request is in progress at a time. Starting another one abandons the
+/// current request, its response is skipped.
+pub struct Sps30<S> {
+    serial: S,
+    address: u8,
+    format: MeasurementFormat,
+    resync_limit: usize,
+    state: State,
+    /// The command of the current request, if any
+    pending: Option<u8>,
+    /// An abandoned request for the same command was sent, its response
+    /// must not answer the current one
+    skip_stale: bool,
+}
+
+impl<S> Sps30<S>
+where
+    S: Read<u8> + Write<u8>,
+    S::Error: MaybeFormat,
+{
+    /// Does not communicate with the device, start the measurement with
+    /// [`Self::start_measurement`]. Uses the float measurement format.
+    pub fn new(serial: S) -> Self {
+        Self {
+            serial,
+            address: DEFAULT_ADDRESS,
+            format: MeasurementFormat::Float,
+            resync_limit: DEFAULT_RESYNC_LIMIT,
+            state: State::Idle,
+            pending: None,
+            skip_stale: false,
+        }
+    }
+
+    /// Address the sensor at `address` instead of the default (0)
+    #[must_use]
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Returns the serial port, dropping the driver
+    pub fn release(self) -> S {
+        self.serial
+    }
+
+    /// Starts sending command `cmd` with `data`, abandoning any request in
+    /// progress. Call [`Self::poll`] to make progress.
+    ///
+    /// # Errors
+    /// Returns [`ProtocolError::FrameTooLarge`] if `data` does not fit a
+    /// request.
+    pub fn start(&mut self, cmd: u8, data: &[u8]) -> Result<(), Error<S::Error, S::Error>> {
+        let request = Request::new(self.address, cmd, data)
+            .map_err(|_| Error::Protocol(ProtocolError::FrameTooLarge))?;
+        let sent = matches!(self.state, State::Flushing(_) | State::Receiving { .. });
+        self.skip_stale = sent && self.pending == Some(cmd);
+        self.state = State::Sending {
+            request,
+            written: 0,
+        };
+        self.pending = Some(cmd);
+        Ok(())
+    }
+
+    /// Sends and receives as many bytes as the serial port accepts and has
+    /// available. Returns the response once it is complete.
+    ///
+    /// # Errors
+    /// Returns [`nb::Error::WouldBlock`] while the request is in progress.
+    /// Fails with [`ProtocolError::EmptyResult`] if no request was started. See
+    /// [`ShdlcDevice::execute`](crate::shdlc::ShdlcDevice::execute) for
+    /// the other errors.
+    pub fn poll(&mut self) -> nb::Result<Response, Error<S::Error, S::Error>> {
+        let result = self.advance();
+        if !matches!(result, Err(nb::Error::WouldBlock)) {
+            self.state = State::Idle;
+            self.pending = None;
+        }
+        result
+    }
+
+    fn advance(&mut self) -> nb::Result<Response, Error<S::Error, S::Error>> {
+        loop {
+            match &mut self.state {
+                State::Idle => {
+                    return Err(nb::Error::Other(Error::Protocol(
+                        ProtocolError::EmptyResult,
+                    )))
+                }
+                State::Sending { request, written } => {
+                    while let Some(byte) = request.bytes().nth(*written) {
+                        self.serial.write(byte).map_err(serial_w)?;
+                        *written += 1;
+                    }
+                    trace!("request sent");
+                    let mut reader = ResponseReader::new(request);
+                    if core::mem::take(&mut self.skip_stale) {
+                        debug!("previous request was abandoned, skipping its response");
+                        reader.skip_next();
+                    }
+                    self.state = State::Flushing(reader);
+                }
+                State::Flushing(reader) => {
+                    self.serial.flush().map_err(serial_w)?;
+                    self.state = State::Receiving {
+                        reader: reader.clone(),
+                        scanned: 0,
+                    };
+                }
+                State::Receiving { reader, scanned } => loop {
+                    let byte = self.serial.read().map_err(serial_r)?;
+                    *scanned += 1;
+                    if let Some(response) = reader.feed(&mut &[byte][..]) {
+                        return response.map_err(nb::Error::Other);
+                    }
+                    if *scanned > self.resync_limit {
+                        debug!("no frame found in {} bytes, giving up", scanned);
+                        return Err(nb::Error::Other(Error::Protocol(
+                            ProtocolError::InvalidFrame,
+                        )));
+                    }
+                },
+            }
+        }
+    }
+
+    /// Starts `cmd` unless it is already in progress, then polls it
+    fn request(
+        &mut self,
+        cmd: Command,
+        data: &[u8],
+    ) -> nb::Result<Response, Error<S::Error, S::Error>> {
+        let cmd = cmd as u8;
+        if self.pending != Some(cmd) {
+            self.start(cmd, data)?;
+        }
+        self.poll()
+    }
+
+    /// Starts the measurement, see
+    /// [`crate::Sps30::start_measurement`]. Call again until it no longer
+    /// returns [`nb::Error::WouldBlock`].
+    ///
+    /// # Errors
+    /// See [`Self::poll`]
+    pub fn start_measurement(&mut self) -> nb::Result<(), Error<S::Error, S::Error>> {
+        const SUBCMD: u8 = 0x01;
+        let format = self.format.code();
+        self.request(Command::StartMeasurement, &[SUBCMD, format])
+            .map(|_| ())
+    }
+
+    /// Stops measuring, see [`crate::Sps30::stop_measurement`]. Call again
+    /// until it no longer returns [`nb::Error::WouldBlock`].
+    ///
+    /// # Errors
+    /// See [`Self::poll`]
+    pub fn stop_measurement(&mut self) -> nb::Result<(), Error<S::Error, S::Error>> {
+        self.request(Command::StopMeasurement, &[]).map(|_| ())
+    }
+
+    /// Reads a measurement, see [`crate::Sps30::read_measurement`]. Call
+    /// again until it no longer returns [`nb::Error::WouldBlock`].
+    ///
+    /// # Errors
+    /// See [`Self::poll`], returns [`ProtocolError::EmptyResult`] if no new
+    /// measurement was available.
+    pub fn read_measurement(&mut self) -> nb::Result<Measurement, Error<S::Error, S::Error>> {
+        let response = self.request(Command::ReadMeasuredData, &[])?;
+        if response.data().is_empty() {
+            return Err(nb::Error::Other(Error::Protocol(
+                ProtocolError::EmptyResult,
+            )));
+        }
+        Measurement::from_data(response.data(), self.format)
+            .map_err(|_| nb::Error::Other(Error::Protocol(ProtocolError::MeasurementDataTooShort)))
+    }
+}
+
+fn serial_w<E: MaybeFormat + fmt::Debug>(error: nb::Error<E>) -> nb::Error<Error<E, E>> {
+    error.map(|e| Error::Transport(TransportError::Write(e)))
+}
+
+fn serial_r<E: MaybeFormat + fmt::Debug>(error: nb::Error<E>) -> nb::Error<Error<E, E>> {
+    error.map(|e| Error::Transport(TransportError::Read(e)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::Sps30;
+    use crate::mock::MockSps30;
+    use crate::{Command, Error, ProtocolError};
+
+    #[test]
+    fn poll_until_done() {
+        let mock = MockSps30::new().with_latency(2);
+        let mut sensor = Sps30::new(&mock);
+        assert_eq!(
+            sensor.poll(),
+            Err(nb::Error::Other(Error::Protocol(
+                ProtocolError::EmptyResult
+            )))
+        );
+
+        nb::block!(sensor.start_measurement()).unwrap();
+        assert!(mock.is_measuring());
+
+        let mut would_block = 0;
+        let measurement = loop {
+            match sensor.read_measurement() {
+                Err(nb::Error::WouldBlock) => would_block += 1,
+                result => break result.unwrap(),
+            }
+        };
+        assert!(would_block > 0);
+        assert_eq!(measurement, mock.measurement());
+        assert_eq!(mock.commands_received(), 2);
+    }
+
+    #[test]
+    fn skips_abandoned_response() {
+        let mock = MockSps30::new().with_latency(2);
+        let mut sensor = Sps30::new(&mock);
+        nb::block!(sensor.start_measurement()).unwrap();
+
+        // sent but not answered yet
+        assert_eq!(sensor.read_measurement(), Err(nb::Error::WouldBlock));
+        sensor.start(Command::ReadMeasuredData as u8, &[]).unwrap();
+        let mut changed = mock.measurement();
+        changed.mass_pm10 += 1.0;
+        mock.set_measurement(changed);
+
+        let measurement = nb::block!(sensor.read_measurement()).unwrap();
+        assert_eq!(measurement, changed);
+        assert_eq!(mock.commands_received(), 3);
+    }
+}
@@ -0,0 +1,211 @@
+//! Several sensors, each on its own uart, driven together, see
+//! [`Sps30Pool`].
+
+use embassy_futures::join::join_array;
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+use crate::shdlc::{FrameObserver, ProtocolLogger};
+use crate::{Error, ErrorKind, MaybeFormat, Measurement, Sps30};
+
+/// Index of a sensor in an [`Sps30Pool`], the position it had in the
+/// array passed to [`Sps30Pool::new`]
+pub type SensorId = usize;
+
+/// The outcome of reading one sensor in an [`Sps30Pool`]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Reading<TxError, RxError>
+where
+    TxError: MaybeFormat + core::fmt::Debug,
+    RxError: MaybeFormat + core::fmt::Debug,
+{
+    /// The sensor that was read
+    pub sensor: SensorId,
+    pub result: Result<Measurement, Error<TxError, RxError>>,
+}
+
+/// Error state of one sensor in an [`Sps30Pool`]. All counters wrap around
+/// on overflow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SensorHealth {
+    /// Successful reads
+    pub reads: u32,
+    /// Failed reads
+    pub errors: u32,
+    /// Reads that failed since the last successful one
+    pub consecutive_errors: u32,
+    /// Category of the most recent error, cleared by a successful read
+    pub last_error: Option<ErrorKind>,
+}
+
+impl SensorHealth {
+    /// Whether the last read succeeded, also true before the first read
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.consecutive_errors == 0
+    }
+
+    fn record<TxError, RxError>(&mut self, result: &Result<Measurement, Error<TxError, RxError>>)
+    where
+        TxError: MaybeFormat + core::fmt::Debug,
+        RxError: MaybeFormat + core::fmt::Debug,
+    {
+        match result {
+            Ok(_) => {
+                self.reads = self.reads.wrapping_add(1);
+                self.consecutive_errors = 0;
+                self.last_error = None;
+            }
+            Err(e) => {
+                self.errors = self.errors.wrapping_add(1);
+                self.consecutive_errors = self.consecutive_errors.wrapping_add(1);
+                self.last_error = Some(e.kind());
+            }
+        }
+    }
+}
+
+/// Owns `N` sensors, for example one per room on a gateway, and reads
+/// them one at a time ([`poll_next`](Self::poll_next)) or all at once
+/// ([`poll_all`](Self::poll_all)). Readings are tagged with the
+/// [`SensorId`] of their sensor and every read updates the
+/// [`SensorHealth`] of that sensor.
+///
+/// The sensors must be of the same type and measuring, as they are after
+/// [`Sps30::from_tx_rx`]. A sensor can be reached for other commands through
+/// [`sensor`](Self::sensor).
+pub struct Sps30Pool<Tx, Rx, D, const N: usize, O = (), L = ()> {
+    sensors: [Sps30<Tx, Rx, D, O, L>; N],
+    health: [SensorHealth; N],
+    /// Sensor read by the next call to `poll_next`
+    next: SensorId,
+}
+
+impl<Tx, Rx, D, const N: usize, O, L> Sps30Pool<Tx, Rx, D, N, O, L>
+where
+    Tx: Write,
+    Tx::Error: MaybeFormat,
+    Rx: Read,
+    Rx::Error: MaybeFormat,
+    D: DelayNs,
+    O: FrameObserver,
+    L: ProtocolLogger,
+{
+    /// Does not communicate with the sensors. Sensor `i` gets
+    /// [`SensorId`] `i`.
+    pub fn new(sensors: [Sps30<Tx, Rx, D, O, L>; N]) -> Self {
+        Self {
+            sensors,
+            health: [SensorHealth::default(); N],
+            next: 0,
+        }
+    }
+
+    /// The sensor with id `sensor`, `None` if there is no such sensor
+    pub fn sensor(&mut self, sensor: SensorId) -> Option<&mut Sps30<Tx, Rx, D, O, L>> {
+        self.sensors.get_mut(sensor)
+    }
+
+    /// Error state of the sensor with id `sensor`, `None` if there is no
+    /// such sensor
+    #[must_use]
+    pub fn health(&self, sensor: SensorId) -> Option<&SensorHealth> {
+        self.health.get(sensor)
+    }
+
+    /// Error state of every sensor, indexed by [`SensorId`]
+    #[must_use]
+    pub fn healths(&self) -> &[SensorHealth; N] {
+        &self.health
+    }
+
+    /// Ids of the sensors whose last read failed
+    pub fn failing(&self) -> impl Iterator<Item = SensorId> + '_ {
+        self.health
+            .iter()
+            .enumerate()
+            .filter(|(_, health)| !health.is_ok())
+            .map(|(id, _)| id)
+    }
+
+    /// Returns the sensors, in the order passed to [`Self::new`]
+    pub fn into_sensors(self) -> [Sps30<Tx, Rx, D, O, L>; N] {
+        self.sensors
+    }
+
+    /// Reads a measurement from the next sensor, going round-robin
+    /// through the pool. A failed read is returned in the reading, the
+    /// next call moves on to the following sensor.
+    ///
+    /// # Panics
+    /// If the pool is empty (`N` is zero)
+    pub async fn poll_next(&mut self) -> Reading<Tx::Error, Rx::Error> {
+        let sensor = self.next;
+        self.next = (self.next + 1) % N;
+        let result = self.sensors[sensor].read_measurement().await;
+        self.health[sensor].record(&result);
+        Reading { sensor, result }
+    }
+
+    /// Reads a measurement from every sensor concurrently. Useful when
+    /// each sensor has its own uart, the reads then take as long as the
+    /// slowest sensor instead of the sum of all.
+    pub async fn poll_all(&mut self) -> [Reading<Tx::Error, Rx::Error>; N] {
+        let reads = self.sensors.each_mut().map(Sps30::read_measurement);
+        let results = join_array(reads).await;
+
+        let mut sensor = 0;
+        results.map(|result| {
+            self.health[sensor].record(&result);
+            let reading = Reading { sensor, result };
+            sensor += 1;
+            reading
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+
+    use super::{SensorHealth, Sps30Pool};
+    use crate::mock::{MockSps30, NoDelay};
+    use crate::{ErrorKind, Sps30};
+
+    #[test]
+    fn tracks_health_per_sensor() {
+        let mocks = [MockSps30::new(), MockSps30::new(), MockSps30::new()];
+        let sensors = mocks
+            .each_ref()
+            .map(|mock| block_on(Sps30::from_tx_rx(mock, mock, NoDelay)).unwrap());
+        let mut pool = Sps30Pool::new(sensors);
+
+        mocks[1].fail_next(0x43);
+        let ids: Vec<_> = (0..4)
+            .map(|_| block_on(pool.poll_next()))
+            .map(|reading| (reading.sensor, reading.result.is_ok()))
+            .collect();
+        assert_eq!(ids, [(0, true), (1, false), (2, true), (0, true)]);
+        assert_eq!(pool.failing().collect::<Vec<_>>(), [1]);
+        assert_eq!(
+            pool.health(1),
+            Some(&SensorHealth {
+                reads: 0,
+                errors: 1,
+                consecutive_errors: 1,
+                last_error: Some(ErrorKind::Device),
+            })
+        );
+
+        mocks[2].fail_next(0x43);
+        let readings = block_on(pool.poll_all());
+        assert!(readings[0].result.is_ok() && readings[1].result.is_ok());
+        assert!(readings[2].result.is_err());
+        assert_eq!(readings.map(|reading| reading.sensor), [0, 1, 2]);
+        assert_eq!(pool.failing().collect::<Vec<_>>(), [2]);
+        assert_eq!(pool.healths().map(|health| health.reads), [3, 1, 1]);
+    }
+}
@@ -0,0 +1,277 @@
+//! Estimate the charge used by the sensor, for sizing a battery in the
+//! field. Set a [`PowerBudget`] as the logger of the driver, it is told
+//! every change of the [mode](crate::Sps30::current_mode), also those
+//! in the middle of a [`LowRateSampler`](crate::schedule::LowRateSampler)
+//! cycle:
+//!
+//! ```ignore
+//! let mut sensor = sensor.with_logger(PowerBudget::new(|| Instant::now().as_millis()));
+//! loop {
+//!     let measurement = sampler.next(&mut sensor).await?;
+//!     info!("used {} mAh so far", sensor.logger_ref().consumed_mah());
+//! }
+//! ```
+
+use core::fmt;
+
+use crate::shdlc::ProtocolLogger;
+use crate::watchdog::WatchdogEvent;
+use crate::{Error, MaybeFormat, Mode};
+
+/// Monotonic time source in milliseconds, for example an RTC or
+/// `embassy_time::Instant`. Implemented for closures returning the time.
+pub trait Clock {
+    fn now_ms(&self) -> u64;
+}
+
+impl<F: Fn() -> u64> Clock for F {
+    fn now_ms(&self) -> u64 {
+        self()
+    }
+}
+
+/// Supply current in each mode in µA
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Currents {
+    pub measurement_ua: u32,
+    pub idle_ua: u32,
+    pub sleep_ua: u32,
+}
+
+impl Currents {
+    /// Typical values from the datasheet at 5V
+    pub const TYPICAL: Self = Self {
+        measurement_ua: 55_000,
+        idle_ua: 330,
+        sleep_ua: 38,
+    };
+
+    fn of(&self, mode: Mode) -> u32 {
+        match mode {
+            Mode::Measurement => self.measurement_ua,
+            Mode::Idle => self.idle_ua,
+            Mode::Sleep => self.sleep_ua,
+        }
+    }
+}
+
+/// Time spent in each mode and the charge that used, see the
+/// [module](self) documentation. Passes everything logged on to another
+/// logger, set with [`with_logger`](Self::with_logger).
+#[derive(Debug, Clone)]
+pub struct PowerBudget<C, L = ()> {
+    clock: C,
+    inner: L,
+    currents: Currents,
+    mode: Option<Mode>,
+    since_ms: u64,
+    /// Indexed by [`index`]
+    time_ms: [u64; 4],
+}
+
+impl<C: Clock> PowerBudget<C> {
+    /// Starts tracking now, in an unknown mode. Uses [`Currents::TYPICAL`].
+    pub fn new(clock: C) -> Self {
+        let since_ms = clock.now_ms();
+        Self {
+            clock,
+            inner: (),
+            currents: Currents::TYPICAL,
+            mode: None,
+            since_ms,
+            time_ms: [0; 4],
+        }
+    }
+}
+
+impl<C: Clock, L: ProtocolLogger> PowerBudget<C, L> {
+    /// Pass everything logged on to `logger`
+    pub fn with_logger<L2: ProtocolLogger>(self, logger: L2) -> PowerBudget<C, L2> {
+        PowerBudget {
+            clock: self.clock,
+            inner: logger,
+            currents: self.currents,
+            mode: self.mode,
+            since_ms: self.since_ms,
+            time_ms: self.time_ms,
+        }
+    }
+
+    /// The logger everything is passed on to
+    pub fn logger(&mut self) -> &mut L {
+        &mut self.inner
+    }
+
+    /// Use measured currents instead of the datasheet values
+    #[must_use]
+    pub fn with_currents(mut self, currents: Currents) -> Self {
+        self.currents = currents;
+        self
+    }
+
+    /// Adds the time since the last update to the previous mode then
+    /// switches to `mode`. Only needed if the budget is not the logger of
+    /// the driver, then pass [`Sps30::current_mode`](crate::Sps30::current_mode)
+    /// after every command that can change it.
+    pub fn update(&mut self, mode: Option<Mode>) {
+        let now_ms = self.clock.now_ms();
+        self.time_ms[index(self.mode)] += now_ms.saturating_sub(self.since_ms);
+        self.since_ms = now_ms;
+        self.mode = mode;
+    }
+
+    /// Milliseconds spent in `mode` so far
+    pub fn time_in_ms(&self, mode: Mode) -> u64 {
+        self.time_of(Some(mode))
+    }
+
+    /// Milliseconds the mode was unknown so far
+    pub fn unknown_ms(&self) -> u64 {
+        self.time_of(None)
+    }
+
+    fn time_of(&self, mode: Option<Mode>) -> u64 {
+        let mut time_ms = self.time_ms[index(mode)];
+        if mode == self.mode {
+            time_ms += self.clock.now_ms().saturating_sub(self.since_ms);
+        }
+        time_ms
+    }
+
+    /// Estimated charge used so far in mAh. Time in an unknown mode is
+    /// counted as measuring, the worst case.
+    #[allow(clippy::cast_precision_loss)] // a rough estimate anyway
+    pub fn consumed_mah(&self) -> f32 {
+        let ua_ms: u64 = [Mode::Measurement, Mode::Idle, Mode::Sleep]
+            .into_iter()
+            .map(|mode| u64::from(self.currents.of(mode)) * self.time_in_ms(mode))
+            .sum::<u64>()
+            + u64::from(self.currents.measurement_ua) * self.unknown_ms();
+        // µA·ms to mAh
+        ua_ms as f32 / 3_600_000_000.0
+    }
+
+    /// Start over from zero, keeps the current mode
+    pub fn reset(&mut self) {
+        self.time_ms = [0; 4];
+        self.since_ms = self.clock.now_ms();
+    }
+}
+
+impl<C: Clock, L: ProtocolLogger> ProtocolLogger for PowerBudget<C, L> {
+    fn on_command(&mut self, address: u8, command: u8, data: &[u8]) {
+        self.inner.on_command(address, command, data);
+    }
+
+    fn on_response(&mut self, command: u8, data: &[u8]) {
+        self.inner.on_response(command, data);
+    }
+
+    fn on_resync(&mut self, command: u8) {
+        self.inner.on_resync(command);
+    }
+
+    fn on_error<TxError, RxError>(&mut self, command: u8, error: &Error<TxError, RxError>)
+    where
+        TxError: MaybeFormat + fmt::Debug,
+        RxError: MaybeFormat + fmt::Debug,
+    {
+        self.inner.on_error(command, error);
+    }
+
+    fn on_watchdog(&mut self, event: WatchdogEvent) {
+        self.inner.on_watchdog(event);
+    }
+
+    fn on_mode_change(&mut self, mode: Option<Mode>) {
+        self.update(mode);
+        self.inner.on_mode_change(mode);
+    }
+}
+
+fn index(mode: Option<Mode>) -> usize {
+    match mode {
+        Some(Mode::Measurement) => 0,
+        Some(Mode::Idle) => 1,
+        Some(Mode::Sleep) => 2,
+        None => 3,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::cell::Cell;
+
+    use embedded_hal_async::delay::DelayNs;
+    use futures::executor::block_on;
+
+    use super::PowerBudget;
+    use crate::mock::MockSps30;
+    use crate::schedule::LowRateSampler;
+    use crate::{Mode, Sps30};
+
+    #[test]
+    fn duty_cycle() {
+        const HOUR_MS: u64 = 3_600_000;
+        let now = Cell::new(0);
+        let mut budget = PowerBudget::new(|| now.get());
+
+        budget.update(Some(Mode::Measurement));
+        now.set(HOUR_MS / 10);
+        budget.update(Some(Mode::Sleep));
+        now.set(HOUR_MS);
+        budget.update(Some(Mode::Sleep));
+
+        assert_eq!(budget.time_in_ms(Mode::Measurement), HOUR_MS / 10);
+        assert_eq!(budget.time_in_ms(Mode::Sleep), 9 * HOUR_MS / 10);
+        assert_eq!(budget.unknown_ms(), 0);
+        let expected = 55.0 / 10.0 + 0.038 * 0.9;
+        assert!((budget.consumed_mah() - expected).abs() < 1e-3);
+
+        budget.reset();
+        assert_eq!(budget.consumed_mah(), 0.0);
+    }
+
+    /// Advances `now` by the time waited
+    struct ClockDelay<'a>(&'a Cell<u64>);
+
+    impl DelayNs for ClockDelay<'_> {
+        async fn delay_ns(&mut self, ns: u32) {
+            self.0.set(self.0.get() + u64::from(ns) / 1_000_000);
+        }
+
+        async fn delay_ms(&mut self, ms: u32) {
+            self.0.set(self.0.get() + u64::from(ms));
+        }
+    }
+
+    #[test]
+    fn follows_low_rate_sampler() {
+        const INTERVAL_MS: u32 = 5 * 60_000;
+        const WARMUP_MS: u32 = 30_000;
+        let mock = MockSps30::new();
+        let now = Cell::new(0);
+        let mut sensor = Sps30::from_tx_rx_uninit(&mock, &mock, ClockDelay(&now))
+            .with_logger(PowerBudget::new(|| now.get()));
+        block_on(sensor.reset()).unwrap();
+
+        let mut sampler = LowRateSampler::new(INTERVAL_MS).with_warmup(WARMUP_MS);
+        block_on(sampler.next(&mut sensor)).unwrap();
+        block_on(sampler.next(&mut sensor)).unwrap();
+        assert!(mock.is_sleeping());
+
+        let budget = sensor.logger();
+        assert_eq!(
+            budget.time_in_ms(Mode::Measurement),
+            2 * u64::from(WARMUP_MS)
+        );
+        assert_eq!(budget.time_in_ms(Mode::Idle), 20); // waiting after the reset
+        assert_eq!(
+            budget.time_in_ms(Mode::Sleep),
+            u64::from(INTERVAL_MS - WARMUP_MS)
+        );
+        assert_eq!(budget.unknown_ms(), 0);
+    }
+}
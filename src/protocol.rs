@@ -0,0 +1,528 @@
+//! The SPS30 protocol without any I/O: encoding requests, finding and
+//! validating responses. The async driver, the `polling` driver (feature
+//! `nb`) and the tests are all built on this. Other frontends, for example
+//! DMA based, move the bytes themselves: send [`Request::bytes`] then feed
+//! whatever arrives to a [`ResponseReader`] until it returns the response.
+//!
+//! ```
+//! use sps30_async::protocol::{Command, Request, ResponseReader};
+//! # use sps30_async::shdlc::{checksum, encode};
+//! # let mut frame = heapless::Vec::<u8, 8>::from_slice(&[0, 0x03, 0, 0]).unwrap();
+//! # frame.push(checksum(&frame)).unwrap();
+//! # let received: heapless::Vec<u8, 18> = encode(&frame).unwrap();
+//!
+//! let request = Request::new(0, Command::ReadMeasuredData as u8, &[]).unwrap();
+//! // for byte in request.bytes() { uart.write(byte) }
+//! let mut reader = ResponseReader::new(&request);
+//! let mut bytes = &received[..]; // uart.read(..)
+//! let response = reader
+//!     .feed::<(), ()>(&mut bytes)
+//!     .expect("frame complete")
+//!     .unwrap();
+//! assert!(response.data().is_empty());
+//! ```
+
+use core::fmt;
+
+use heapless::Vec;
+
+use crate::shdlc::device::MAX_DECODED_FRAME_SIZE;
+use crate::shdlc::MAX_REQUEST_DATA_LEN;
+use crate::shdlc::{self, checksum, max_encoded_len, Decoder, Frame, Response};
+use crate::{
+    DeviceError, Error, MaybeFormat, Measurement, MeasurementFormat, ProtocolError, RawFrame,
+};
+
+/// header (address, command, length), data and checksum
+const MAX_REQUEST_FRAME_SIZE: usize = 3 + MAX_REQUEST_DATA_LEN + 1;
+/// Largest encoded request, including frame boundaries
+pub const MAX_ENCODED_REQUEST_LEN: usize = max_encoded_len(MAX_REQUEST_FRAME_SIZE);
+
+/// SPS30 commands, the second byte of every frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Command {
+    StartMeasurement = 0,
+    StopMeasurement = 1,
+    ReadMeasuredData = 3,
+    Sleep = 0x10,
+    WakeUp = 0x11,
+    /// Read or Write Auto Cleaning Interval
+    ReadWriteAutoCleaningInterval = 0x80,
+    StartFanCleaning = 0x56,
+    DeviceInformation = 0xD0,
+    ReadVersion = 0xD1,
+    ReadDeviceStatusRegister = 0xD2,
+    Reset = 0xD3,
+}
+
+/// Data for [`Command::DeviceInformation`], selects the field returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum InfoField {
+    ProductType = 0,
+    // ProductName = 1,
+    // ArticleCode = 2,
+    SerialNumber = 3,
+}
+
+/// A request frame, escaped while it is sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    frame: Vec<u8, MAX_REQUEST_FRAME_SIZE>,
+}
+
+impl Request {
+    /// Command `command` with `data` for the device at `address`
+    ///
+    /// # Errors
+    /// Returns [`shdlc::Error::TooMuchData`] if `data` is longer than
+    /// [`MAX_REQUEST_DATA_LEN`].
+    pub fn new(address: u8, command: u8, data: &[u8]) -> Result<Self, shdlc::Error> {
+        if data.len() > MAX_REQUEST_DATA_LEN {
+            return Err(shdlc::Error::TooMuchData);
+        }
+        #[allow(clippy::cast_possible_truncation)] // checked above
+        let data_len = data.len() as u8;
+
+        let mut frame = Vec::new();
+        frame
+            .extend_from_slice(&[address, command, data_len])
+            .and_then(|()| frame.extend_from_slice(data))
+            .map_err(|()| shdlc::Error::TooMuchData)?;
+        frame.push(checksum(&frame))?;
+        Ok(Self { frame })
+    }
+
+    /// Starts measuring in `format`, see
+    /// [`Sps30::start_measurement`](crate::Sps30::start_measurement)
+    #[must_use]
+    pub fn start_measurement(address: u8, format: MeasurementFormat) -> Self {
+        const SUBCMD: u8 = 0x01;
+        let data = [SUBCMD, format.code()];
+        Self::new(address, Command::StartMeasurement as u8, &data).expect("data fits request")
+    }
+
+    /// Reads a measurement, parse the response with [`parse_measurement`]
+    #[must_use]
+    pub fn read_measurement(address: u8) -> Self {
+        Self::new(address, Command::ReadMeasuredData as u8, &[]).expect("data fits request")
+    }
+
+    /// The address of the device this is for
+    #[must_use]
+    pub fn address(&self) -> u8 {
+        self.frame[0]
+    }
+
+    /// The command requested
+    #[must_use]
+    pub fn command(&self) -> u8 {
+        self.frame[1]
+    }
+
+    /// The decoded frame, without escaping or boundary markers
+    #[must_use]
+    pub fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    /// The encoded frame, these bytes go on the wire. Use
+    /// [`shdlc::encode_to`] with [`Self::frame`] to write them to an async
+    /// uart instead.
+    pub fn bytes(&self) -> impl Iterator<Item = u8> + Clone + '_ {
+        shdlc::encoded_bytes(&self.frame)
+    }
+}
+
+/// Something [`ResponseReader::feed_with`] came across while looking for
+/// the response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// A decoded frame, passed before it is checked
+    Frame(&'a [u8]),
+    /// A malformed frame, a frame from another device or for another
+    /// command or a stale response was skipped
+    Skipped,
+}
+
+/// Finds and validates the response to a [`Request`] in the received
+/// bytes. Noise, malformed frames and frames from other devices or for
+/// other commands are skipped.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseReader {
+    decoder: Decoder<MAX_DECODED_FRAME_SIZE>,
+    address: u8,
+    command: u8,
+    /// Skip the next response, it belongs to an abandoned request
+    skip_stale: bool,
+}
+
+impl ResponseReader {
+    /// Waits for the response to `request`
+    #[must_use]
+    pub fn new(request: &Request) -> Self {
+        let mut reader = Self::default();
+        reader.expect(request);
+        reader
+    }
+
+    /// Waits for the response to `request` instead. Bytes fed before are
+    /// kept, a partially received frame completes as usual.
+    pub fn expect(&mut self, request: &Request) {
+        self.address = request.address();
+        self.command = request.command();
+        self.skip_stale = false;
+    }
+
+    /// Skip the next response, use this when a request for the same
+    /// command was abandoned after it was sent.
+    pub fn skip_next(&mut self) {
+        self.skip_stale = true;
+    }
+
+    /// Drop any partially received frame
+    pub fn reset(&mut self) {
+        self.decoder.reset();
+    }
+
+    /// Feeds bytes from the front of `bytes` until the response is
+    /// complete or `bytes` runs out. Consumed bytes are removed from
+    /// `bytes`, anything after the response is left.
+    ///
+    /// # Errors
+    /// Returns an error if the response is invalid or the device reports an
+    /// error, see [`Error`].
+    pub fn feed<TxError, RxError>(
+        &mut self,
+        bytes: &mut &[u8],
+    ) -> Option<Result<Response, Error<TxError, RxError>>>
+    where
+        TxError: MaybeFormat + fmt::Debug,
+        RxError: MaybeFormat + fmt::Debug,
+    {
+        self.feed_with(bytes, |_| ())
+    }
+
+    /// Like [`feed`](Self::feed) but passes what it comes across to
+    /// `on_event`, for statistics or logging.
+    ///
+    /// # Errors
+    /// See [`Self::feed`]
+    pub fn feed_with<TxError, RxError>(
+        &mut self,
+        bytes: &mut &[u8],
+        mut on_event: impl FnMut(Event),
+    ) -> Option<Result<Response, Error<TxError, RxError>>>
+    where
+        TxError: MaybeFormat + fmt::Debug,
+        RxError: MaybeFormat + fmt::Debug,
+    {
+        while !bytes.is_empty() {
+            let frame = match self.decoder.push(bytes)? {
+                Err(e) => {
+                    debug!("skipping malformed frame: {}", e);
+                    on_event(Event::Skipped);
+                    continue;
+                }
+                Ok(frame) => frame,
+            };
+            on_event(Event::Frame(frame));
+            if !is_for(frame, self.address, self.command) {
+                on_event(Event::Skipped);
+                continue;
+            }
+            if self.skip_stale {
+                debug!("skipping response to abandoned request");
+                self.skip_stale = false;
+                on_event(Event::Skipped);
+                continue;
+            }
+
+            let response =
+                parse_miso_frame(frame, self.address, self.command).map(|_| Response::new(frame));
+            // anything after the response is noise
+            self.decoder.reset();
+            return Some(response);
+        }
+        None
+    }
+
+    /// Checks a frame decoded elsewhere, for example by a
+    /// [`FrameAssembler`](crate::shdlc::FrameAssembler). Returns `None` if
+    /// it is not the response, then it should be skipped.
+    ///
+    /// # Errors
+    /// See [`Self::feed`]
+    pub fn feed_frame<TxError, RxError>(
+        &self,
+        frame: &Frame,
+    ) -> Option<Result<Response, Error<TxError, RxError>>>
+    where
+        TxError: MaybeFormat + fmt::Debug,
+        RxError: MaybeFormat + fmt::Debug,
+    {
+        let frame = frame.bytes();
+        is_for(frame, self.address, self.command).then(|| {
+            parse_miso_frame(frame, self.address, self.command).map(|_| Response::new(frame))
+        })
+    }
+}
+
+/// Finds and validates the response to `request` in `received`, for
+/// uart stacks that deliver a whole response at once, for example using
+/// idle line detection and DMA. Noise around the response is skipped.
+///
+/// # Errors
+/// Returns [`ProtocolError::InvalidFrame`] if `received` holds no complete
+/// response to `request`, see [`ResponseReader::feed`] for the other errors.
+pub fn process_response<TxError, RxError>(
+    request: &Request,
+    mut received: &[u8],
+) -> Result<Response, Error<TxError, RxError>>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    ResponseReader::new(request)
+        .feed(&mut received)
+        .unwrap_or(Err(Error::Protocol(ProtocolError::InvalidFrame)))
+}
+
+/// The measurement in a response to [`Request::read_measurement`]. The
+/// format is detected from the length of the response, `format` is only
+/// used if that is not conclusive.
+///
+/// # Errors
+/// Returns [`ProtocolError::EmptyResult`] if no new measurement was available
+/// and [`ProtocolError::MeasurementDataTooShort`] if the data does not fit
+/// either format.
+pub fn parse_measurement<TxError, RxError>(
+    response: &Response,
+    format: MeasurementFormat,
+) -> Result<Measurement, Error<TxError, RxError>>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    if response.data().is_empty() {
+        return Err(Error::Protocol(ProtocolError::EmptyResult));
+    }
+    Measurement::from_data(response.data(), format)
+        .map_err(|_| Error::Protocol(ProtocolError::MeasurementDataTooShort))
+}
+
+/// Whether `frame` claims to come from `address` and answer `command`
+fn is_for(frame: &[u8], address: u8, command: u8) -> bool {
+    let matches = frame.get(..2) == Some(&[address, command]);
+    if !matches {
+        debug!("skipping frame for other device or command");
+    }
+    matches
+}
+
+/// Perform checks on decoded MISO Frame, returns the data
+///
+/// Start
+///  ADR     CMD       State    Length    RX Data          CHK     Stop
+///  0x7E   1 Byte   1 Byte    1 Byte    0...255 bytes    1 Byte   0x7E
+///
+/// Accepts any input, malformed frames are reported as errors and never
+/// cause a panic.
+///
+/// # Errors
+/// - [`ProtocolError::InvalidResponse`] the frame is too short, its length
+///   field does not match or it is not from `address` answering `cmd_type`
+/// - [`ProtocolError::ChecksumFailed`] the checksum does not match
+/// - [`Error::Device`] the device reported an error
+pub fn parse_miso_frame<TxError, RxError>(
+    frame: &[u8],
+    address: u8,
+    cmd_type: u8,
+) -> Result<&[u8], Error<TxError, RxError>>
+where
+    RxError: MaybeFormat + fmt::Debug,
+    TxError: MaybeFormat + fmt::Debug,
+{
+    let [addr, cmd, state, length, data @ .., check_sum] = frame else {
+        return Err(Error::Protocol(ProtocolError::InvalidResponse(
+            RawFrame::new(frame),
+        )));
+    };
+    trace!("frame: {:?}", frame);
+    trace!("cmd: {}, state: {}, length: {}", cmd, state, length);
+    trace!("data len: {}", data.len());
+
+    let [without_checksum @ .., _] = frame else {
+        unreachable!()
+    };
+    if *check_sum != checksum(without_checksum) {
+        return Err(Error::Protocol(ProtocolError::ChecksumFailed(
+            RawFrame::new(frame),
+        )));
+    }
+
+    if *addr != address || *cmd != cmd_type {
+        return Err(Error::Protocol(ProtocolError::InvalidResponse(
+            RawFrame::new(frame),
+        )));
+    }
+    if *state != 0 {
+        let dev_err = DeviceError::from(*state);
+        return Err(Error::Device(dev_err));
+    }
+
+    if *length as usize != data.len() {
+        return Err(Error::Protocol(ProtocolError::InvalidResponse(
+            RawFrame::new(frame),
+        )));
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_measurement, parse_miso_frame, process_response, Request, ResponseReader};
+    use crate::shdlc::{checksum, encode, FrameAssembler, FRAME_BOUNDARY_MARKER as FB};
+    use crate::{DeviceError, Error, MeasurementFormat, ProtocolError};
+    use core::convert::Infallible;
+    use heapless::Vec;
+
+    fn response(cmd: u8, state: u8, data: &[u8]) -> Vec<u8, 128> {
+        let mut frame: Vec<u8, 64> = Vec::new();
+        #[allow(clippy::cast_possible_truncation)]
+        frame
+            .extend_from_slice(&[0, cmd, state, data.len() as u8])
+            .unwrap();
+        frame.extend_from_slice(data).unwrap();
+        frame.push(checksum(&frame)).unwrap();
+        encode(&frame).unwrap()
+    }
+
+    #[test]
+    fn request_too_large() {
+        assert!(Request::new(0, 0x80, &[0; 5]).is_ok());
+        assert!(Request::new(0, 0x80, &[0; 6]).is_err());
+    }
+
+    #[test]
+    fn skips_other_frames() {
+        let request = Request::new(0, 3, &[]).unwrap();
+        assert!(request.bytes().eq([FB, 0, 3, 0, 0xfc, FB]));
+        let mut reader = ResponseReader::new(&request);
+
+        let mut received: Vec<u8, 64> = Vec::new();
+        received.extend_from_slice(&[1, FB, 2]).unwrap();
+        received
+            .extend_from_slice(&response(0x80, 0, &[9]))
+            .unwrap();
+        received
+            .extend_from_slice(&response(3, 0, &[1, 2]))
+            .unwrap();
+        received.push(7).unwrap();
+        let (first, second) = received.split_at(9);
+
+        let mut bytes = first;
+        assert!(reader.feed::<(), ()>(&mut bytes).is_none());
+        let mut bytes = second;
+        let response = reader.feed::<(), ()>(&mut bytes).unwrap().unwrap();
+        assert_eq!(response.data(), [1, 2]);
+        assert_eq!(bytes, [7]);
+    }
+
+    #[test]
+    fn device_error() {
+        let request = Request::new(0, 3, &[]).unwrap();
+        let mut reader = ResponseReader::new(&request);
+        let mut bytes = &response(3, 0x43, &[])[..];
+        assert_eq!(
+            reader.feed::<(), ()>(&mut bytes).unwrap(),
+            Err(Error::Device(DeviceError::InvalidStateForCommand))
+        );
+    }
+
+    #[test]
+    fn assembled_frames() {
+        let request = Request::new(0, 3, &[]).unwrap();
+        let reader = ResponseReader::new(&request);
+        let mut assembler = FrameAssembler::new();
+        let mut received: Vec<u8, 64> = Vec::new();
+        received
+            .extend_from_slice(&response(0x80, 0, &[9]))
+            .unwrap();
+        received.extend_from_slice(&response(3, 0, &[1])).unwrap();
+
+        let response = received
+            .iter()
+            .filter_map(|byte| assembler.push_byte(*byte))
+            .find_map(|frame| reader.feed_frame::<(), ()>(&frame))
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.data(), [1]);
+    }
+
+    #[test]
+    fn rejected_frame_is_kept() {
+        let frame = [0, 3, 0, 1, 42, 0];
+        let Err(Error::Protocol(ProtocolError::ChecksumFailed(raw))) =
+            parse_miso_frame::<Infallible, Infallible>(&frame, 0, 3)
+        else {
+            panic!("checksum should fail");
+        };
+        if cfg!(feature = "frame-diagnostics") {
+            assert_eq!(raw.bytes(), frame);
+        } else {
+            assert!(raw.bytes().is_empty());
+        }
+    }
+
+    #[test]
+    fn dma_buffer() {
+        let request = Request::read_measurement(0);
+        let mut data: Vec<u8, 40> = Vec::new();
+        for value in [1.5f32, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 0.5] {
+            data.extend_from_slice(&value.to_be_bytes()).unwrap();
+        }
+        let mut received: Vec<u8, 128> = Vec::from_slice(&[0, 0]).unwrap();
+        received.extend_from_slice(&response(3, 0, &data)).unwrap();
+
+        let response = process_response::<(), ()>(&request, &received).unwrap();
+        let measurement = parse_measurement::<(), ()>(&response, MeasurementFormat::Float).unwrap();
+        assert_eq!(measurement.mass_pm1_0, 1.5);
+        assert_eq!(
+            process_response::<(), ()>(&request, &received[..20]),
+            Err(Error::Protocol(ProtocolError::InvalidFrame))
+        );
+    }
+
+    #[test]
+    fn arbitrary_input() {
+        // xorshift, deterministic input for the parsers
+        let mut state = 0x2545_f491_u32;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+        let request = Request::read_measurement(0);
+        for _ in 0..2_000 {
+            let len = next() as usize % 64;
+            let mut input: Vec<u8, 64> = Vec::new();
+            for _ in 0..len {
+                // favour the special bytes
+                let byte = match next() % 4 {
+                    0 => [FB, 0x7d, 0x5e, 0x5d, 0x31, 0x33, 0, 3][next() as usize % 8],
+                    _ => next().to_le_bytes()[0],
+                };
+                input.push(byte).unwrap();
+            }
+            let _ = parse_miso_frame::<(), ()>(&input, 0, 3);
+            let _ = crate::shdlc::decode::<64>(&input);
+            let _ = process_response::<(), ()>(&request, &input);
+        }
+    }
+}
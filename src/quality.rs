@@ -0,0 +1,300 @@
+//! Not every reading is equally trustworthy. An [`Assessor`] reads
+//! measurements together with an assessment of their quality so
+//! consumers can drop or weight doubtful samples.
+//!
+//! ```ignore
+//! let mut assessor = Assessor::new(|| Instant::now().as_millis());
+//! sensor.start_measurement().await?;
+//! loop {
+//!     let reading = assessor.read(&mut sensor).await?;
+//!     if reading.quality.is_good() {
+//!         radio.send(&reading.measurement).await;
+//!     }
+//! }
+//! ```
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+use crate::power::Clock;
+use crate::schedule::DEFAULT_WARMUP_MS;
+use crate::shdlc::{FrameObserver, ProtocolLogger};
+use crate::{DeviceStatus, Error, MaybeFormat, Measurement, Sps30};
+
+/// Highest mass concentration the sensor is specified for in µg/m³
+const MAX_MASS: f32 = 1_000.0;
+/// Highest number concentration the sensor is specified for in #/cm³
+const MAX_NUMBER: f32 = 3_000.0;
+/// Largest typical particle size the sensor can report in µm
+const MAX_PARTICLE_SIZE: f32 = 10.0;
+
+/// Density assumed for the particles in g/cm³, typical for outdoor dust
+const PARTICLE_DENSITY: f32 = 1.65;
+/// Concentrations in µg/m³ below which mass and number are always
+/// consistent, they are dominated by rounding there
+const CONSISTENCY_FLOOR: f32 = 1.0;
+/// Default for [`Assessor::with_max_divergence`], readings from a clean
+/// sensor stay well within this factor
+pub const DEFAULT_MAX_DIVERGENCE: f32 = 10.0;
+
+/// How far a cumulative concentration may fall below the one of a smaller
+/// size before it is implausible, leaves room for rounding
+fn within_rounding(smaller: f32, larger: f32) -> bool {
+    smaller <= larger * 1.01 + 0.1
+}
+
+impl Measurement {
+    /// Whether every value is possible: finite, not negative, within the
+    /// range of the sensor and the cumulative concentrations grow with the
+    /// particle size (PM1.0 is included in PM2.5 and so on).
+    #[must_use]
+    pub fn is_plausible(&self) -> bool {
+        let mass = [
+            self.mass_pm1_0,
+            self.mass_pm2_5,
+            self.mass_pm4_0,
+            self.mass_pm10,
+        ];
+        let number = self.number_per_cm3();
+        let in_range = |values: &[f32], max: f32| {
+            values
+                .iter()
+                .all(|v| v.is_finite() && (0.0..=max).contains(v))
+        };
+        let cumulative = |values: &[f32]| values.windows(2).all(|w| within_rounding(w[0], w[1]));
+
+        in_range(&mass, MAX_MASS)
+            && in_range(&number, MAX_NUMBER)
+            && in_range(&[self.typical_particle_size], MAX_PARTICLE_SIZE)
+            && cumulative(&mass)
+            && cumulative(&number)
+    }
+
+    /// PM2.5 mass concentration in µg/m³ estimated from the number
+    /// concentrations, assuming spheres of typical density at the middle
+    /// of each size bin. Larger bins hold too few particles for their
+    /// number to say much, so PM10 is not estimated.
+    #[must_use]
+    pub fn estimated_mass_pm2_5(&self) -> f32 {
+        let [pm0_5_1_0, pm1_0_2_5, ..] = self.number_bins_per_cm3();
+        // (number in bin, middle of the bin in µm), the sensor counts from
+        // 0.3µm
+        let bins = [(self.mass_pm0_5, 0.4), (pm0_5_1_0, 0.75), (pm1_0_2_5, 1.75)];
+        // a particle of d µm weighs ρ·π/6·d³·10⁻⁶ µg, there are 10⁶ times
+        // more per m³ than per cm³
+        bins.iter()
+            .map(|(number, diameter)| {
+                number
+                    * PARTICLE_DENSITY
+                    * core::f32::consts::FRAC_PI_6
+                    * diameter
+                    * diameter
+                    * diameter
+            })
+            .sum()
+    }
+
+    /// Whether the PM2.5 mass and number concentrations agree within a
+    /// factor `max_divergence`. The firmware derives both from the same
+    /// size bins, wildly diverging values usually mean dust or
+    /// condensation on the optics.
+    #[must_use]
+    pub fn is_consistent(&self, max_divergence: f32) -> bool {
+        let estimated = self.estimated_mass_pm2_5();
+        let measured = self.mass_pm2_5;
+        measured <= estimated * max_divergence + CONSISTENCY_FLOOR
+            && estimated <= measured * max_divergence + CONSISTENCY_FLOOR
+    }
+}
+
+/// What might be wrong with a measurement, see [`AssessedMeasurement`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Quality {
+    /// Read shortly after the measurement started, before the readings
+    /// settled
+    pub warming_up: bool,
+    /// The values are impossible, see [`Measurement::is_plausible`]
+    pub implausible: bool,
+    /// Mass and number concentrations diverge, a sign of contaminated
+    /// optics, see [`Measurement::is_consistent`]
+    pub inconsistent: bool,
+    /// Faults the device reported when the measurement was read
+    pub status: DeviceStatus,
+}
+
+impl Quality {
+    /// Nothing is known to be wrong
+    #[must_use]
+    pub fn is_good(&self) -> bool {
+        !self.warming_up && !self.implausible && !self.inconsistent && self.status.is_empty()
+    }
+}
+
+/// A measurement and how far to trust it, read by [`Assessor::read`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct AssessedMeasurement {
+    pub measurement: Measurement,
+    pub quality: Quality,
+}
+
+/// Reads measurements and assesses their [`Quality`], see the
+/// [module](self) documentation.
+///
+/// The warm up is timed from the first read, or the last
+/// [`restart`](Self::restart). Call `restart` after starting the
+/// measurement again.
+#[derive(Debug, Clone)]
+pub struct Assessor<C> {
+    clock: C,
+    warmup_ms: u32,
+    max_divergence: f32,
+    /// Time of the first read since the (re)start
+    started_ms: Option<u64>,
+}
+
+impl<C: Clock> Assessor<C> {
+    /// Flags readings during the first [`DEFAULT_WARMUP_MS`] as warming up
+    pub fn new(clock: C) -> Self {
+        Self {
+            clock,
+            warmup_ms: DEFAULT_WARMUP_MS,
+            max_divergence: DEFAULT_MAX_DIVERGENCE,
+            started_ms: None,
+        }
+    }
+
+    /// Flag readings during the first `ms` milliseconds instead of
+    /// [`DEFAULT_WARMUP_MS`]
+    #[must_use]
+    pub fn with_warmup(mut self, ms: u32) -> Self {
+        self.warmup_ms = ms;
+        self
+    }
+
+    /// Flag readings whose mass and number concentrations diverge by more
+    /// than a factor `max` instead of [`DEFAULT_MAX_DIVERGENCE`]
+    #[must_use]
+    pub fn with_max_divergence(mut self, max: f32) -> Self {
+        self.max_divergence = max;
+        self
+    }
+
+    /// The measurement was started again, time the warm up from the next
+    /// read
+    pub fn restart(&mut self) {
+        self.started_ms = None;
+    }
+
+    /// Assesses a measurement read now, with the status register as read
+    /// alongside it
+    pub fn assess(
+        &mut self,
+        measurement: Measurement,
+        status: DeviceStatus,
+    ) -> AssessedMeasurement {
+        let now = self.clock.now_ms();
+        let started = *self.started_ms.get_or_insert(now);
+        AssessedMeasurement {
+            measurement,
+            quality: Quality {
+                warming_up: now.saturating_sub(started) < u64::from(self.warmup_ms),
+                implausible: !measurement.is_plausible(),
+                inconsistent: !measurement.is_consistent(self.max_divergence),
+                status,
+            },
+        }
+    }
+
+    /// Reads a measurement and the status register, which is not
+    /// cleared, then assesses them
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    pub async fn read<Tx, Rx, D, O, L>(
+        &mut self,
+        sensor: &mut Sps30<Tx, Rx, D, O, L>,
+    ) -> Result<AssessedMeasurement, Error<Tx::Error, Rx::Error>>
+    where
+        Tx: Write,
+        Tx::Error: MaybeFormat,
+        Rx: Read,
+        Rx::Error: MaybeFormat,
+        D: DelayNs,
+        O: FrameObserver,
+        L: ProtocolLogger,
+    {
+        let measurement = sensor.read_measurement().await?;
+        let status = sensor.read_device_status_register(false).await?;
+        Ok(self.assess(measurement, status))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::cell::Cell;
+
+    use futures::executor::block_on;
+
+    use super::{Assessor, DEFAULT_MAX_DIVERGENCE};
+    use crate::mock::{MockSps30, NoDelay};
+    use crate::{DeviceStatus, Measurement, Sps30};
+
+    #[test]
+    fn flags_doubtful_readings() {
+        let mock = MockSps30::new();
+        let mut sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+        let now = Cell::new(0);
+        let mut assessor = Assessor::new(|| now.get()).with_warmup(10_000);
+
+        let first = block_on(assessor.read(&mut sensor)).unwrap();
+        assert!(first.quality.warming_up && !first.quality.implausible);
+        assert!(!first.quality.inconsistent);
+        now.set(10_000);
+        let settled = block_on(assessor.read(&mut sensor)).unwrap();
+        assert!(settled.quality.is_good());
+        assert_eq!(settled.measurement, mock.measurement());
+
+        let mut impossible = mock.measurement();
+        impossible.mass_pm10 = impossible.mass_pm1_0 / 2.0;
+        mock.set_measurement(impossible);
+        mock.set_status_register(DeviceStatus::LASER_FAILURE.bits());
+        let quality = block_on(assessor.read(&mut sensor)).unwrap().quality;
+        assert!(quality.implausible && !quality.warming_up);
+        assert_eq!(quality.status, DeviceStatus::LASER_FAILURE);
+
+        assessor.restart();
+        assert!(
+            block_on(assessor.read(&mut sensor))
+                .unwrap()
+                .quality
+                .warming_up
+        );
+    }
+
+    #[test]
+    fn detects_contaminated_optics() {
+        let clean = MockSps30::new().measurement();
+        let estimated = clean.estimated_mass_pm2_5();
+        assert!((estimated / clean.mass_pm2_5 - 1.0).abs() < 0.5);
+        assert!(clean.is_consistent(DEFAULT_MAX_DIVERGENCE));
+
+        // scattered light from dust on the lens reads as mass without
+        // matching particle counts
+        let contaminated = Measurement {
+            mass_pm1_0: 40.0,
+            mass_pm2_5: 80.0,
+            mass_pm4_0: 90.0,
+            mass_pm10: 95.0,
+            ..clean
+        };
+        assert!(contaminated.is_plausible());
+        assert!(!contaminated.is_consistent(DEFAULT_MAX_DIVERGENCE));
+        assert!(Measurement::zeroed().is_consistent(DEFAULT_MAX_DIVERGENCE));
+    }
+}
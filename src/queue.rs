@@ -0,0 +1,469 @@
+//! A device server for firmware where many tasks need the sensor. Tasks
+//! [`submit`](CommandQueue::submit) requests to a [`CommandQueue`] and
+//! await the returned [`Ticket`] for the response. One task
+//! [runs](CommandQueue::run) the queue: it executes the requests on a
+//! [`SharedSps30`] highest [`Priority`] first, oldest first within a
+//! priority. Reads pending at the same time are executed once and their
+//! response is shared.
+//!
+//! ```ignore
+//! static QUEUE: CommandQueue<CriticalSectionRawMutex, UartError, UartError, 8> =
+//!     CommandQueue::new();
+//!
+//! #[embassy_executor::task]
+//! async fn server(sensor: &'static SharedSps30<..>) -> ! {
+//!     QUEUE.run(sensor).await
+//! }
+//!
+//! #[embassy_executor::task]
+//! async fn display() -> ! {
+//!     loop {
+//!         let ticket = QUEUE.submit(Request::ReadMeasurement, Priority::Normal)?;
+//!         if let Response::Measurement(measurement) = ticket.await? {
+//!             show(measurement);
+//!         }
+//!         Timer::after_secs(1).await;
+//!     }
+//! }
+//! ```
+
+use core::cell::RefCell;
+use core::cmp::Reverse;
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_sync::waitqueue::WakerRegistration;
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+use heapless::Vec;
+
+use crate::shared::SharedSps30;
+use crate::shdlc::{FrameObserver, ProtocolLogger};
+use crate::{DeviceStatus, Error, MaybeFormat, Measurement};
+
+/// What a task can ask of the sensor through a [`CommandQueue`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Request {
+    /// See [`Sps30::read_measurement`](crate::Sps30::read_measurement)
+    ReadMeasurement,
+    /// See [`Sps30::read_device_status_register`](crate::Sps30::read_device_status_register)
+    ReadStatus { clear: bool },
+    /// See [`Sps30::start_fan_cleaning`](crate::Sps30::start_fan_cleaning)
+    StartFanCleaning,
+}
+
+impl Request {
+    /// Whether identical requests pending together can share one response
+    fn coalesces(self) -> bool {
+        matches!(self, Request::ReadMeasurement | Request::ReadStatus { .. })
+    }
+}
+
+/// The answer to a [`Request`] of the same name
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Response {
+    Measurement(Measurement),
+    Status(DeviceStatus),
+    /// The fan cleaning started
+    Done,
+}
+
+/// Order in which pending requests are executed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+/// Every slot of the [`CommandQueue`] holds a request, wait for one to be
+/// answered before submitting more
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct QueueFull;
+
+impl fmt::Display for QueueFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("command queue is full")
+    }
+}
+
+type Outcome<TxError, RxError> = Result<Response, Error<TxError, RxError>>;
+
+/// A request from submitting it until its [`Ticket`] took the response.
+/// The sequence number tells a reused slot apart from the request it held
+/// before.
+enum Slot<TxError, RxError>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    Free,
+    Queued {
+        request: Request,
+        priority: Priority,
+        seq: u64,
+    },
+    Running {
+        seq: u64,
+    },
+    Done {
+        seq: u64,
+        outcome: Outcome<TxError, RxError>,
+    },
+}
+
+impl<TxError, RxError> Slot<TxError, RxError>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    fn seq(&self) -> Option<u64> {
+        match self {
+            Slot::Free => None,
+            Slot::Queued { seq, .. } | Slot::Running { seq } | Slot::Done { seq, .. } => Some(*seq),
+        }
+    }
+}
+
+struct Entry<TxError, RxError>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    slot: Slot<TxError, RxError>,
+    /// The task awaiting the [`Ticket`] of this slot
+    waker: WakerRegistration,
+}
+
+struct State<TxError, RxError, const N: usize>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    entries: [Entry<TxError, RxError>; N],
+    next_seq: u64,
+}
+
+/// Requests waiting for the sensor, see the [module](self) documentation.
+///
+/// Holds up to `N` requests, submitted but not yet answered ones and
+/// answered ones whose [`Ticket`] has not been awaited yet. The error types
+/// are those of the uart halves of the driver.
+pub struct CommandQueue<M, TxError, RxError, const N: usize>
+where
+    M: RawMutex,
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    state: Mutex<M, RefCell<State<TxError, RxError, N>>>,
+    /// Signaled on every submit, wakes [`CommandQueue::run`]
+    work: Signal<M, ()>,
+}
+
+impl<M, TxError, RxError, const N: usize> Default for CommandQueue<M, TxError, RxError, N>
+where
+    M: RawMutex,
+    TxError: MaybeFormat + fmt::Debug + Clone,
+    RxError: MaybeFormat + fmt::Debug + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M, TxError, RxError, const N: usize> CommandQueue<M, TxError, RxError, N>
+where
+    M: RawMutex,
+    TxError: MaybeFormat + fmt::Debug + Clone,
+    RxError: MaybeFormat + fmt::Debug + Clone,
+{
+    /// An empty queue, usable in a `static`
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(State {
+                entries: [const {
+                    Entry {
+                        slot: Slot::Free,
+                        waker: WakerRegistration::new(),
+                    }
+                }; N],
+                next_seq: 0,
+            })),
+            work: Signal::new(),
+        }
+    }
+
+    /// Queues `request`, await the returned ticket for the response.
+    /// Dropping the ticket withdraws the request, or discards the response
+    /// if it already ran.
+    ///
+    /// # Errors
+    /// Returns [`QueueFull`] if all `N` slots are in use.
+    pub fn submit(
+        &self,
+        request: Request,
+        priority: Priority,
+    ) -> Result<Ticket<'_, M, TxError, RxError, N>, QueueFull> {
+        let (index, seq) = self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+            let seq = state.next_seq;
+            let index = state
+                .entries
+                .iter()
+                .position(|entry| matches!(entry.slot, Slot::Free))
+                .ok_or(QueueFull)?;
+            state.entries[index].slot = Slot::Queued {
+                request,
+                priority,
+                seq,
+            };
+            state.next_seq += 1;
+            Ok((index, seq))
+        })?;
+        self.work.signal(());
+        Ok(Ticket {
+            queue: self,
+            index,
+            seq,
+        })
+    }
+
+    /// Number of requests waiting to be executed
+    pub fn pending(&self) -> usize {
+        self.state.lock(|state| {
+            state
+                .borrow()
+                .entries
+                .iter()
+                .filter(|entry| matches!(entry.slot, Slot::Queued { .. }))
+                .count()
+        })
+    }
+
+    /// Executes requests until none are pending, returns how many commands
+    /// were sent. Coalesced reads count once.
+    pub async fn process_pending<SM, Tx, Rx, D, O, L>(
+        &self,
+        sensor: &SharedSps30<SM, Tx, Rx, D, O, L>,
+    ) -> usize
+    where
+        SM: RawMutex,
+        Tx: Write<Error = TxError>,
+        Rx: Read<Error = RxError>,
+        D: DelayNs,
+        O: FrameObserver,
+        L: ProtocolLogger,
+    {
+        let mut executed = 0;
+        while let Some((request, batch)) = self.take_next() {
+            let outcome = match request {
+                Request::ReadMeasurement => {
+                    sensor.read_measurement().await.map(Response::Measurement)
+                }
+                Request::ReadStatus { clear } => sensor
+                    .read_device_status_register(clear)
+                    .await
+                    .map(Response::Status),
+                Request::StartFanCleaning => {
+                    sensor.start_fan_cleaning().await.map(|()| Response::Done)
+                }
+            };
+            self.complete(&batch, &outcome);
+            executed += 1;
+        }
+        executed
+    }
+
+    /// Serves requests forever, run this in its own task
+    pub async fn run<SM, Tx, Rx, D, O, L>(&self, sensor: &SharedSps30<SM, Tx, Rx, D, O, L>) -> !
+    where
+        SM: RawMutex,
+        Tx: Write<Error = TxError>,
+        Rx: Read<Error = RxError>,
+        D: DelayNs,
+        O: FrameObserver,
+        L: ProtocolLogger,
+    {
+        loop {
+            self.work.wait().await;
+            self.process_pending(sensor).await;
+        }
+    }
+
+    /// Marks the next request to execute running, together with the
+    /// pending requests it coalesces with. Returns the request and the
+    /// slots awaiting its response.
+    fn take_next(&self) -> Option<(Request, Vec<(usize, u64), N>)> {
+        self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+            let (next, request) = state
+                .entries
+                .iter()
+                .filter_map(|entry| match entry.slot {
+                    Slot::Queued {
+                        request,
+                        priority,
+                        seq,
+                    } => Some(((priority, Reverse(seq)), request)),
+                    _ => None,
+                })
+                .max_by_key(|(order, _)| *order)
+                .map(|((_, Reverse(seq)), request)| (seq, request))?;
+
+            let mut batch = Vec::new();
+            for (index, entry) in state.entries.iter_mut().enumerate() {
+                let Slot::Queued {
+                    request: queued,
+                    seq,
+                    ..
+                } = entry.slot
+                else {
+                    continue;
+                };
+                if seq == next || (queued == request && request.coalesces()) {
+                    entry.slot = Slot::Running { seq };
+                    batch
+                        .push((index, seq))
+                        .expect("batch has a capacity of all slots");
+                }
+            }
+            Some((request, batch))
+        })
+    }
+
+    /// Stores the response in every slot still awaiting it and wakes their
+    /// tickets
+    fn complete(&self, batch: &[(usize, u64)], outcome: &Outcome<TxError, RxError>) {
+        self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+            for (index, seq) in batch {
+                let entry = &mut state.entries[*index];
+                if matches!(entry.slot, Slot::Running { seq: running } if running == *seq) {
+                    entry.slot = Slot::Done {
+                        seq: *seq,
+                        outcome: outcome.clone(),
+                    };
+                    entry.waker.wake();
+                }
+            }
+        });
+    }
+}
+
+/// The response to a submitted [`Request`], see
+/// [`CommandQueue::submit`]
+#[must_use = "the request is withdrawn when the ticket is dropped"]
+pub struct Ticket<'q, M, TxError, RxError, const N: usize>
+where
+    M: RawMutex,
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    queue: &'q CommandQueue<M, TxError, RxError, N>,
+    index: usize,
+    seq: u64,
+}
+
+impl<M, TxError, RxError, const N: usize> Future for Ticket<'_, M, TxError, RxError, N>
+where
+    M: RawMutex,
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    type Output = Outcome<TxError, RxError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.queue.state.lock(|state| {
+            let mut state = state.borrow_mut();
+            let entry = &mut state.entries[self.index];
+            match core::mem::replace(&mut entry.slot, Slot::Free) {
+                Slot::Done { seq, outcome } if seq == self.seq => Poll::Ready(outcome),
+                other => {
+                    entry.slot = other;
+                    entry.waker.register(cx.waker());
+                    Poll::Pending
+                }
+            }
+        })
+    }
+}
+
+impl<M, TxError, RxError, const N: usize> Drop for Ticket<'_, M, TxError, RxError, N>
+where
+    M: RawMutex,
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    fn drop(&mut self) {
+        self.queue.state.lock(|state| {
+            let mut state = state.borrow_mut();
+            let slot = &mut state.entries[self.index].slot;
+            if slot.seq() == Some(self.seq) {
+                *slot = Slot::Free;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::convert::Infallible;
+
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use futures::executor::block_on;
+
+    use super::{CommandQueue, Priority, QueueFull, Request, Response};
+    use crate::mock::{MockSps30, NoDelay};
+    use crate::shared::SharedSps30;
+    use crate::Sps30;
+
+    #[test]
+    fn prioritizes_and_coalesces() {
+        let mock = MockSps30::new();
+        let sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+        let sensor = SharedSps30::<NoopRawMutex, _, _, _>::new(sensor);
+        let queue = CommandQueue::<NoopRawMutex, Infallible, Infallible, 4>::new();
+
+        let read = queue
+            .submit(Request::ReadMeasurement, Priority::Low)
+            .unwrap();
+        let withdrawn = queue
+            .submit(Request::StartFanCleaning, Priority::Low)
+            .unwrap();
+        let status = queue
+            .submit(Request::ReadStatus { clear: false }, Priority::High)
+            .unwrap();
+        let same_read = queue
+            .submit(Request::ReadMeasurement, Priority::Normal)
+            .unwrap();
+        assert_eq!(
+            queue.submit(Request::ReadMeasurement, Priority::Low).err(),
+            Some(QueueFull)
+        );
+        drop(withdrawn);
+        assert_eq!(queue.pending(), 3);
+
+        // the status read runs first and gets the error
+        mock.fail_next(0x43);
+        let before = mock.commands_received();
+        assert_eq!(block_on(queue.process_pending(&sensor)), 2);
+        assert_eq!(mock.commands_received() - before, 2);
+
+        assert!(block_on(status).is_err());
+        let expected = Ok(Response::Measurement(mock.measurement()));
+        assert_eq!(block_on(read), expected);
+        assert_eq!(block_on(same_read), expected);
+        assert_eq!(queue.pending(), 0);
+    }
+}
@@ -0,0 +1,309 @@
+//! Record raw uart traffic and replay it later, for example to reproduce a
+//! failure captured in the field.
+//!
+//! Captures use a simple log format: a sequence of records each consisting
+//! of a [`Direction`] byte, a length byte and that many bytes of traffic.
+//! [`Capture`] writes this format and [`ReplayTransport`] reads it.
+
+use core::cell::{Cell, RefCell};
+use core::convert::Infallible;
+
+use embedded_io_async::{ErrorType, Read, ReadReady, Write};
+use heapless::Vec;
+
+/// Which way the bytes went
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Direction {
+    /// Bytes sent to the sensor
+    Sent = 0,
+    /// Bytes received from the sensor
+    Received = 1,
+}
+
+/// Receives a copy of all traffic passing through a [`RecordingTransport`]
+pub trait TrafficSink {
+    /// Called for every successful read or write
+    fn on_bytes(&mut self, direction: Direction, bytes: &[u8]);
+}
+
+/// Wraps the uart halves copying all traffic into a [`TrafficSink`]
+pub struct RecordingTransport<Tx, Rx, Sink> {
+    tx: Tx,
+    rx: Rx,
+    sink: RefCell<Sink>,
+}
+
+impl<Tx, Rx, Sink: TrafficSink> RecordingTransport<Tx, Rx, Sink> {
+    /// Wraps `tx` and `rx`, use [`split`](Self::split) to get the halves
+    /// for the driver.
+    pub fn new(tx: Tx, rx: Rx, sink: Sink) -> Self {
+        Self {
+            tx,
+            rx,
+            sink: RefCell::new(sink),
+        }
+    }
+
+    /// Recording tx and rx halves to construct the driver with
+    pub fn split(&mut self) -> (RecordingTx<'_, Tx, Sink>, RecordingRx<'_, Rx, Sink>) {
+        (
+            RecordingTx {
+                inner: &mut self.tx,
+                sink: &self.sink,
+            },
+            RecordingRx {
+                inner: &mut self.rx,
+                sink: &self.sink,
+            },
+        )
+    }
+
+    /// The sink receiving the traffic
+    pub fn sink(&mut self) -> &mut Sink {
+        self.sink.get_mut()
+    }
+
+    /// Returns the wrapped halves and the sink
+    pub fn into_inner(self) -> (Tx, Rx, Sink) {
+        (self.tx, self.rx, self.sink.into_inner())
+    }
+}
+
+/// Tx half of a [`RecordingTransport`]
+pub struct RecordingTx<'a, Tx, Sink> {
+    inner: &'a mut Tx,
+    sink: &'a RefCell<Sink>,
+}
+
+/// Rx half of a [`RecordingTransport`]
+pub struct RecordingRx<'a, Rx, Sink> {
+    inner: &'a mut Rx,
+    sink: &'a RefCell<Sink>,
+}
+
+impl<Tx: ErrorType, Sink> ErrorType for RecordingTx<'_, Tx, Sink> {
+    type Error = Tx::Error;
+}
+
+impl<Rx: ErrorType, Sink> ErrorType for RecordingRx<'_, Rx, Sink> {
+    type Error = Rx::Error;
+}
+
+impl<Tx: Write, Sink: TrafficSink> Write for RecordingTx<'_, Tx, Sink> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let n = self.inner.write(buf).await?;
+        self.sink.borrow_mut().on_bytes(Direction::Sent, &buf[..n]);
+        Ok(n)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush().await
+    }
+}
+
+impl<Rx: Read, Sink: TrafficSink> Read for RecordingRx<'_, Rx, Sink> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let n = self.inner.read(buf).await?;
+        self.sink
+            .borrow_mut()
+            .on_bytes(Direction::Received, &buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<Rx: ReadReady, Sink> ReadReady for RecordingRx<'_, Rx, Sink> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        self.inner.read_ready()
+    }
+}
+
+/// [`TrafficSink`] storing traffic in the replay log format
+#[derive(Debug, Default)]
+pub struct Capture<const N: usize> {
+    log: Vec<u8, N>,
+    truncated: bool,
+}
+
+impl<const N: usize> Capture<N> {
+    /// An empty capture
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            log: Vec::new(),
+            truncated: false,
+        }
+    }
+
+    /// The captured log, feed this to [`ReplayTransport::new`]
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.log
+    }
+
+    /// True if the capture ran out of space, later traffic was dropped
+    #[must_use]
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl<const N: usize> TrafficSink for Capture<N> {
+    fn on_bytes(&mut self, direction: Direction, bytes: &[u8]) {
+        for chunk in bytes.chunks(u8::MAX as usize) {
+            if self.truncated || self.log.len() + 2 + chunk.len() > N {
+                self.truncated = true;
+                return;
+            }
+            #[allow(clippy::cast_possible_truncation)] // chunks are at most u8::MAX
+            let header = [direction as u8, chunk.len() as u8];
+            self.log
+                .extend_from_slice(&header)
+                .expect("checked capacity above");
+            self.log
+                .extend_from_slice(chunk)
+                .expect("checked capacity above");
+        }
+    }
+}
+
+/// Plays back a log recorded with [`Capture`]. Reads return the received
+/// bytes in the same chunks as they were originally read, followed by EOF.
+/// Writes are compared against the recorded sent bytes.
+///
+/// Like the [`mock`](crate::mock) it is implemented for shared references,
+/// so one instance can be passed as both tx and rx.
+pub struct ReplayTransport<'a> {
+    log: &'a [u8],
+    /// (record start, offset within record)
+    read_pos: Cell<(usize, usize)>,
+    write_pos: Cell<(usize, usize)>,
+    mismatches: Cell<usize>,
+}
+
+impl<'a> ReplayTransport<'a> {
+    /// Replays `log` as recorded by a [`Capture`]
+    #[must_use]
+    pub fn new(log: &'a [u8]) -> Self {
+        Self {
+            log,
+            read_pos: Cell::new((0, 0)),
+            write_pos: Cell::new((0, 0)),
+            mismatches: Cell::new(0),
+        }
+    }
+
+    /// Number of written bytes that differed from the recording
+    #[must_use]
+    pub fn mismatches(&self) -> usize {
+        self.mismatches.get()
+    }
+
+    /// Finds the data of the first record in `direction` at or after
+    /// `start`. Returns the record start and its data.
+    fn next_record(&self, mut start: usize, direction: Direction) -> Option<(usize, &'a [u8])> {
+        loop {
+            let [tag, len, ..] = self.log.get(start..)? else {
+                return None;
+            };
+            let data = self.log.get(start + 2..start + 2 + *len as usize)?;
+            if *tag == direction as u8 {
+                return Some((start, data));
+            }
+            start += 2 + data.len();
+        }
+    }
+
+    fn replay_read(&self, buf: &mut [u8]) -> usize {
+        let (mut start, mut offset) = self.read_pos.get();
+        loop {
+            let Some((record, data)) = self.next_record(start, Direction::Received) else {
+                return 0; // eof
+            };
+            let remaining = &data[offset..];
+            if remaining.is_empty() {
+                start = record + 2 + data.len();
+                offset = 0;
+                continue;
+            }
+
+            let n = remaining.len().min(buf.len());
+            buf[..n].copy_from_slice(&remaining[..n]);
+            self.read_pos.set((record, offset + n));
+            return n;
+        }
+    }
+
+    fn replay_write(&self, buf: &[u8]) {
+        let (mut start, mut offset) = self.write_pos.get();
+        for byte in buf {
+            let expected = loop {
+                let Some((record, data)) = self.next_record(start, Direction::Sent) else {
+                    break None;
+                };
+                start = record;
+                if let Some(expected) = data.get(offset) {
+                    offset += 1;
+                    break Some(*expected);
+                }
+                start = record + 2 + data.len();
+                offset = 0;
+            };
+            if expected != Some(*byte) {
+                self.mismatches.set(self.mismatches.get() + 1);
+            }
+        }
+        self.write_pos.set((start, offset));
+    }
+}
+
+impl ErrorType for &ReplayTransport<'_> {
+    type Error = Infallible;
+}
+
+impl Read for &ReplayTransport<'_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Ok(self.replay_read(buf))
+    }
+}
+
+impl ReadReady for &ReplayTransport<'_> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(true) // either data or eof is always available
+    }
+}
+
+impl Write for &ReplayTransport<'_> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.replay_write(buf);
+        Ok(buf.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Capture, RecordingTransport, ReplayTransport};
+    use crate::mock::{MockSps30, NoDelay};
+    use crate::Sps30;
+    use futures::executor::block_on;
+
+    #[test]
+    fn replay_recorded_session() {
+        let mock = MockSps30::new().with_chunk_size(7);
+        let mut transport = RecordingTransport::new(&mock, &mock, Capture::<1024>::new());
+        let recorded = {
+            let (tx, rx) = transport.split();
+            let mut sensor = block_on(Sps30::from_tx_rx(tx, rx, NoDelay)).unwrap();
+            block_on(sensor.read_measurement()).unwrap()
+        };
+
+        let capture = transport.sink();
+        assert!(!capture.truncated());
+        let replay = ReplayTransport::new(capture.as_bytes());
+        let mut sensor = block_on(Sps30::from_tx_rx(&replay, &replay, NoDelay)).unwrap();
+        let replayed = block_on(sensor.read_measurement()).unwrap();
+        assert_eq!(recorded.mass_pm10, replayed.mass_pm10);
+        assert_eq!(replay.mismatches(), 0);
+    }
+}
@@ -0,0 +1,180 @@
+//! Evenly spaced measurements, see [`Sps30::sample_every`].
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+use crate::power::Clock;
+use crate::shdlc::{FrameObserver, ProtocolLogger};
+use crate::{Error, MaybeFormat, Measurement, Sps30, Stats};
+
+/// Time to transfer one byte at 115200 baud with a start and stop bit
+const BYTE_TIME_NS: u64 = 10 * 1_000_000_000 / 115_200;
+
+/// Reads a measurement every interval, created by [`Sps30::sample_every`].
+/// Call [`next`](Self::next) in a loop.
+///
+/// With a [clock](Self::with_clock) every sample is due exactly one
+/// interval after the previous, so samples do not drift apart. Without
+/// one the time each read takes is estimated from the bytes sent and
+/// received, [`DelayNs`] offers no clock, and subtracted from the wait
+/// before the next. That misses the time the sensor takes to respond and
+/// the time the caller spends between calls to `next`, so samples slowly
+/// drift apart.
+pub struct Sampler<'a, Tx, Rx, D, O = (), L = ()> {
+    sensor: &'a mut Sps30<Tx, Rx, D, O, L>,
+    interval_ms: u32,
+    clock: Option<&'a dyn Clock>,
+    /// Estimated duration of the previous read, `None` before the first
+    last_read_ns: Option<u64>,
+    /// Time the next sample is due by the clock, `None` before the first
+    due_ms: Option<u64>,
+}
+
+impl<'a, Tx, Rx, D, O, L> Sampler<'a, Tx, Rx, D, O, L>
+where
+    Tx: Write,
+    Tx::Error: MaybeFormat,
+    Rx: Read,
+    Rx::Error: MaybeFormat,
+    D: DelayNs,
+    O: FrameObserver,
+    L: ProtocolLogger,
+{
+    pub(crate) fn new(sensor: &'a mut Sps30<Tx, Rx, D, O, L>, interval_ms: u32) -> Self {
+        Self {
+            sensor,
+            interval_ms,
+            clock: None,
+            last_read_ns: None,
+            due_ms: None,
+        }
+    }
+
+    /// Times the samples using `clock`, in milliseconds. Samples are then
+    /// spaced exactly, whatever the reads or the caller take. Samples
+    /// missed because the caller was late are skipped.
+    #[must_use]
+    pub fn with_clock(mut self, clock: &'a dyn Clock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Waits until the next sample is due then reads it. The first sample
+    /// is read right away.
+    ///
+    /// # Errors
+    /// See [`Sps30::read_measurement`]. A failed read does not change the
+    /// pacing, the next call waits for the following interval.
+    pub async fn next(&mut self) -> Result<Measurement, Error<Tx::Error, Rx::Error>> {
+        if let Some(clock) = self.clock {
+            let now_ms = clock.now_ms();
+            let due_ms = self.due_ms.unwrap_or(now_ms);
+            delay(
+                self.sensor.delay(),
+                due_ms.saturating_sub(now_ms) * 1_000_000,
+            )
+            .await;
+            self.due_ms = Some(due_ms.max(now_ms) + u64::from(self.interval_ms));
+            return self.sensor.read_measurement().await;
+        }
+
+        if let Some(last_read_ns) = self.last_read_ns {
+            let interval_ns = u64::from(self.interval_ms) * 1_000_000;
+            let wait_ns = interval_ns.saturating_sub(last_read_ns);
+            delay(self.sensor.delay(), wait_ns).await;
+        }
+
+        let before = self.sensor.stats();
+        let measurement = self.sensor.read_measurement().await;
+        self.last_read_ns = Some(transfer_time_ns(before, self.sensor.stats()));
+        measurement
+    }
+
+    /// The driver, for other commands in between samples
+    pub fn sensor(&mut self) -> &mut Sps30<Tx, Rx, D, O, L> {
+        self.sensor
+    }
+}
+
+fn transfer_time_ns(before: Stats, after: Stats) -> u64 {
+    let bytes_in = after.bytes_in.wrapping_sub(before.bytes_in);
+    let bytes_out = after.bytes_out.wrapping_sub(before.bytes_out);
+    (u64::from(bytes_in) + u64::from(bytes_out)) * BYTE_TIME_NS
+}
+
+/// [`DelayNs::delay_ns`] takes at most ~4.3 seconds
+async fn delay(delay: &mut impl DelayNs, mut ns: u64) {
+    while ns > 0 {
+        let step = u32::try_from(ns).unwrap_or(u32::MAX);
+        delay.delay_ns(step).await;
+        ns -= u64::from(step);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::cell::Cell;
+
+    use embedded_hal_async::delay::DelayNs;
+    use futures::executor::block_on;
+
+    use super::BYTE_TIME_NS;
+    use crate::mock::MockSps30;
+    use crate::Sps30;
+
+    #[derive(Default)]
+    struct TotalDelay(u64);
+
+    impl DelayNs for TotalDelay {
+        async fn delay_ns(&mut self, ns: u32) {
+            self.0 += u64::from(ns);
+        }
+    }
+
+    #[test]
+    fn compensates_read_time() {
+        let mock = MockSps30::new();
+        let mut sensor = block_on(Sps30::from_tx_rx(&mock, &mock, TotalDelay::default())).unwrap();
+        let waited_before = sensor.delay().0;
+        sensor.reset_stats();
+
+        let mut sampler = sensor.sample_every(5_000);
+        for _ in 0..3 {
+            block_on(sampler.next()).unwrap();
+        }
+        let stats = sampler.sensor().stats();
+        let per_read = u64::from(stats.bytes_in + stats.bytes_out) / 3;
+
+        let waited = sensor.delay().0 - waited_before;
+        assert_eq!(waited, 2 * (5_000_000_000 - per_read * BYTE_TIME_NS));
+    }
+
+    /// Advances a shared clock by the time waited
+    struct ClockDelay<'a>(&'a Cell<u64>);
+
+    impl DelayNs for ClockDelay<'_> {
+        async fn delay_ns(&mut self, ns: u32) {
+            self.0.set(self.0.get() + u64::from(ns));
+        }
+    }
+
+    #[test]
+    fn keeps_to_clock() {
+        let now_ns = Cell::new(0);
+        let clock = || now_ns.get() / 1_000_000;
+        let mock = MockSps30::new();
+        let mut sensor = block_on(Sps30::from_tx_rx(&mock, &mock, ClockDelay(&now_ns))).unwrap();
+        let start_ms = clock();
+
+        let mut sampler = sensor.sample_every(5_000).with_clock(&clock);
+        let mut sampled_at = std::vec::Vec::new();
+        for work_ms in [700, 700, 6_000, 0] {
+            block_on(sampler.next()).unwrap();
+            sampled_at.push(clock() - start_ms);
+            // the caller handling the sample
+            now_ns.set(now_ns.get() + work_ms * 1_000_000);
+        }
+        // the third sample took too long to handle, the fourth is late
+        assert_eq!(sampled_at, [0, 5_000, 10_000, 16_000]);
+    }
+}
@@ -0,0 +1,336 @@
+//! Low power sampling for battery or solar powered devices: the sensor
+//! sleeps between samples while the MCU waits for an RTC alarm. Without
+//! an alarm use a [`LowRateSampler`], it waits using the driver's delay.
+//!
+//! ```ignore
+//! struct RtcAlarm<'a>(&'a mut Rtc);
+//!
+//! impl Alarm for RtcAlarm<'_> {
+//!     async fn wait(&mut self) {
+//!         self.0.set_alarm_in(Duration::from_secs(15 * 60));
+//!         self.0.wait_for_alarm().await // the MCU sleeps in here
+//!     }
+//! }
+//!
+//! let mut scheduler = Scheduler::new(RtcAlarm(&mut rtc));
+//! loop {
+//!     let measurement = scheduler.sample(&mut sensor).await?;
+//!     radio.send(&measurement).await;
+//! }
+//! ```
+
+use core::future::Future;
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+use crate::shdlc::{FrameObserver, ProtocolLogger};
+use crate::{DeviceError, Error, MaybeFormat, Measurement, Sps30};
+
+/// Time the sensor needs after starting before readings are stable, see
+/// [`Scheduler::with_warmup`]
+pub const DEFAULT_WARMUP_MS: u32 = 30_000;
+
+/// How [`Sps30::measure_once`] takes its measurement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OneShot {
+    /// Time between starting the measurement and the first read, see
+    /// [`Scheduler::with_warmup`]
+    pub warmup_ms: u32,
+    /// Number of measurements averaged, read one second apart. At least
+    /// one is read.
+    pub samples: u8,
+}
+
+impl Default for OneShot {
+    /// A single measurement after a [`DEFAULT_WARMUP_MS`] warmup
+    fn default() -> Self {
+        Self {
+            warmup_ms: DEFAULT_WARMUP_MS,
+            samples: 1,
+        }
+    }
+}
+
+/// Source of the wake up signal between samples, usually an RTC alarm
+/// supplied by the user.
+pub trait Alarm {
+    /// Waits until the next sample is due. The MCU may sleep in here.
+    fn wait(&mut self) -> impl Future<Output = ()>;
+}
+
+impl<A: Alarm> Alarm for &mut A {
+    fn wait(&mut self) -> impl Future<Output = ()> {
+        A::wait(self)
+    }
+}
+
+/// Takes one sample per alarm, keeping the sensor asleep in between.
+pub struct Scheduler<A> {
+    alarm: A,
+    warmup_ms: u32,
+}
+
+impl<A: Alarm> Scheduler<A> {
+    /// Samples on every alarm using a [`DEFAULT_WARMUP_MS`] warmup
+    pub fn new(alarm: A) -> Self {
+        Self {
+            alarm,
+            warmup_ms: DEFAULT_WARMUP_MS,
+        }
+    }
+
+    /// Time between starting the measurement and reading it. The datasheet
+    /// lists 8 to 30 seconds depending on the particle concentration,
+    /// shorter saves power at the cost of accuracy.
+    #[must_use]
+    pub fn with_warmup(mut self, ms: u32) -> Self {
+        self.warmup_ms = ms;
+        self
+    }
+
+    /// Waits for the alarm, wakes the sensor, lets it warm up, reads a
+    /// measurement and puts the sensor back to sleep.
+    ///
+    /// The sensor may be asleep or idle when this is called, it is left
+    /// asleep even if taking the sample failed.
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    pub async fn sample<Tx, Rx, D, O, L>(
+        &mut self,
+        sensor: &mut Sps30<Tx, Rx, D, O, L>,
+    ) -> Result<Measurement, Error<Tx::Error, Rx::Error>>
+    where
+        Tx: Write,
+        Tx::Error: MaybeFormat,
+        Rx: Read,
+        Rx::Error: MaybeFormat,
+        D: DelayNs,
+        O: FrameObserver,
+        L: ProtocolLogger,
+    {
+        self.alarm.wait().await;
+        match sensor.wake_up().await {
+            // not asleep, for example on the first sample
+            Ok(())
+            | Err(
+                Error::Device(DeviceError::InvalidStateForCommand) | Error::WrongDriverState { .. },
+            ) => (),
+            Err(e) => return Err(e),
+        }
+
+        let measurement = sensor
+            .measure_once(OneShot {
+                warmup_ms: self.warmup_ms,
+                samples: 1,
+            })
+            .await;
+        sensor.sleep().await?;
+        measurement
+    }
+}
+
+/// What the sensor does between samples of a [`LowRateSampler`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rest {
+    /// Stop measuring, the fan and laser are off
+    Idle,
+    /// Sleep, uses the least power. Requires firmware 2.0 or newer.
+    Sleep,
+}
+
+/// Samples every few minutes, resting the sensor in between. Keeping the
+/// fan running for minutes between samples wastes power and wears it out.
+/// The sensor is restarted a warmup before each sample is due.
+///
+/// Waits using the delay of the driver, with an RTC alarm use a
+/// [`Scheduler`] so the MCU can sleep too.
+#[derive(Debug, Clone)]
+pub struct LowRateSampler {
+    interval_ms: u32,
+    warmup_ms: u32,
+    rest: Rest,
+    first: bool,
+}
+
+impl LowRateSampler {
+    /// A sample every `interval_ms`, sleeping in between with a
+    /// [`DEFAULT_WARMUP_MS`] warmup
+    #[must_use]
+    pub fn new(interval_ms: u32) -> Self {
+        Self {
+            interval_ms,
+            warmup_ms: DEFAULT_WARMUP_MS,
+            rest: Rest::Sleep,
+            first: true,
+        }
+    }
+
+    /// Time between restarting the sensor and reading it, see
+    /// [`Scheduler::with_warmup`]
+    #[must_use]
+    pub fn with_warmup(mut self, ms: u32) -> Self {
+        self.warmup_ms = ms;
+        self
+    }
+
+    /// What the sensor does between samples, sleeps by default
+    #[must_use]
+    pub fn with_rest(mut self, rest: Rest) -> Self {
+        self.rest = rest;
+        self
+    }
+
+    /// Waits until the next sample is due, starting the sensor a warmup
+    /// before, then reads it and lets the sensor rest. The first sample is
+    /// taken after just the warmup.
+    ///
+    /// The sensor may be asleep or idle when this is first called.
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    pub async fn next<Tx, Rx, D, O, L>(
+        &mut self,
+        sensor: &mut Sps30<Tx, Rx, D, O, L>,
+    ) -> Result<Measurement, Error<Tx::Error, Rx::Error>>
+    where
+        Tx: Write,
+        Tx::Error: MaybeFormat,
+        Rx: Read,
+        Rx::Error: MaybeFormat,
+        D: DelayNs,
+        O: FrameObserver,
+        L: ProtocolLogger,
+    {
+        if !self.first {
+            let rest_ms = self.interval_ms.saturating_sub(self.warmup_ms);
+            sensor.delay().delay_ms(rest_ms).await;
+        }
+        self.first = false;
+
+        match sensor.wake_up().await {
+            // resting idle or the first sample
+            Ok(())
+            | Err(
+                Error::Device(DeviceError::InvalidStateForCommand) | Error::WrongDriverState { .. },
+            ) => (),
+            Err(e) => return Err(e),
+        }
+        let measurement = sensor
+            .measure_once(OneShot {
+                warmup_ms: self.warmup_ms,
+                samples: 1,
+            })
+            .await;
+        if self.rest == Rest::Sleep {
+            sensor.sleep().await?;
+        }
+        measurement
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Alarm, LowRateSampler, OneShot, Rest, Scheduler};
+    use crate::mock::{MockSps30, NoDelay};
+    use crate::{DeviceError, Error, ProtocolError, Sps30};
+    use futures::executor::block_on;
+
+    struct Count(usize);
+
+    impl Alarm for Count {
+        async fn wait(&mut self) {
+            self.0 += 1;
+        }
+    }
+
+    #[test]
+    fn sleeps_between_samples() {
+        let mock = MockSps30::new();
+        let mut sensor = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        let mut alarm = Count(0);
+        let mut scheduler = Scheduler::new(&mut alarm);
+        for _ in 0..2 {
+            let measurement = block_on(scheduler.sample(&mut sensor)).unwrap();
+            assert_eq!(measurement.mass_pm10, mock.measurement().mass_pm10);
+            assert!(mock.is_sleeping());
+            assert!(!mock.is_measuring());
+        }
+
+        // only a sensor that is already awake may fail to wake up
+        mock.fail_next(0x28);
+        assert_eq!(
+            block_on(scheduler.sample(&mut sensor)).unwrap_err(),
+            Error::Device(DeviceError::InternalOutOfRange)
+        );
+        assert_eq!(alarm.0, 3);
+    }
+
+    #[test]
+    fn low_rate_sampler() {
+        let mock = MockSps30::new();
+        let mut sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+        block_on(sensor.stop_measurement()).unwrap();
+
+        for rest in [Rest::Sleep, Rest::Idle] {
+            let mut sampler = LowRateSampler::new(5 * 60_000).with_rest(rest);
+            for _ in 0..2 {
+                let measurement = block_on(sampler.next(&mut sensor)).unwrap();
+                assert_eq!(measurement.mass_pm10, mock.measurement().mass_pm10);
+                assert!(!mock.is_measuring());
+                assert_eq!(mock.is_sleeping(), rest == Rest::Sleep);
+            }
+        }
+
+        let mut sampler = LowRateSampler::new(5 * 60_000);
+        block_on(sensor.sleep()).unwrap();
+        mock.fail_next(0x28);
+        assert_eq!(
+            block_on(sampler.next(&mut sensor)).unwrap_err(),
+            Error::Device(DeviceError::InternalOutOfRange)
+        );
+    }
+
+    #[test]
+    fn measure_once() {
+        let mock = MockSps30::new();
+        let mut sensor = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        let settings = OneShot {
+            warmup_ms: 8_000,
+            samples: 3,
+        };
+        let measurement = block_on(sensor.measure_once(settings)).unwrap();
+        let expected = mock.measurement();
+        assert!((measurement.mass_pm10 - expected.mass_pm10).abs() < 1e-4);
+        assert!(!mock.is_measuring());
+        // start, three reads and stop
+        assert_eq!(mock.commands_received(), 5);
+    }
+
+    #[test]
+    fn measure_once_short_warmup() {
+        let settings = OneShot {
+            warmup_ms: 0,
+            samples: 1,
+        };
+        let mock = MockSps30::new().with_warmup(2);
+        let mut sensor = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        let measurement = block_on(sensor.measure_once(settings)).unwrap();
+        assert!((measurement.mass_pm10 - mock.measurement().mass_pm10).abs() < 1e-4);
+        // start, two empty reads, a read and stop
+        assert_eq!(mock.commands_received(), 5);
+
+        let mock = MockSps30::new().with_warmup(u32::MAX);
+        let mut sensor = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        assert_eq!(
+            block_on(sensor.measure_once(settings)).unwrap_err(),
+            Error::Protocol(ProtocolError::EmptyResult)
+        );
+        assert!(!mock.is_measuring());
+    }
+}
@@ -0,0 +1,121 @@
+//! Result of [`Sps30::self_test`](crate::Sps30::self_test), meant to run
+//! at boot to catch wiring or sensor faults before relying on the
+//! readings.
+
+use core::fmt;
+
+use crate::{DeviceInfo, DeviceStatus, Error, MaybeFormat, Measurement, Version};
+
+/// Product type every SPS30 reports
+pub(crate) const PRODUCT_TYPE: &str = "00080000";
+/// Oldest firmware with a device status register
+pub(crate) const STATUS_REGISTER_FIRMWARE: Version = Version { major: 2, minor: 2 };
+
+/// Outcome of every step of the self test
+#[derive(Debug)]
+pub struct SelfTestReport<TxError, RxError>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    /// Serial number, product type and versions
+    pub device_info: Result<DeviceInfo, Error<TxError, RxError>>,
+    /// The device status register, `None` if the firmware is older than
+    /// 2.2 and does not have one
+    pub status_register: Option<Result<DeviceStatus, Error<TxError, RxError>>>,
+    /// A single measurement
+    pub measurement: Result<Measurement, Error<TxError, RxError>>,
+}
+
+/// First problem found by the self test, see [`SelfTestReport::fault`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Fault {
+    /// Could not read the device information, usually a wiring problem
+    NoDeviceInfo,
+    /// Something answered but it is not an SPS30
+    WrongProductType,
+    /// Could not read the status register
+    NoStatusRegister,
+    /// The fan speed is out of range
+    FanSpeed,
+    /// The laser current is out of range
+    Laser,
+    /// The fan is not running
+    Fan,
+    /// Could not read a measurement
+    NoMeasurement,
+    /// The measurement contains negative or non finite concentrations
+    ImplausibleMeasurement,
+}
+
+impl<TxError, RxError> SelfTestReport<TxError, RxError>
+where
+    TxError: MaybeFormat + fmt::Debug,
+    RxError: MaybeFormat + fmt::Debug,
+{
+    /// The first problem found, in the order the steps ran. `None` if the
+    /// sensor passed.
+    pub fn fault(&self) -> Option<Fault> {
+        match &self.device_info {
+            Err(_) => return Some(Fault::NoDeviceInfo),
+            Ok(info) if info.product_type != PRODUCT_TYPE => return Some(Fault::WrongProductType),
+            Ok(_) => (),
+        }
+        match self.status_register {
+            Some(Err(_)) => return Some(Fault::NoStatusRegister),
+            Some(Ok(status)) if status.fan_speed_warning() => return Some(Fault::FanSpeed),
+            Some(Ok(status)) if status.laser_failure() => return Some(Fault::Laser),
+            Some(Ok(status)) if status.fan_failure() => return Some(Fault::Fan),
+            Some(Ok(_)) | None => (),
+        }
+        match &self.measurement {
+            Err(_) => Some(Fault::NoMeasurement),
+            Ok(m) if !is_plausible(m) => Some(Fault::ImplausibleMeasurement),
+            Ok(_) => None,
+        }
+    }
+
+    /// Whether every step succeeded and found no problems
+    pub fn passed(&self) -> bool {
+        self.fault().is_none()
+    }
+}
+
+fn is_plausible(measurement: &Measurement) -> bool {
+    measurement
+        .mass_mg_per_m3()
+        .into_iter()
+        .chain(measurement.number_per_m3())
+        .all(|val| val.is_finite() && val >= 0.0)
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+
+    use super::Fault;
+    use crate::mock::{MockSps30, NoDelay};
+    use crate::{DeviceStatus, Sps30};
+
+    #[test]
+    fn idle_sensor_is_left_idle() {
+        let mock = MockSps30::new().with_warmup(2);
+        let mut sensor = Sps30::from_tx_rx_uninit(&mock, &mock, NoDelay);
+        let report = block_on(sensor.self_test());
+        assert_eq!(report.fault(), None);
+        assert!(report.status_register.unwrap().unwrap().is_empty());
+        assert!(!mock.is_measuring());
+    }
+
+    #[test]
+    fn reports_fan_failure() {
+        let mock = MockSps30::new().with_status_register(DeviceStatus::FAN_FAILURE.bits());
+        let mut sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+        let report = block_on(sensor.self_test());
+        assert!(report.measurement.is_ok());
+        assert_eq!(report.fault(), Some(Fault::Fan));
+        assert!(mock.is_measuring());
+    }
+}
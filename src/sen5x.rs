@@ -0,0 +1,318 @@
+//! Basic driver for the SEN5x environmental sensor node (SEN50, SEN54 and
+//! SEN55) over UART. They speak the same [SHDLC](crate::shdlc) framing as
+//! the SPS30, only the commands and payloads differ, so this driver is a
+//! thin layer over the shared [`ShdlcDevice`]. Errors are the same
+//! [`Error`] the SPS30 driver returns.
+//!
+//! Supports starting, stopping and reading measurements. Use
+//! [`Sen5x::device`] to send commands not covered here.
+//!
+//! ```ignore
+//! let mut sensor = Sen5x::new(tx, rx, delay);
+//! sensor.reset().await?;
+//! sensor.start_measurement().await?;
+//! loop {
+//!     delay.delay_ms(1000).await;
+//!     if sensor.data_ready().await? {
+//!         let measurement = sensor.read_measurement().await?;
+//!         info!("{} °C", measurement.temperature);
+//!     }
+//! }
+//! ```
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+use heapless::{String, Vec};
+
+use crate::shdlc::{FrameObserver, ProtocolLogger, ShdlcDevice};
+use crate::{until_nul, Error, MaybeFormat, ProtocolError, RawFrame};
+
+/// SEN5x SHDLC commands
+#[derive(Debug, Clone, Copy)]
+#[repr(u8)]
+enum Command {
+    StartMeasurement = 0x00,
+    StopMeasurement = 0x01,
+    ReadDataReady = 0x02,
+    ReadMeasuredValues = 0x03,
+    StartFanCleaning = 0x56,
+    DeviceInformation = 0xD0,
+    Reset = 0xD3,
+}
+
+/// Sub command of [`Command::StartMeasurement`] enabling every sensor
+const ALL_SENSORS: u8 = 0x01;
+/// Sub command of [`Command::DeviceInformation`]
+const PRODUCT_NAME: u8 = 0x01;
+/// Sub command of [`Command::DeviceInformation`]
+const SERIAL_NUMBER: u8 = 0x03;
+/// Signed values not (yet) available, for example the NOx index during
+/// its warm up or the humidity on a SEN50
+const UNKNOWN_I16: i16 = i16::MAX;
+/// Unsigned values not (yet) available
+const UNKNOWN_U16: u16 = u16::MAX;
+
+/// Measured values of a SEN5x. Values the model does not measure, or
+/// has not measured yet, are `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Measurement {
+    /// Mass concentration PM1.0 in µg/m³
+    pub mass_pm1_0: Option<f32>,
+    /// Mass concentration PM2.5 in µg/m³
+    pub mass_pm2_5: Option<f32>,
+    /// Mass concentration PM4.0 in µg/m³
+    pub mass_pm4_0: Option<f32>,
+    /// Mass concentration PM10 in µg/m³
+    pub mass_pm10: Option<f32>,
+    /// Ambient relative humidity in %
+    pub humidity: Option<f32>,
+    /// Ambient temperature in °C
+    pub temperature: Option<f32>,
+    /// VOC index, 1 to 500 with 100 being the average of the last 24
+    /// hours
+    pub voc_index: Option<f32>,
+    /// NOx index, 1 to 500 with 1 being the average of the last 24 hours
+    pub nox_index: Option<f32>,
+}
+
+impl Measurement {
+    /// Parses the payload of a read measured values response: four
+    /// unsigned and four signed big endian 16 bit integers, each scaled.
+    fn from_data(data: &[u8]) -> Option<Self> {
+        let mut words = data
+            .chunks_exact(2)
+            .map(|word| u16::from_be_bytes([word[0], word[1]]));
+        let mut unsigned = |scale: f32| {
+            let raw = words.next()?;
+            Some((raw != UNKNOWN_U16).then(|| f32::from(raw) / scale))
+        };
+        let mass_pm1_0 = unsigned(10.0)?;
+        let mass_pm2_5 = unsigned(10.0)?;
+        let mass_pm4_0 = unsigned(10.0)?;
+        let mass_pm10 = unsigned(10.0)?;
+        let mut signed = |scale: f32| {
+            #[allow(clippy::cast_possible_wrap)]
+            let raw = words.next()? as i16;
+            Some((raw != UNKNOWN_I16).then(|| f32::from(raw) / scale))
+        };
+        Some(Self {
+            mass_pm1_0,
+            mass_pm2_5,
+            mass_pm4_0,
+            mass_pm10,
+            humidity: signed(100.0)?,
+            temperature: signed(200.0)?,
+            voc_index: signed(10.0)?,
+            nox_index: signed(10.0)?,
+        })
+    }
+}
+
+/// Driver for a SEN5x, see the [module](self) documentation.
+///
+/// Take care to setup the UART with the same settings as for the SPS30:
+/// 115200 baud, 8 data bits, 1 stop bit and no parity.
+pub struct Sen5x<Tx, Rx, D, O = (), L = ()> {
+    device: ShdlcDevice<Tx, Rx, D, O, L>,
+}
+
+impl<Tx, Rx, D> Sen5x<Tx, Rx, D>
+where
+    Tx: Write,
+    Tx::Error: MaybeFormat,
+    Rx: Read,
+    Rx::Error: MaybeFormat,
+    D: DelayNs,
+{
+    /// Does not communicate with the device, take care to start the
+    /// measurement.
+    pub fn new(uart_tx: Tx, uart_rx: Rx, delay: D) -> Self {
+        Self::from_device(ShdlcDevice::new(uart_tx, uart_rx, delay))
+    }
+}
+
+impl<Tx, Rx, D, O, L> Sen5x<Tx, Rx, D, O, L>
+where
+    Tx: Write,
+    Tx::Error: MaybeFormat,
+    Rx: Read,
+    Rx::Error: MaybeFormat,
+    D: DelayNs,
+    O: FrameObserver,
+    L: ProtocolLogger,
+{
+    /// Wraps an already configured SHDLC device, for example one with an
+    /// [observer](ShdlcDevice::with_observer) or a
+    /// [timeout](ShdlcDevice::set_timeout)
+    pub fn from_device(device: ShdlcDevice<Tx, Rx, D, O, L>) -> Self {
+        Self { device }
+    }
+
+    /// The SHDLC device underneath, to send commands this driver does not
+    /// support
+    pub fn device(&mut self) -> &mut ShdlcDevice<Tx, Rx, D, O, L> {
+        &mut self.device
+    }
+
+    /// Returns the uart halves and delay
+    pub fn release(self) -> (Tx, Rx, D) {
+        self.device.release()
+    }
+
+    /// Starts measuring with every sensor of the module
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    pub async fn start_measurement(&mut self) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        self.device
+            .execute_ref(Command::StartMeasurement as u8, &[ALL_SENSORS])
+            .await?;
+        Ok(())
+    }
+
+    /// Stops measuring, the module returns to idle
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    pub async fn stop_measurement(&mut self) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        self.device
+            .execute_ref(Command::StopMeasurement as u8, &[])
+            .await?;
+        Ok(())
+    }
+
+    /// Whether a new measurement can be read, the module measures once a
+    /// second
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    pub async fn data_ready(&mut self) -> Result<bool, Error<Tx::Error, Rx::Error>> {
+        let data = self
+            .device
+            .execute_ref(Command::ReadDataReady as u8, &[])
+            .await?;
+        match data {
+            [_, ready] => Ok(*ready != 0),
+            _ => Err(Error::Protocol(ProtocolError::InvalidResponse(
+                RawFrame::new(data),
+            ))),
+        }
+    }
+
+    /// Reads the latest measurement
+    ///
+    /// # Errors
+    /// Returns [`ProtocolError::MeasurementDataTooShort`] if the response
+    /// is too short to hold a measurement.
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    pub async fn read_measurement(&mut self) -> Result<Measurement, Error<Tx::Error, Rx::Error>> {
+        let data = self
+            .device
+            .execute_ref(Command::ReadMeasuredValues as u8, &[])
+            .await?;
+        Measurement::from_data(data).ok_or(Error::Protocol(ProtocolError::MeasurementDataTooShort))
+    }
+
+    /// Runs the fan at maximum speed for 10 seconds to blow out dust. Only
+    /// works while measuring.
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    pub async fn start_fan_cleaning(&mut self) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        self.device
+            .execute_ref(Command::StartFanCleaning as u8, &[])
+            .await?;
+        Ok(())
+    }
+
+    /// The product name, for example `SEN55`
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    pub async fn product_name(&mut self) -> Result<String<32>, Error<Tx::Error, Rx::Error>> {
+        self.device_information(PRODUCT_NAME).await
+    }
+
+    /// The serial number of the device
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    pub async fn serial_number(&mut self) -> Result<String<32>, Error<Tx::Error, Rx::Error>> {
+        self.device_information(SERIAL_NUMBER).await
+    }
+
+    /// Resets the device, it stops measuring. Waits the 100ms the device
+    /// needs to restart.
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    pub async fn reset(&mut self) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        self.device.execute_ref(Command::Reset as u8, &[]).await?;
+        self.device.delay().delay_ms(100).await;
+        Ok(())
+    }
+
+    async fn device_information(
+        &mut self,
+        field: u8,
+    ) -> Result<String<32>, Error<Tx::Error, Rx::Error>> {
+        let data = self
+            .device
+            .execute_ref(Command::DeviceInformation as u8, &[field])
+            .await?;
+        let invalid = || Error::Protocol(ProtocolError::InvalidResponse(RawFrame::new(data)));
+        let text = Vec::from_slice(until_nul(data)).map_err(|()| invalid())?;
+        String::from_utf8(text).map_err(|_| invalid())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Measurement;
+
+    #[test]
+    fn parse_measurement() {
+        let data = [
+            0x00, 0x64, // pm1.0: 10.0
+            0x00, 0xc8, // pm2.5: 20.0
+            0x01, 0x2c, // pm4.0: 30.0
+            0x01, 0x90, // pm10: 40.0
+            0x13, 0x88, // humidity: 50.00
+            0xfc, 0x18, // temperature: -5.0
+            0x03, 0xe8, // voc index: 100.0
+            0x7f, 0xff, // nox index: not available yet
+        ];
+        assert_eq!(
+            Measurement::from_data(&data),
+            Some(Measurement {
+                mass_pm1_0: Some(10.0),
+                mass_pm2_5: Some(20.0),
+                mass_pm4_0: Some(30.0),
+                mass_pm10: Some(40.0),
+                humidity: Some(50.0),
+                temperature: Some(-5.0),
+                voc_index: Some(100.0),
+                nox_index: None,
+            })
+        );
+        assert_eq!(Measurement::from_data(&data[..15]), None);
+    }
+}
@@ -0,0 +1,132 @@
+//! Share one sensor between tasks. A [`SharedSps30`] can be used through
+//! a shared reference, each command holds a lock until its response has
+//! been read so commands from different tasks never interleave on the
+//! UART.
+//!
+//! ```ignore
+//! static SENSOR: StaticCell<SharedSps30<CriticalSectionRawMutex, Tx, Rx, Delay>> =
+//!     StaticCell::new();
+//! let sensor: &'static _ = SENSOR.init(SharedSps30::new(sensor));
+//!
+//! #[embassy_executor::task]
+//! async fn reader(sensor: &'static SharedSps30<..>) -> ! {
+//!     loop {
+//!         Timer::after_secs(1).await;
+//!         let measurement = sensor.read_measurement().await;
+//!     }
+//! }
+//!
+//! #[embassy_executor::task]
+//! async fn maintenance(sensor: &'static SharedSps30<..>) -> ! {
+//!     loop {
+//!         Timer::after_secs(24 * 60 * 60).await;
+//!         sensor.start_fan_cleaning().await;
+//!     }
+//! }
+//! ```
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::mutex::{Mutex, MutexGuard};
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+use crate::shdlc::{FrameObserver, ProtocolLogger};
+use crate::{DeviceStatus, Error, MaybeFormat, Measurement, Sps30};
+
+/// A driver shared between tasks, see the [module](self) documentation.
+///
+/// Commands not available here can be sent through [`lock`](Self::lock).
+pub struct SharedSps30<M: RawMutex, Tx, Rx, D, O = (), L = ()> {
+    inner: Mutex<M, Sps30<Tx, Rx, D, O, L>>,
+}
+
+impl<M, Tx, Rx, D, O, L> SharedSps30<M, Tx, Rx, D, O, L>
+where
+    M: RawMutex,
+    Tx: Write,
+    Tx::Error: MaybeFormat,
+    Rx: Read,
+    Rx::Error: MaybeFormat,
+    D: DelayNs,
+    O: FrameObserver,
+    L: ProtocolLogger,
+{
+    /// Shares an initialized driver
+    pub fn new(sensor: Sps30<Tx, Rx, D, O, L>) -> Self {
+        Self {
+            inner: Mutex::new(sensor),
+        }
+    }
+
+    /// Exclusive access to the driver, other tasks wait until the guard
+    /// is dropped. Keep it short, for example to run a few commands that
+    /// must not be interrupted.
+    pub async fn lock(&self) -> MutexGuard<'_, M, Sps30<Tx, Rx, D, O, L>> {
+        self.inner.lock().await
+    }
+
+    /// Returns the driver once no task needs it anymore
+    pub fn into_inner(self) -> Sps30<Tx, Rx, D, O, L> {
+        self.inner.into_inner()
+    }
+
+    /// See [`Sps30::read_measurement`]
+    ///
+    /// # Errors
+    /// See [`Sps30::read_measurement`]
+    pub async fn read_measurement(&self) -> Result<Measurement, Error<Tx::Error, Rx::Error>> {
+        self.lock().await.read_measurement().await
+    }
+
+    /// See [`Sps30::read_device_status_register`]
+    ///
+    /// # Errors
+    /// See [`Sps30::read_device_status_register`]
+    pub async fn read_device_status_register(
+        &self,
+        clear: bool,
+    ) -> Result<DeviceStatus, Error<Tx::Error, Rx::Error>> {
+        self.lock().await.read_device_status_register(clear).await
+    }
+
+    /// See [`Sps30::start_fan_cleaning`]
+    ///
+    /// # Errors
+    /// See [`Sps30::start_fan_cleaning`]
+    pub async fn start_fan_cleaning(&self) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        self.lock().await.start_fan_cleaning().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use futures::executor::block_on;
+    use futures::join;
+
+    use super::SharedSps30;
+    use crate::mock::{MockSps30, NoDelay};
+    use crate::{DeviceStatus, Sps30};
+
+    #[test]
+    fn serializes_commands_of_tasks() {
+        let mock = MockSps30::new();
+        let sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+        let shared = SharedSps30::<NoopRawMutex, _, _, _>::new(sensor);
+        let before = mock.commands_received();
+
+        let reader = async {
+            for _ in 0..3 {
+                shared.read_measurement().await.unwrap();
+            }
+        };
+        let maintenance = async {
+            shared.start_fan_cleaning().await.unwrap();
+            shared.read_device_status_register(false).await.unwrap()
+        };
+        let ((), status) = block_on(async { join!(reader, maintenance) });
+        assert_eq!(status, DeviceStatus::default());
+        assert_eq!(mock.commands_received() - before, 5);
+        assert!(mock.is_measuring());
+    }
+}
@@ -0,0 +1,314 @@
+//! The SHDLC framing shared by Sensirion's UART sensors (SPS30, SVM41,
+//! SFA30, STC31, ...). Frames are surrounded by [`FRAME_BOUNDARY_MARKER`]
+//! and special bytes inside are escaped.
+//!
+//! A decoded MOSI frame (sent to the device) looks like:
+//!
+//! ```text
+//!  ADR     CMD       Length    TX Data          CHK
+//!  1 Byte  1 Byte    1 Byte    0...255 bytes    1 Byte
+//! ```
+//!
+//! A decoded MISO frame (sent by the device) has an extra state byte:
+//!
+//! ```text
+//!  ADR     CMD       State    Length    RX Data          CHK
+//!  1 Byte  1 Byte    1 Byte   1 Byte    0...255 bytes    1 Byte
+//! ```
+
+use heapless::Vec;
+
+mod assembler;
+mod decoder;
+pub(crate) mod device;
+mod error;
+pub use assembler::{Frame, FrameAssembler};
+pub use decoder::Decoder;
+#[cfg(feature = "defmt")]
+pub use device::DefmtLogger;
+pub use device::{
+    FrameBuffer, FrameObserver, ProtocolLogger, Response, ShdlcDevice, Stats, BROADCAST_ADDRESS,
+    DEFAULT_ADDRESS, DEFAULT_RESYNC_LIMIT, MAX_DATA_LEN, MAX_REQUEST_DATA_LEN,
+};
+pub use error::Error;
+
+/// Smallest encoded frame, includes frame boundaries
+pub const MIN_FRAME_SIZE: usize = 6;
+/// Precedes an escaped byte
+pub const ESCAPE_MARKER: u8 = 0x7d;
+/// Start and end of every frame
+pub const FRAME_BOUNDARY_MARKER: u8 = 0x7e;
+/// (org, replacement)
+const ESCAPED: [(u8, u8); 4] = [(0x7d, 0x5d), (0x7e, 0x5e), (0x11, 0x31), (0x13, 0x33)];
+
+/// Worst case encoded size of a frame of `decoded_len` bytes: every byte
+/// escaped plus the frame boundaries.
+#[must_use]
+pub const fn max_encoded_len(decoded_len: usize) -> usize {
+    2 + 2 * decoded_len
+}
+
+/// Checksum implemented as per section 4.1 from spec. Covers every byte of
+/// a decoded frame except the checksum itself.
+#[allow(clippy::cast_lossless)]
+#[allow(clippy::cast_possible_truncation)]
+#[must_use]
+pub fn checksum(data: &[u8]) -> u8 {
+    let mut cksum: u8 = 0;
+    for &byte in data {
+        let val: u16 = cksum as u16 + byte as u16;
+        let lsb = val % 256;
+        cksum = lsb as u8;
+    }
+
+    255 - cksum
+}
+
+/// Produces escaped (encoded) message surrounded with frame boundary
+/// markers.
+///
+/// # Errors
+///
+/// If the passed `MAX_ENCODED_SIZE` is too small this returns
+/// [`Error::TooMuchData`], see [`max_encoded_len`].
+pub fn encode<const MAX_ENCODED_SIZE: usize>(
+    data: &[u8],
+) -> Result<Vec<u8, MAX_ENCODED_SIZE>, Error> {
+    if max_encoded_len(data.len()) > MAX_ENCODED_SIZE {
+        return Err(Error::TooMuchData);
+    }
+
+    let mut output = Vec::new();
+    output.push(FRAME_BOUNDARY_MARKER)?;
+    for &byte in data {
+        if let Some(replacement) = replacement(byte) {
+            output.push(ESCAPE_MARKER)?;
+            output.push(replacement)?;
+        } else {
+            output.push(byte)?;
+        }
+    }
+    output.push(FRAME_BOUNDARY_MARKER)?;
+
+    Ok(output)
+}
+
+/// Like [`encode`] but escapes on the fly, writing the frame to `writer`
+/// in small chunks. No buffer for the whole encoded frame is needed.
+/// Returns the number of bytes written.
+///
+/// # Errors
+/// Returns the error of the writer if writing fails.
+pub async fn encode_to<W: embedded_io_async::Write>(
+    data: &[u8],
+    writer: &mut W,
+) -> Result<usize, W::Error> {
+    const CHUNK_SIZE: usize = 16;
+    let mut chunk = [0u8; CHUNK_SIZE];
+    let mut len = 0;
+    let mut written = 0;
+
+    chunk[0] = FRAME_BOUNDARY_MARKER;
+    len += 1;
+    for &byte in data {
+        if len + 2 > CHUNK_SIZE {
+            writer.write_all(&chunk[..len]).await?;
+            written += len;
+            len = 0;
+        }
+        if let Some(replacement) = replacement(byte) {
+            chunk[len] = ESCAPE_MARKER;
+            chunk[len + 1] = replacement;
+            len += 2;
+        } else {
+            chunk[len] = byte;
+            len += 1;
+        }
+    }
+    if len == CHUNK_SIZE {
+        writer.write_all(&chunk).await?;
+        written += len;
+        len = 0;
+    }
+    chunk[len] = FRAME_BOUNDARY_MARKER;
+    len += 1;
+    writer.write_all(&chunk[..len]).await?;
+    Ok(written + len)
+}
+
+/// Like [`encode`] but yields the encoded bytes one at a time, escaping on
+/// the fly. For frontends that write byte by byte.
+pub fn encoded_bytes(data: &[u8]) -> impl Iterator<Item = u8> + Clone + '_ {
+    let escaped = data.iter().flat_map(|&byte| match replacement(byte) {
+        Some(replacement) => [Some(ESCAPE_MARKER), Some(replacement)],
+        None => [Some(byte), None],
+    });
+    core::iter::once(FRAME_BOUNDARY_MARKER)
+        .chain(escaped.flatten())
+        .chain(core::iter::once(FRAME_BOUNDARY_MARKER))
+}
+
+/// What `byte` is replaced with after an [`ESCAPE_MARKER`], if it needs
+/// escaping
+fn replacement(byte: u8) -> Option<u8> {
+    ESCAPED
+        .iter()
+        .find(|(org, _)| *org == byte)
+        .map(|(_, replacement)| *replacement)
+}
+
+/// Produces unescaped (decoded) message without frame boundary markers.
+///
+/// # Errors
+/// The following errors can occur while decoding:
+///
+/// - [`Error::TooMuchData`]
+/// - [`Error::FendCharInData`]
+/// - [`Error::MissingTradeChar`]
+/// - [`Error::MissingFirstFend`]
+/// - [`Error::MissingFinalFend`]
+/// - [`Error::TooFewData`]
+///
+/// See the error type documentation for more.
+pub fn decode<const MAX_DECODED_SIZE: usize>(
+    input: &[u8],
+) -> Result<Vec<u8, MAX_DECODED_SIZE>, Error> {
+    let mut output = Vec::new();
+    let mut unescape = Unescape::new(input)?;
+    while let Some(byte) = unescape.next(input)? {
+        output.push(byte)?;
+    }
+    Ok(output)
+}
+
+/// Decodes `frame` in place, without needing a second buffer. Returns the
+/// part of `frame` holding the decoded message, without frame boundary
+/// markers.
+///
+/// # Errors
+/// The same as [`decode`] except [`Error::TooMuchData`], decoding never
+/// needs more space than the encoded frame.
+pub fn decode_in_place(frame: &mut [u8]) -> Result<&mut [u8], Error> {
+    let mut unescape = Unescape::new(frame)?;
+    let mut written = 0;
+    // decoded output never overtakes the input
+    while let Some(byte) = unescape.next(frame)? {
+        frame[written] = byte;
+        written += 1;
+    }
+    Ok(&mut frame[..written])
+}
+
+/// Cursor unescaping an encoded frame one byte at a time. Takes the frame
+/// on every call so the output may be written into it.
+struct Unescape {
+    read: usize,
+    end: usize,
+}
+
+impl Unescape {
+    fn new(frame: &[u8]) -> Result<Self, Error> {
+        if frame.len() < 4 {
+            return Err(Error::TooFewData);
+        }
+        if frame[0] != FRAME_BOUNDARY_MARKER {
+            return Err(Error::MissingFirstFend);
+        }
+        let end = frame.len() - 1;
+        if frame[end] != FRAME_BOUNDARY_MARKER {
+            return Err(Error::MissingFinalFend);
+        }
+        Ok(Self { read: 1, end })
+    }
+
+    fn next(&mut self, frame: &[u8]) -> Result<Option<u8>, Error> {
+        if self.read == self.end {
+            return Ok(None);
+        }
+
+        let byte = frame[self.read];
+        self.read += 1;
+        if byte != ESCAPE_MARKER {
+            return Ok(Some(byte));
+        }
+
+        if self.read == self.end {
+            return Err(Error::MissingTradeChar);
+        }
+        let escaped_byte = frame[self.read];
+        self.read += 1;
+        let (org, _) = ESCAPED
+            .iter()
+            .find(|(_, escaped)| *escaped == escaped_byte)
+            .ok_or(Error::FendCharInData)?;
+        Ok(Some(*org))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_start_measumement() {
+        let mosi_data = [0x00, 0x00, 0x02, 0x01, 0x03, 0xf9];
+        let expected = [0x7e, 0x00, 0x00, 0x02, 0x01, 0x03, 0xf9, 0x7e];
+        let encoded: Vec<u8, 20> = (encode(&mosi_data)).unwrap();
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn encode_test() {
+        let mosi_data = [0x00, 0x01, 0x00, 0xfe];
+        let expected = [0x7e, 0x00, 0x01, 0x00, 0xfe, 0x7e];
+        let encoded: Vec<u8, 15> = (encode(&mosi_data)).unwrap();
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn decode_test() {
+        let expected = [0x00, 0x01, 0x00, 0xfe];
+        let mosi_data = [0x7e, 0x00, 0x01, 0x00, 0xfe, 0x7e];
+        let encoded: Vec<u8, 10> = (decode(&mosi_data)).unwrap();
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn escaping_roundtrip() {
+        let data = [0x00, 0x7e, 0x7d, 0x11, 0x13, 0x42];
+        let encoded: Vec<u8, { max_encoded_len(6) }> = (encode(&data)).unwrap();
+        assert_eq!(
+            encoded,
+            [0x7e, 0x00, 0x7d, 0x5e, 0x7d, 0x5d, 0x7d, 0x31, 0x7d, 0x33, 0x42, 0x7e]
+        );
+        let decoded: Vec<u8, 6> = (decode(&encoded)).unwrap();
+        assert_eq!(decoded, data);
+
+        let mut in_place = encoded.clone();
+        assert_eq!(decode_in_place(&mut in_place).unwrap(), data);
+    }
+
+    #[test]
+    fn encode_to_writer() {
+        struct Collect(Vec<u8, 128>);
+        impl embedded_io_async::ErrorType for Collect {
+            type Error = core::convert::Infallible;
+        }
+        impl embedded_io_async::Write for Collect {
+            async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+                self.0.extend_from_slice(buf).unwrap();
+                Ok(buf.len())
+            }
+        }
+
+        for len in 0..40 {
+            let data: Vec<u8, 40> = (0..len).map(|i| [0x7e, 0x11, 7][i % 3]).collect();
+            let expected: Vec<u8, 82> = encode(&data).unwrap();
+            let mut writer = Collect(Vec::new());
+            let written = futures::executor::block_on(encode_to(&data, &mut writer)).unwrap();
+            assert_eq!(writer.0, expected);
+            assert_eq!(written, expected.len());
+            assert!(encoded_bytes(&data).eq(expected));
+        }
+    }
+}
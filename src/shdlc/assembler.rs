@@ -0,0 +1,93 @@
+use heapless::Vec;
+
+use super::device::MAX_DECODED_FRAME_SIZE;
+use super::Decoder;
+
+/// A decoded frame, without escaping or boundary markers. Not yet
+/// validated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame(Vec<u8, MAX_DECODED_FRAME_SIZE>);
+
+impl Frame {
+    /// The decoded bytes, including header and checksum
+    #[must_use]
+    pub fn bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Assembles frames one byte at a time, for calling from a uart receive
+/// interrupt or a DMA completion handler. Hand the frames to the
+/// application, for example through a queue, and check them with
+/// [`ResponseReader::feed_frame`](crate::protocol::ResponseReader::feed_frame).
+///
+/// Does not allocate or block, each byte takes a few instructions.
+/// Malformed frames are dropped and counted.
+///
+/// ```
+/// use sps30_async::shdlc::FrameAssembler;
+///
+/// let mut assembler = FrameAssembler::new();
+/// let mut frames = [0x7e, 0, 3, 0x7d, 0x5e, 0x7e]
+///     .into_iter()
+///     .filter_map(|byte| assembler.push_byte(byte));
+/// assert_eq!(frames.next().unwrap().bytes(), [0, 3, 0x7e]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FrameAssembler {
+    decoder: Decoder<MAX_DECODED_FRAME_SIZE>,
+    malformed: u32,
+}
+
+impl FrameAssembler {
+    /// Waiting for the start of a frame
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            decoder: Decoder::new(),
+            malformed: 0,
+        }
+    }
+
+    /// Adds a received byte, returns the frame it completes if any
+    pub fn push_byte(&mut self, byte: u8) -> Option<Frame> {
+        match self.decoder.push(&mut &[byte][..])? {
+            Ok(frame) => Some(Frame(
+                Vec::from_slice(frame).expect("decoder has the same capacity"),
+            )),
+            Err(_) => {
+                self.malformed = self.malformed.wrapping_add(1);
+                None
+            }
+        }
+    }
+
+    /// Drop any partial frame, for example after a uart error
+    pub fn reset(&mut self) {
+        self.decoder.reset();
+    }
+
+    /// Number of malformed frames dropped, wraps around on overflow
+    #[must_use]
+    pub fn malformed(&self) -> u32 {
+        self.malformed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FrameAssembler;
+    use crate::shdlc::FRAME_BOUNDARY_MARKER as FB;
+
+    #[test]
+    fn counts_malformed() {
+        let mut assembler = FrameAssembler::new();
+        let frames: heapless::Vec<_, 4> = [FB, 1, 0x7d, 0x00, 2, FB, FB, 5, FB]
+            .into_iter()
+            .filter_map(|byte| assembler.push_byte(byte))
+            .collect();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].bytes(), [5]);
+        assert_eq!(assembler.malformed(), 1);
+    }
+}
@@ -0,0 +1,171 @@
+use heapless::Vec;
+
+use super::{Error, ESCAPED, ESCAPE_MARKER, FRAME_BOUNDARY_MARKER};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Waiting for a frame boundary, bytes are noise
+    Idle,
+    InFrame,
+    /// Previous byte was an [`ESCAPE_MARKER`]
+    Escaped,
+    /// A frame was just returned, it is cleared on the next byte
+    Complete,
+}
+
+/// Incremental SHDLC decoder, unescapes bytes as they arrive. Only needs
+/// space for the decoded frame (`N` bytes), no encoded copy is kept. Bytes
+/// can be fed from an interrupt handler or straight from a uart read.
+///
+/// ```
+/// use sps30_async::shdlc::Decoder;
+///
+/// let mut decoder = Decoder::<8>::new();
+/// let mut bytes: &[u8] = &[0x7e, 1, 0x7d, 0x5e];
+/// assert_eq!(decoder.push(&mut bytes), None);
+/// let mut bytes: &[u8] = &[2, 0x7e];
+/// assert_eq!(decoder.push(&mut bytes), Some(Ok(&[1, 0x7e, 2][..])));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Decoder<const N: usize> {
+    frame: Vec<u8, N>,
+    state: State,
+}
+
+impl<const N: usize> Default for Decoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Decoder<N> {
+    /// A decoder waiting for the start of a frame
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            frame: Vec::new(),
+            state: State::Idle,
+        }
+    }
+
+    /// Drop any partial frame and wait for the next frame boundary
+    pub fn reset(&mut self) {
+        self.frame.clear();
+        self.state = State::Idle;
+    }
+
+    /// Feeds bytes from the front of `bytes` until a frame completes or
+    /// `bytes` runs out. Consumed bytes are removed from `bytes`, call again
+    /// while it is not empty to get any following frames.
+    ///
+    /// Returns the decoded frame without boundary markers. The frame is
+    /// not validated beyond the escaping.
+    ///
+    /// # Errors
+    /// Malformed frames are reported once and then skipped:
+    ///
+    /// - [`Error::TooMuchData`] the frame does not fit in `N` bytes
+    /// - [`Error::FendCharInData`] an escape marker followed by a byte that
+    ///   is not an escaped character
+    /// - [`Error::MissingTradeChar`] an escape marker directly before a
+    ///   frame boundary
+    pub fn push(&mut self, bytes: &mut &[u8]) -> Option<Result<&[u8], Error>> {
+        while let [byte, rest @ ..] = *bytes {
+            *bytes = rest;
+            match self.push_byte(*byte) {
+                Ok(true) => return Some(Ok(&self.frame)),
+                Ok(false) => (),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+
+    /// Returns true if `byte` completed a frame
+    fn push_byte(&mut self, byte: u8) -> Result<bool, Error> {
+        if self.state == State::Complete {
+            self.frame.clear();
+            self.state = State::InFrame;
+        }
+
+        match (self.state, byte) {
+            (State::Idle, FRAME_BOUNDARY_MARKER) => {
+                self.state = State::InFrame;
+                Ok(false)
+            }
+            (State::Idle, _) => Ok(false),
+            // consecutive boundaries, the last one starts the frame
+            (State::InFrame, FRAME_BOUNDARY_MARKER) if self.frame.is_empty() => Ok(false),
+            (State::InFrame, FRAME_BOUNDARY_MARKER) => {
+                self.state = State::Complete;
+                Ok(true)
+            }
+            (State::InFrame, ESCAPE_MARKER) => {
+                self.state = State::Escaped;
+                Ok(false)
+            }
+            (State::InFrame, _) => self.store(byte),
+            (State::Escaped, FRAME_BOUNDARY_MARKER) => {
+                // the boundary might start the next frame
+                self.frame.clear();
+                self.state = State::InFrame;
+                Err(Error::MissingTradeChar)
+            }
+            (State::Escaped, _) => {
+                let Some((org, _)) = ESCAPED.iter().find(|(_, escaped)| *escaped == byte) else {
+                    self.reset();
+                    return Err(Error::FendCharInData);
+                };
+                self.state = State::InFrame;
+                self.store(*org)
+            }
+            (State::Complete, _) => unreachable!("handled above"),
+        }
+    }
+
+    fn store(&mut self, byte: u8) -> Result<bool, Error> {
+        if self.frame.push(byte).is_err() {
+            self.reset();
+            return Err(Error::TooMuchData);
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Decoder;
+    use crate::shdlc::{encode, max_encoded_len, Error, FRAME_BOUNDARY_MARKER as FB};
+    use heapless::Vec;
+
+    #[test]
+    fn byte_by_byte() {
+        let data = [0, 0x7e, 0x11, 3, 0x7d, 0x13];
+        let encoded: Vec<u8, { max_encoded_len(6) }> = encode(&data).unwrap();
+        let mut decoder = Decoder::<6>::new();
+        let (last, rest) = encoded.split_last().unwrap();
+        for byte in rest {
+            assert_eq!(decoder.push(&mut &[*byte][..]), None);
+        }
+        assert_eq!(decoder.push(&mut &[*last][..]), Some(Ok(&data[..])));
+    }
+
+    #[test]
+    fn noise_and_multiple_frames() {
+        let mut bytes: &[u8] = &[3, 4, FB, 1, 2, FB, FB, 5, FB, 6];
+        let mut decoder = Decoder::<4>::new();
+        assert_eq!(decoder.push(&mut bytes), Some(Ok(&[1, 2][..])));
+        assert_eq!(decoder.push(&mut bytes), Some(Ok(&[5][..])));
+        assert_eq!(decoder.push(&mut bytes), None);
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn recovers_from_errors() {
+        let mut bytes: &[u8] = &[FB, 1, 2, 3, 4, 5, FB, 0x7d, FB, 7, FB];
+        let mut decoder = Decoder::<4>::new();
+        assert_eq!(decoder.push(&mut bytes), Some(Err(Error::TooMuchData)));
+        assert_eq!(decoder.push(&mut bytes), Some(Err(Error::MissingTradeChar)));
+        assert_eq!(decoder.push(&mut bytes), Some(Ok(&[7][..])));
+    }
+}
@@ -0,0 +1,1249 @@
+use core::fmt;
+use core::future::{pending, poll_fn, Future};
+use core::pin::{pin, Pin};
+use core::task::Poll;
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, ReadReady, Write};
+use heapless::Vec;
+
+use super::{encode_to, max_encoded_len, FRAME_BOUNDARY_MARKER};
+use crate::protocol::{Event, Request, ResponseReader};
+use crate::recording::Direction;
+use crate::watchdog::WatchdogEvent;
+use crate::{Error, MaybeFormat, Mode, ProtocolError, TransportError};
+
+/// Largest data payload [`ShdlcDevice`] can receive
+pub const MAX_DATA_LEN: usize = 10 * core::mem::size_of::<f32>();
+/// Largest data payload [`ShdlcDevice`] can send
+pub const MAX_REQUEST_DATA_LEN: usize = 5;
+/// header (address, command, state, length), data and checksum
+pub(crate) const MAX_DECODED_FRAME_SIZE: usize = 4 + MAX_DATA_LEN + 1;
+pub(crate) const MAX_ENCODED_FRAME_SIZE: usize = max_encoded_len(MAX_DECODED_FRAME_SIZE);
+/// Address used by devices unless configured otherwise
+pub const DEFAULT_ADDRESS: u8 = 0;
+/// Reserved for broadcasts, no device answers requests sent to it
+pub const BROADCAST_ADDRESS: u8 = 0xFF;
+/// Bytes read while looking for a response before giving up, unless
+/// configured otherwise. Room for a few stale frames or some line noise.
+pub const DEFAULT_RESYNC_LIMIT: usize = 4 * MAX_ENCODED_FRAME_SIZE;
+
+/// Buffer a decoded response is placed in, see
+/// [`ShdlcDevice::execute_in`]
+#[derive(Debug, Default)]
+pub struct FrameBuffer(Vec<u8, MAX_DECODED_FRAME_SIZE>);
+
+impl FrameBuffer {
+    /// An empty buffer
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+}
+
+/// A validated response from the device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    frame: Vec<u8, MAX_DECODED_FRAME_SIZE>,
+}
+
+impl Response {
+    /// A validated decoded frame
+    pub(crate) fn new(frame: &[u8]) -> Self {
+        Self {
+            frame: Vec::from_slice(frame).expect("frames are at most MAX_DECODED_FRAME_SIZE"),
+        }
+    }
+
+    /// The address of the device that sent this
+    #[must_use]
+    pub fn address(&self) -> u8 {
+        self.frame[0]
+    }
+
+    /// The command this is a response to
+    #[must_use]
+    pub fn command(&self) -> u8 {
+        self.frame[1]
+    }
+
+    /// The whole decoded frame including header and checksum
+    #[must_use]
+    pub fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    /// The data payload
+    #[must_use]
+    pub fn data(&self) -> &[u8] {
+        payload(&self.frame)
+    }
+}
+
+/// Communication statistics, use these to monitor the health of the link.
+/// All counters wrap around on overflow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Stats {
+    /// Valid frames received, including device error responses
+    pub frames_received: u32,
+    /// Frames received with an invalid checksum
+    pub checksum_failures: u32,
+    /// Partial or outdated frames discarded while looking for a response
+    pub resyncs: u32,
+    /// Requests that failed because no response arrived within the
+    /// [timeout](ShdlcDevice::set_timeout)
+    pub timeouts: u32,
+    /// Requests that failed because no frame was found within the
+    /// [resync limit](ShdlcDevice::set_resync_limit)
+    pub resync_limit_hits: u32,
+    /// Bytes read from the uart
+    pub bytes_in: u32,
+    /// Bytes written to the uart
+    pub bytes_out: u32,
+    /// Requests sent again after a recoverable error, see
+    /// [`ShdlcDevice::set_retries`]
+    pub retries: u32,
+}
+
+/// Sees every frame passing through a [`ShdlcDevice`], for protocol
+/// analyzers, black-box recorders or custom logging. Use
+/// [`recording`](crate::recording) to capture the raw uart traffic
+/// instead.
+pub trait FrameObserver {
+    /// Called with every frame sent ([`Direction::Sent`]) and received
+    /// ([`Direction::Received`]). Frames are passed decoded, without
+    /// escaping or boundary markers. Received frames are passed before they
+    /// are validated.
+    fn on_frame(&mut self, direction: Direction, frame: &[u8]);
+}
+
+/// Does nothing, the default observer
+impl FrameObserver for () {
+    fn on_frame(&mut self, _: Direction, _: &[u8]) {}
+}
+
+impl<O: FrameObserver> FrameObserver for &mut O {
+    fn on_frame(&mut self, direction: Direction, frame: &[u8]) {
+        (**self).on_frame(direction, frame);
+    }
+}
+
+/// Told about every step of the request/response cycle of a
+/// [`ShdlcDevice`], for diagnostics through defmt, log, RTT or a custom
+/// black-box recorder. All methods do nothing by default, implement the
+/// ones you need. Use [`FrameObserver`] to see the raw frames instead.
+pub trait ProtocolLogger {
+    /// Called before `data` is sent as `command` to the device at
+    /// `address`. Retries call this again.
+    fn on_command(&mut self, _address: u8, _command: u8, _data: &[u8]) {}
+
+    /// Called with the data of a validated response to `command`
+    fn on_response(&mut self, _command: u8, _data: &[u8]) {}
+
+    /// Called when bytes that are not the response to `command` are
+    /// skipped, for example line noise or a stale frame.
+    fn on_resync(&mut self, _command: u8) {}
+
+    /// Called when an attempt at `command` failed. Retries call this for
+    /// every failed attempt.
+    fn on_error<TxError, RxError>(&mut self, _command: u8, _error: &Error<TxError, RxError>)
+    where
+        TxError: MaybeFormat + fmt::Debug,
+        RxError: MaybeFormat + fmt::Debug,
+    {
+    }
+
+    /// Called when the [`Watchdog`](crate::watchdog::Watchdog) acts
+    fn on_watchdog(&mut self, _event: WatchdogEvent) {}
+
+    /// Called when the mode the driver believes the sensor is in changes,
+    /// see [`Sps30::current_mode`](crate::Sps30::current_mode)
+    fn on_mode_change(&mut self, _mode: Option<Mode>) {}
+}
+
+/// Does nothing, the default logger
+impl ProtocolLogger for () {}
+
+impl<L: ProtocolLogger> ProtocolLogger for &mut L {
+    fn on_command(&mut self, address: u8, command: u8, data: &[u8]) {
+        (**self).on_command(address, command, data);
+    }
+
+    fn on_response(&mut self, command: u8, data: &[u8]) {
+        (**self).on_response(command, data);
+    }
+
+    fn on_resync(&mut self, command: u8) {
+        (**self).on_resync(command);
+    }
+
+    fn on_error<TxError, RxError>(&mut self, command: u8, error: &Error<TxError, RxError>)
+    where
+        TxError: MaybeFormat + fmt::Debug,
+        RxError: MaybeFormat + fmt::Debug,
+    {
+        (**self).on_error(command, error);
+    }
+
+    fn on_watchdog(&mut self, event: WatchdogEvent) {
+        (**self).on_watchdog(event);
+    }
+
+    fn on_mode_change(&mut self, mode: Option<Mode>) {
+        (**self).on_mode_change(mode);
+    }
+}
+
+/// Logs every step at debug level, and errors at warn level, through
+/// defmt
+#[cfg(feature = "defmt")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefmtLogger;
+
+#[cfg(feature = "defmt")]
+impl ProtocolLogger for DefmtLogger {
+    fn on_command(&mut self, address: u8, command: u8, data: &[u8]) {
+        defmt::debug!(
+            "sending command {=u8:#x} to {=u8}: {=[u8]}",
+            command,
+            address,
+            data
+        );
+    }
+
+    fn on_response(&mut self, command: u8, data: &[u8]) {
+        defmt::debug!("response to {=u8:#x}: {=[u8]}", command, data);
+    }
+
+    fn on_resync(&mut self, command: u8) {
+        defmt::debug!("skipped bytes waiting for response to {=u8:#x}", command);
+    }
+
+    fn on_error<TxError, RxError>(&mut self, command: u8, error: &Error<TxError, RxError>)
+    where
+        TxError: MaybeFormat + fmt::Debug,
+        RxError: MaybeFormat + fmt::Debug,
+    {
+        defmt::warn!("command {=u8:#x} failed: {}", command, error);
+    }
+
+    fn on_watchdog(&mut self, event: WatchdogEvent) {
+        defmt::warn!("watchdog: {}", event);
+    }
+
+    fn on_mode_change(&mut self, mode: Option<Mode>) {
+        defmt::debug!("sensor mode: {}", mode);
+    }
+}
+
+/// Progress of the current request. Stored in the device so a request
+/// future that is dropped (cancelled) halfway does not confuse the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pending {
+    Idle,
+    /// The request might be partially written
+    Sending,
+    /// The request was sent, its response not yet (completely) read
+    Receiving {
+        cmd: u8,
+    },
+}
+
+/// The request/response cycle common to all SHDLC devices: encode, send,
+/// read a frame, decode and validate it. Device specific drivers (like
+/// [`Sps30`](crate::Sps30)) are built on top of this.
+///
+/// All buffers needed to send and receive live inside this struct, its size
+/// is all the memory the driver uses. Requests only keep a few bytes on the
+/// stack.
+pub struct ShdlcDevice<Tx, Rx, D, O = (), L = ()> {
+    /// The concrete Serial device implementation.
+    uart_tx: Tx,
+    uart_rx: Rx,
+    delay: D,
+    address: u8,
+    resync_limit: usize,
+    timeout_ms: Option<u32>,
+    retries: u8,
+    stats: Stats,
+    observer: O,
+    logger: L,
+    /// Finds the response in the received bytes, keeps partial frames
+    /// between calls
+    reader: ResponseReader,
+    /// Uart reads land here, kept in the driver so requests use little
+    /// stack. Responses are decoded as they arrive so any size works, a
+    /// whole frame fits most responses in one read.
+    rx_chunk: [u8; MAX_ENCODED_FRAME_SIZE],
+    /// The last validated response
+    response: FrameBuffer,
+    pending: Pending,
+    /// Set if pending bytes are discarded before every request
+    drain_before_send: Option<ReadReadyFn<Rx>>,
+}
+
+impl<Tx, Rx, D> ShdlcDevice<Tx, Rx, D>
+where
+    Tx: Write,
+    Tx::Error: MaybeFormat,
+    Rx: Read,
+    Rx::Error: MaybeFormat,
+    D: DelayNs,
+{
+    /// Does not communicate with the device. Uses [`DEFAULT_ADDRESS`].
+    pub fn new(uart_tx: Tx, uart_rx: Rx, delay: D) -> Self {
+        Self {
+            uart_tx,
+            uart_rx,
+            delay,
+            address: DEFAULT_ADDRESS,
+            resync_limit: DEFAULT_RESYNC_LIMIT,
+            timeout_ms: None,
+            retries: 0,
+            stats: Stats::default(),
+            observer: (),
+            logger: (),
+            reader: ResponseReader::default(),
+            rx_chunk: [0; MAX_ENCODED_FRAME_SIZE],
+            response: FrameBuffer::new(),
+            pending: Pending::Idle,
+            drain_before_send: None,
+        }
+    }
+}
+
+impl<Tx, Rx, D, O, L> ShdlcDevice<Tx, Rx, D, O, L>
+where
+    Tx: Write,
+    Tx::Error: MaybeFormat,
+    Rx: Read,
+    Rx::Error: MaybeFormat,
+    D: DelayNs,
+    O: FrameObserver,
+    L: ProtocolLogger,
+{
+    /// Passes every frame sent and received to `observer`, replacing the
+    /// current observer.
+    pub fn with_observer<O2: FrameObserver>(self, observer: O2) -> ShdlcDevice<Tx, Rx, D, O2, L> {
+        ShdlcDevice {
+            uart_tx: self.uart_tx,
+            uart_rx: self.uart_rx,
+            delay: self.delay,
+            address: self.address,
+            resync_limit: self.resync_limit,
+            timeout_ms: self.timeout_ms,
+            retries: self.retries,
+            stats: self.stats,
+            observer,
+            logger: self.logger,
+            reader: self.reader,
+            rx_chunk: self.rx_chunk,
+            response: self.response,
+            pending: self.pending,
+            drain_before_send: self.drain_before_send,
+        }
+    }
+
+    /// The observer set with [`with_observer`](Self::with_observer)
+    pub fn observer(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Reports every command, response, resync and error to `logger`,
+    /// replacing the current logger.
+    pub fn with_logger<L2: ProtocolLogger>(self, logger: L2) -> ShdlcDevice<Tx, Rx, D, O, L2> {
+        ShdlcDevice {
+            uart_tx: self.uart_tx,
+            uart_rx: self.uart_rx,
+            delay: self.delay,
+            address: self.address,
+            resync_limit: self.resync_limit,
+            timeout_ms: self.timeout_ms,
+            retries: self.retries,
+            stats: self.stats,
+            observer: self.observer,
+            logger,
+            reader: self.reader,
+            rx_chunk: self.rx_chunk,
+            response: self.response,
+            pending: self.pending,
+            drain_before_send: self.drain_before_send,
+        }
+    }
+
+    /// The logger set with [`with_logger`](Self::with_logger)
+    pub fn logger(&mut self) -> &mut L {
+        &mut self.logger
+    }
+
+    /// Like [`logger`](Self::logger) but only needs a shared reference
+    pub fn logger_ref(&self) -> &L {
+        &self.logger
+    }
+
+    /// Returns the uart halves and delay
+    pub fn release(self) -> (Tx, Rx, D) {
+        (self.uart_tx, self.uart_rx, self.delay)
+    }
+
+    /// The SHDLC address of the device, for multi-drop setups such as
+    /// several devices on one RS-485 bus.
+    pub fn set_address(&mut self, address: u8) {
+        self.address = address;
+    }
+
+    /// The SHDLC address requests are sent to
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Maximum number of bytes read while looking for a response frame.
+    /// Once exceeded the request fails with [`ProtocolError::InvalidFrame`]
+    /// instead of waiting forever on a noisy line or a wrong baud rate.
+    /// Defaults to [`DEFAULT_RESYNC_LIMIT`].
+    pub fn set_resync_limit(&mut self, bytes: usize) {
+        self.resync_limit = bytes;
+    }
+
+    /// Give up waiting for a response after `ms` milliseconds, measured
+    /// with the delay provider. The request then fails with
+    /// [`ProtocolError::Timeout`]. A response arriving later is skipped like
+    /// one to a cancelled request. Without a timeout (the default) a
+    /// request waits as long as the uart does.
+    pub fn set_timeout(&mut self, ms: Option<u32>) {
+        self.timeout_ms = ms;
+    }
+
+    /// Number of times a request is sent again when it fails with a
+    /// [recoverable](Error::is_recoverable) error, for example a corrupted
+    /// response. Defaults to 0, requests are not retried.
+    pub fn set_retries(&mut self, retries: u8) {
+        self.retries = retries;
+    }
+
+    /// Communication statistics since construction or the last
+    /// [`reset_stats`](Self::reset_stats)
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Set all communication statistics to zero
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+    }
+
+    /// The delay provider passed in on construction
+    pub fn delay(&mut self) -> &mut D {
+        &mut self.delay
+    }
+
+    /// Forgets a cancelled request and its partially read response, the
+    /// next request neither finishes nor skips it. For when a different
+    /// device is connected to the uart, for example behind a mux.
+    pub(crate) fn forget_pending(&mut self) {
+        self.pending = Pending::Idle;
+        self.reader.reset();
+    }
+
+    /// Sends command `cmd` with `data` then waits for and validates the
+    /// response.
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors. Passing more then
+    /// [`MAX_REQUEST_DATA_LEN`] bytes of data returns
+    /// [`ProtocolError::FrameTooLarge`].
+    pub async fn execute(
+        &mut self,
+        cmd: u8,
+        data: &[u8],
+    ) -> Result<Response, Error<Tx::Error, Rx::Error>> {
+        self.transact(cmd, data).await?;
+        Ok(Response {
+            frame: self.response.0.clone(),
+        })
+    }
+
+    /// Like [`execute`](Self::execute) but the response is copied into
+    /// `buf`. Returns the validated data payload.
+    ///
+    /// # Errors
+    /// See [`execute`](Self::execute)
+    pub async fn execute_in<'b>(
+        &mut self,
+        cmd: u8,
+        data: &[u8],
+        buf: &'b mut FrameBuffer,
+    ) -> Result<&'b [u8], Error<Tx::Error, Rx::Error>> {
+        self.transact(cmd, data).await?;
+        buf.0.clone_from(&self.response.0);
+        Ok(payload(&buf.0))
+    }
+
+    /// Like [`execute`](Self::execute) but returns the validated data
+    /// payload straight from the buffer inside the device. Nothing is
+    /// copied and no frame sized buffer is needed on the stack.
+    ///
+    /// # Errors
+    /// See [`execute`](Self::execute)
+    pub async fn execute_ref(
+        &mut self,
+        cmd: u8,
+        data: &[u8],
+    ) -> Result<&[u8], Error<Tx::Error, Rx::Error>> {
+        self.transact(cmd, data).await?;
+        Ok(payload(&self.response.0))
+    }
+
+    /// Writes `bytes` to the uart as is, without framing. Some devices need
+    /// this, for example a byte to wake the uart from sleep.
+    ///
+    /// # Errors
+    /// Returns [`TransportError::Write`] if writing fails.
+    pub async fn write_raw(&mut self, bytes: &[u8]) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        self.uart_tx
+            .write_all(bytes)
+            .await
+            .map_err(|e| Error::Transport(TransportError::Write(e)))?;
+        count(&mut self.stats.bytes_out, bytes.len());
+        self.uart_tx
+            .flush()
+            .await
+            .map_err(|e| Error::Transport(TransportError::Write(e)))
+    }
+
+    /// Sends a request, retrying as configured, and places the validated
+    /// decoded response frame in `self.response`.
+    async fn transact(&mut self, cmd: u8, data: &[u8]) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        let mut attempts_left = self.retries;
+        loop {
+            let result = self.transact_once(cmd, data).await;
+            if let Err(e) = &result {
+                self.logger.on_error(cmd, e);
+            }
+            match result {
+                Err(e) if e.is_recoverable() && attempts_left > 0 => {
+                    debug!("request failed, retrying: {}", e);
+                    telemetry!(WARN, command = cmd, attempts_left, error = ?e, "retrying request");
+                    attempts_left -= 1;
+                    count(&mut self.stats.retries, 1);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Sends a request and places the validated decoded response frame in
+    /// `self.response`.
+    ///
+    /// This is cancel safe: if the future is dropped the progress is kept in
+    /// `self.pending` and the partially read response in `self.reader`.
+    /// The next request then skips the response to the cancelled one.
+    /// A request that timed out is treated the same, its response may
+    /// still arrive and must not answer the retry or the next request.
+    ///
+    /// If a request that skipped times out the skipped response was
+    /// probably its own, the abandoned request was never answered, so the
+    /// next request does not skip again.
+    async fn transact_once(
+        &mut self,
+        cmd: u8,
+        data: &[u8],
+    ) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        let request = Request::new(self.address, cmd, data)
+            .map_err(|_| Error::Protocol(ProtocolError::FrameTooLarge))?;
+
+        match self.pending {
+            Pending::Idle => (),
+            Pending::Sending => {
+                debug!("previous request was cancelled while sending, terminating it");
+                self.uart_tx
+                    .write_all(&[FRAME_BOUNDARY_MARKER])
+                    .await
+                    .map_err(|e| Error::Transport(TransportError::Write(e)))?;
+            }
+            Pending::Receiving { .. } => (),
+        }
+        if let Some(read_ready) = self.drain_before_send {
+            self.discard_pending(read_ready).await?;
+        }
+        let cancelled = self.pending;
+        self.reader.expect(&request);
+        let skip = cancelled == (Pending::Receiving { cmd });
+        if skip {
+            debug!("previous request was abandoned, skipping its response");
+            telemetry!(
+                DEBUG,
+                command = cmd,
+                "skipping response to cancelled request"
+            );
+            // responses to other commands are skipped anyway
+            self.reader.skip_next();
+        }
+
+        self.pending = Pending::Sending;
+        self.logger.on_command(self.address, cmd, data);
+        self.send(&request).await?;
+        self.pending = Pending::Receiving { cmd };
+
+        let received = self.receive(cmd).await;
+        let timed_out = matches!(
+            received,
+            Err(Error::Protocol(ProtocolError::Timeout { .. }))
+        );
+        if !timed_out || skip {
+            self.pending = Pending::Idle;
+        }
+        received
+    }
+
+    /// Send the request through serial interface
+    #[inline(always)]
+    async fn send(&mut self, request: &Request) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        let written = encode_to(request.frame(), &mut self.uart_tx)
+            .await
+            .map_err(|e| Error::Transport(TransportError::Write(e)))?;
+        count(&mut self.stats.bytes_out, written);
+        self.observer.on_frame(Direction::Sent, request.frame());
+        self.uart_tx
+            .flush()
+            .await
+            .map_err(|e| Error::Transport(TransportError::Write(e)))
+    }
+
+    /// Reads until the response to the request arrives, validates it and
+    /// places it in `self.response`. Frames for other devices or commands
+    /// are skipped.
+    #[inline(always)]
+    async fn receive(&mut self, cmd: u8) -> Result<(), Error<Tx::Error, Rx::Error>> {
+        let mut scanned = 0usize;
+        let (delay, timeout_ms) = (&mut self.delay, self.timeout_ms);
+        let mut timer = pin!(async move {
+            match timeout_ms {
+                Some(ms) => delay.delay_ms(ms).await,
+                None => pending().await,
+            }
+        });
+        loop {
+            trace!("waiting to receive bytes");
+            let read = self.uart_rx.read(&mut self.rx_chunk);
+            let Some(read) = until(read, timer.as_mut()).await else {
+                let elapsed_ms = timeout_ms.unwrap_or_default();
+                debug!("no response to {} within {} ms", cmd, elapsed_ms);
+                telemetry!(WARN, command = cmd, elapsed_ms, "request timed out");
+                count(&mut self.stats.timeouts, 1);
+                return Err(Error::Protocol(ProtocolError::Timeout {
+                    command: cmd,
+                    elapsed_ms,
+                }));
+            };
+            let n = read.map_err(|e| {
+                self.reader.reset();
+                Error::Transport(TransportError::Read(e))
+            })?;
+            if n == 0 {
+                self.reader.reset();
+                return Err(Error::Transport(TransportError::Eof));
+            }
+            count(&mut self.stats.bytes_in, n);
+            scanned = scanned.saturating_add(n);
+
+            let mut bytes = &self.rx_chunk[..n];
+            let (stats, observer, logger) = (&mut self.stats, &mut self.observer, &mut self.logger);
+            let received = self.reader.feed_with(&mut bytes, |event| match event {
+                Event::Frame(frame) => observer.on_frame(Direction::Received, frame),
+                Event::Skipped => {
+                    telemetry!(DEBUG, command = cmd, "resynchronizing, skipped a frame");
+                    logger.on_resync(cmd);
+                    count(&mut stats.resyncs, 1)
+                }
+            });
+            match received {
+                Some(Ok(response)) => {
+                    count(&mut self.stats.frames_received, 1);
+                    self.response.0 = response.frame;
+                    self.logger.on_response(cmd, payload(&self.response.0));
+                    return Ok(());
+                }
+                Some(Err(e)) => {
+                    match e {
+                        Error::Protocol(ProtocolError::ChecksumFailed(_)) => {
+                            count(&mut self.stats.checksum_failures, 1)
+                        }
+                        Error::Protocol(ProtocolError::InvalidResponse(_)) => (),
+                        _ => count(&mut self.stats.frames_received, 1),
+                    }
+                    return Err(e);
+                }
+                None => (),
+            }
+
+            if scanned > self.resync_limit {
+                debug!("no frame found in {} bytes, giving up", scanned);
+                telemetry!(WARN, command = cmd, scanned, "no frame found, giving up");
+                count(&mut self.stats.resync_limit_hits, 1);
+                self.reader.reset();
+                return Err(Error::Protocol(ProtocolError::InvalidFrame));
+            }
+        }
+    }
+
+    /// Used by the builder which can not require [`ReadReady`], see
+    /// [`set_drain_before_send`](Self::set_drain_before_send)
+    pub(crate) fn set_drain_before_send_with(&mut self, read_ready: Option<ReadReadyFn<Rx>>) {
+        self.drain_before_send = read_ready;
+    }
+
+    /// Reads and discards bytes for as long as `read_ready` reports some
+    /// are waiting. Drops any partially received frame unless it could be
+    /// the response to a cancelled request.
+    async fn discard_pending(
+        &mut self,
+        read_ready: ReadReadyFn<Rx>,
+    ) -> Result<usize, Error<Tx::Error, Rx::Error>> {
+        let mut discarded = 0usize;
+        let mut answered = false;
+        while read_ready(&mut self.uart_rx) {
+            let n = self
+                .uart_rx
+                .read(&mut self.rx_chunk)
+                .await
+                .map_err(|e| Error::Transport(TransportError::Read(e)))?;
+            if n == 0 {
+                break;
+            }
+            count(&mut self.stats.bytes_in, n);
+            discarded = discarded.saturating_add(n);
+
+            if let Pending::Receiving { .. } = self.pending {
+                let mut bytes = &self.rx_chunk[..n];
+                while !bytes.is_empty() {
+                    let response = self.reader.feed::<Tx::Error, Rx::Error>(&mut bytes);
+                    answered |= response.is_some();
+                }
+            }
+        }
+        // otherwise the response to the cancelled request is still on its
+        // way, keep what arrived so the next request can skip it
+        if answered {
+            self.pending = Pending::Idle;
+        }
+        if !matches!(self.pending, Pending::Receiving { .. }) {
+            self.reader.reset();
+        }
+        if discarded > 0 {
+            debug!("discarded {} pending bytes", discarded);
+        }
+        Ok(discarded)
+    }
+}
+
+impl<Tx, Rx, D, O, L> ShdlcDevice<Tx, Rx, D, O, L>
+where
+    Tx: Write,
+    Tx::Error: MaybeFormat,
+    Rx: Read + ReadReady,
+    Rx::Error: MaybeFormat,
+    D: DelayNs,
+    O: FrameObserver,
+    L: ProtocolLogger,
+{
+    /// Discards every byte already received but not yet read, for
+    /// example stale or corrupted data.
+    /// Returns the number of bytes discarded.
+    ///
+    /// Requests skip stale data on their own, use this to start from a
+    /// clean slate before a critical command. A response to a cancelled
+    /// request that has not arrived yet is still skipped by the next
+    /// request.
+    ///
+    /// # Errors
+    /// Returns [`TransportError::Read`] if reading from the uart fails.
+    pub async fn drain_rx(&mut self) -> Result<usize, Error<Tx::Error, Rx::Error>> {
+        self.discard_pending(read_ready::<Rx>).await
+    }
+
+    /// Discard pending received bytes, like [`drain_rx`](Self::drain_rx),
+    /// right before sending each request. Prevents a buffered response,
+    /// for example to a cancelled request, from being mistaken for the
+    /// answer to the next one. Costs a uart readiness check per request.
+    /// Disabled by default.
+    pub fn set_drain_before_send(&mut self, enabled: bool) {
+        self.set_drain_before_send_with(enabled.then_some(read_ready::<Rx>));
+    }
+}
+
+/// [`ReadReady::read_ready`] without the error type so it can be stored
+/// for any uart. A failing uart is reported by the read that follows.
+pub(crate) type ReadReadyFn<Rx> = fn(&mut Rx) -> bool;
+
+pub(crate) fn read_ready<Rx: ReadReady>(uart_rx: &mut Rx) -> bool {
+    uart_rx.read_ready().unwrap_or(false)
+}
+
+/// Runs `future` unless `timer` finishes first, then returns `None`
+async fn until<F: Future>(
+    future: F,
+    mut timer: Pin<&mut impl Future<Output = ()>>,
+) -> Option<F::Output> {
+    let mut future = pin!(future);
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        timer.as_mut().poll(cx).map(|()| None)
+    })
+    .await
+}
+
+/// The data of a validated response frame
+fn payload(frame: &[u8]) -> &[u8] {
+    &frame[4..frame.len() - 1]
+}
+
+/// Adds `n` to a [`Stats`] counter, wrapping around on overflow
+#[allow(clippy::cast_possible_truncation)] // wrapping is documented
+fn count(counter: &mut u32, n: usize) {
+    *counter = counter.wrapping_add(n as u32);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FrameObserver, ProtocolLogger, ShdlcDevice, MAX_ENCODED_FRAME_SIZE};
+    use crate::mock::{MockSps30, NoDelay};
+    use crate::protocol::{Command, Request};
+    use crate::recording::Direction;
+    use crate::shdlc::{checksum, encode, encode_to, FRAME_BOUNDARY_MARKER as FB};
+    use crate::{
+        CleaningInterval, Error, ErrorKind, MaybeFormat, Measurement, MeasurementFormat,
+        ProtocolError, Sps30, TransportError,
+    };
+    use core::cell::Cell;
+    use core::convert::Infallible;
+    use core::future::{pending, Future};
+    use core::task::Context;
+    use embedded_hal_async::delay::DelayNs;
+    use embedded_io_async::{ErrorType, Read, ReadReady, Write};
+    use futures::executor::block_on;
+    use futures::pin_mut;
+    use heapless::Vec;
+
+    /// Returns `data` in chunks of the given sizes, then EOF
+    struct ScriptedRx {
+        data: Vec<u8, 512>,
+        chunks: &'static [usize],
+    }
+
+    impl ErrorType for ScriptedRx {
+        type Error = Infallible;
+    }
+
+    impl Read for ScriptedRx {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let chunk = match self.chunks {
+                [chunk, rest @ ..] => {
+                    self.chunks = rest;
+                    *chunk
+                }
+                [] => usize::MAX,
+            };
+            let n = chunk.min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data.rotate_left(n);
+            self.data.truncate(self.data.len() - n);
+            Ok(n)
+        }
+    }
+
+    struct Sink;
+
+    impl ErrorType for Sink {
+        type Error = Infallible;
+    }
+
+    impl Write for Sink {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            Ok(buf.len())
+        }
+    }
+
+    fn response(cmd: u8, data: &[u8]) -> Vec<u8, MAX_ENCODED_FRAME_SIZE> {
+        let mut frame: Vec<u8, 64> = Vec::new();
+        #[allow(clippy::cast_possible_truncation)]
+        frame
+            .extend_from_slice(&[0, cmd, 0, data.len() as u8])
+            .unwrap();
+        frame.extend_from_slice(data).unwrap();
+        frame.push(checksum(&frame)).unwrap();
+        encode(&frame).unwrap()
+    }
+
+    fn device(parts: &[&[u8]], chunks: &'static [usize]) -> ShdlcDevice<Sink, ScriptedRx, NoDelay> {
+        let mut data = Vec::new();
+        for part in parts {
+            data.extend_from_slice(part).unwrap();
+        }
+        ShdlcDevice::new(Sink, ScriptedRx { data, chunks }, NoDelay)
+    }
+
+    #[test]
+    fn skips_noise_and_other_frames() {
+        let mut device = device(
+            &[
+                &[1, 2, FB, 3],
+                &response(0x80, &[9]),
+                &response(3, &[0x7e, 0x11]),
+            ],
+            &[1, 1, 1, 5],
+        );
+        let response = block_on(device.execute(3, &[])).unwrap();
+        assert_eq!(response.data(), [0x7e, 0x11]);
+        assert_eq!(device.stats().resyncs, 2);
+    }
+
+    #[test]
+    fn byte_by_byte() {
+        let mut device = device(&[&response(3, &[1, 2, 3])], &[1; 16]);
+        let response = block_on(device.execute(3, &[])).unwrap();
+        assert_eq!(response.data(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn frames_split_over_reads() {
+        // the first read ends with the stale frame and the start of the
+        // response, the second holds the rest
+        let stale = response(0x80, &[9]);
+        assert_eq!(stale.len(), 8);
+        let mut device = device(&[&stale, &response(3, &[1, 2, 3])], &[9]);
+        let response = block_on(device.execute(3, &[])).unwrap();
+        assert_eq!(response.data(), [1, 2, 3]);
+        assert_eq!(device.stats().resyncs, 1);
+    }
+
+    #[test]
+    fn last_frame_split() {
+        // noise, then the response split over two reads
+        let full = response(3, &[1, 2, 3]);
+        assert_eq!(full.len(), 10);
+        let mut device = device(
+            &[&[12, 13, 14, 15, 16, 17, 18, 19, 20, 21], &full],
+            &[7, 9, 4],
+        );
+        let response = block_on(device.execute(3, &[])).unwrap();
+        assert_eq!(response.data(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn two_frames_in_one_read() {
+        let mut device = device(&[&response(4, &[7]), &response(3, &[42])], &[]);
+        let response = block_on(device.execute(3, &[])).unwrap();
+        assert_eq!(response.data(), [42]);
+        assert_eq!(device.stats().resyncs, 1);
+    }
+
+    #[test]
+    fn huge_read() {
+        // noise and a long stale frame before the response, all read at once
+        let mut device = device(
+            &[
+                &[0xff; 200],
+                &response(0x80, &[9; 40]),
+                &response(3, &[1, 2, 3]),
+            ],
+            &[],
+        );
+        let response = block_on(device.execute(3, &[])).unwrap();
+        assert_eq!(response.data(), [1, 2, 3]);
+        assert_eq!(device.stats().resyncs, 1);
+        assert_eq!(device.stats().frames_received, 1);
+    }
+
+    #[test]
+    fn eof_on_noise() {
+        // the end of a frame, noise framed by boundary markers and a frame
+        // for another command, then the uart closes
+        let mut device = device(
+            &[
+                &[2, 3, 4, 5, 6, 7, 8, FB],
+                &[20, 21, 22, FB, 1, 2, 3, 4, 5],
+                &[6, FB, 25, 26, 27],
+            ],
+            &[8, 9, 5],
+        );
+        assert_eq!(
+            block_on(device.execute(3, &[])).unwrap_err(),
+            Error::Transport(TransportError::Eof)
+        );
+        assert_eq!(device.stats().resyncs, 2);
+    }
+
+    #[test]
+    fn eof_mid_frame() {
+        let full = response(3, &[1, 2, 3]);
+        let mut device = device(&[&full[..5]], &[]);
+        assert_eq!(
+            block_on(device.execute(3, &[])).unwrap_err(),
+            Error::Transport(TransportError::Eof)
+        );
+    }
+
+    #[test]
+    fn gives_up_on_garbage() {
+        let mut device = device(&[&[1; 30], &response(3, &[])], &[7; 8]);
+        device.set_resync_limit(20);
+        assert_eq!(
+            block_on(device.execute(3, &[])).unwrap_err(),
+            Error::Protocol(ProtocolError::InvalidFrame)
+        );
+        assert_eq!(device.stats().resync_limit_hits, 1);
+        assert_eq!(device.stats().timeouts, 0);
+    }
+
+    #[test]
+    fn retries_corrupted_response() {
+        // one read per frame, anything read after a response is dropped
+        let corrupted: Vec<u8, 16> = encode(&[0, 3, 0, 1, 42, 0]).unwrap();
+        assert_eq!(corrupted.len(), 8);
+        let mut device = device(&[&corrupted, &response(3, &[42])], &[8]);
+        device.set_retries(1);
+        let response = block_on(device.execute(3, &[])).unwrap();
+        assert_eq!(response.data(), [42]);
+        assert_eq!(device.stats().checksum_failures, 1);
+        assert_eq!(device.stats().retries, 1);
+    }
+
+    /// Finishes at once, or if a count is set only that many more times
+    /// then never
+    struct Expire<'a>(&'a Cell<Option<u32>>);
+
+    impl DelayNs for Expire<'_> {
+        async fn delay_ns(&mut self, _ns: u32) {
+            match self.0.get() {
+                None => (),
+                Some(0) => pending().await,
+                Some(n) => self.0.set(Some(n - 1)),
+            }
+        }
+    }
+
+    #[test]
+    fn late_response_to_timed_out_request() {
+        const READ: u8 = Command::ReadMeasuredData as u8;
+        // byte by byte, so a late response is not read along with the next one
+        let mock = MockSps30::new().with_latency(1).with_chunk_size(1);
+        let expire = Cell::new(None);
+        let mut device = ShdlcDevice::new(&mock, &mock, Expire(&expire));
+        block_on(device.execute(Command::StartMeasurement as u8, &[0x01, 0x03])).unwrap();
+
+        // the first attempt gives up before its response arrives
+        device.set_timeout(Some(100));
+        device.set_retries(1);
+        expire.set(Some(1));
+        block_on(device.execute(READ, &[])).unwrap();
+        assert_eq!(device.stats().timeouts, 1);
+        assert_eq!(device.stats().retries, 1);
+
+        // the response to the retry was read, not left for the next request
+        let mut changed = mock.measurement();
+        changed.mass_pm10 += 1.0;
+        mock.set_measurement(changed);
+        let data = block_on(device.execute_ref(READ, &[])).unwrap();
+        let measurement = Measurement::from_data(data, MeasurementFormat::Float).ok();
+        assert_eq!(measurement.map(|m| m.mass_pm10), Some(changed.mass_pm10));
+    }
+
+    #[test]
+    fn unanswered_request() {
+        const STATUS: u8 = Command::ReadDeviceStatusRegister as u8;
+        let mock = MockSps30::new().with_blocking_reads();
+        let expire = Cell::new(None);
+        let mut device = ShdlcDevice::new(&mock, &mock, Expire(&expire));
+        device.set_timeout(Some(100));
+
+        // the first retry skips its own response, the second does not
+        device.set_retries(3);
+        mock.ignore_next();
+        block_on(device.execute(STATUS, &[0])).unwrap();
+        assert_eq!(device.stats().timeouts, 2);
+        for _ in 0..5 {
+            block_on(device.execute(STATUS, &[0])).unwrap();
+        }
+        assert_eq!(device.stats().timeouts, 2);
+
+        // without retries only the request after the lost one fails
+        device.set_retries(0);
+        mock.ignore_next();
+        let timeout = Error::Protocol(ProtocolError::Timeout {
+            command: STATUS,
+            elapsed_ms: 100,
+        });
+        for _ in 0..2 {
+            assert_eq!(block_on(device.execute(STATUS, &[0])).unwrap_err(), timeout);
+        }
+        for _ in 0..5 {
+            block_on(device.execute(STATUS, &[0])).unwrap();
+        }
+    }
+
+    #[test]
+    fn observe_frames() {
+        #[derive(Default)]
+        struct Count {
+            sent: usize,
+            received: usize,
+        }
+        impl FrameObserver for Count {
+            fn on_frame(&mut self, direction: Direction, frame: &[u8]) {
+                match direction {
+                    Direction::Sent => self.sent += 1,
+                    Direction::Received => {
+                        assert_eq!(frame[1], Command::ReadMeasuredData as u8);
+                        self.received += 1;
+                    }
+                }
+            }
+        }
+
+        let mock = MockSps30::new();
+        let sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+        let mut sensor = sensor.with_observer(Count::default());
+        block_on(sensor.read_measurement()).unwrap();
+        assert_eq!(sensor.observer().sent, 1);
+        assert_eq!(sensor.observer().received, 1);
+    }
+
+    #[test]
+    fn log_protocol() {
+        #[derive(Default)]
+        struct Log {
+            commands: usize,
+            responses: usize,
+            errors: usize,
+        }
+        impl ProtocolLogger for Log {
+            fn on_command(&mut self, _: u8, _: u8, _: &[u8]) {
+                self.commands += 1;
+            }
+            fn on_response(&mut self, command: u8, data: &[u8]) {
+                assert_eq!(command, Command::ReadMeasuredData as u8);
+                assert_eq!(data.len(), 40);
+                self.responses += 1;
+            }
+            fn on_error<TxError, RxError>(&mut self, _: u8, error: &Error<TxError, RxError>)
+            where
+                TxError: MaybeFormat + core::fmt::Debug,
+                RxError: MaybeFormat + core::fmt::Debug,
+            {
+                assert_eq!(error.kind(), ErrorKind::Device);
+                self.errors += 1;
+            }
+        }
+
+        let mock = MockSps30::new();
+        let sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+        let mut sensor = sensor.with_logger(Log::default());
+        block_on(sensor.read_measurement()).unwrap();
+        mock.fail_next(0x43);
+        block_on(sensor.read_measurement()).unwrap_err();
+        assert_eq!(sensor.logger().commands, 2);
+        assert_eq!(sensor.logger().responses, 1);
+        assert_eq!(sensor.logger().errors, 1);
+    }
+
+    #[test]
+    fn flush_rx() {
+        let mock = MockSps30::new();
+        let mut sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+        assert_eq!(block_on(sensor.flush_rx()), Ok(0));
+
+        // a response nobody asked for, for example left by a bootloader
+        let request = Request::read_measurement(0);
+        let mut uart = &mock;
+        block_on(encode_to(request.frame(), &mut uart)).unwrap();
+        assert!(block_on(sensor.flush_rx()).unwrap() > 40);
+        assert!(!uart.read_ready().unwrap());
+
+        block_on(sensor.read_measurement()).unwrap();
+    }
+
+    #[test]
+    fn drain_before_command() {
+        let mock = MockSps30::new();
+        let mut sensor = block_on(
+            Sps30::builder(&mock, &mock, NoDelay)
+                .drain_before_command()
+                .build(),
+        )
+        .unwrap();
+
+        // a stale response to the same command
+        let mut uart = &mock;
+        let request = Request::read_measurement(0);
+        block_on(encode_to(request.frame(), &mut uart)).unwrap();
+        let mut changed = mock.measurement();
+        changed.mass_pm10 += 1.0;
+        mock.set_measurement(changed);
+
+        let measurement = block_on(sensor.read_measurement()).unwrap();
+        assert_eq!(measurement.mass_pm10, changed.mass_pm10);
+        assert!(sensor.stats().bytes_in > 2 * 50);
+    }
+
+    #[test]
+    fn timeout() {
+        let mock = MockSps30::new().with_latency(1);
+        let expire = Cell::new(None);
+        let sensor = block_on(Sps30::from_tx_rx(&mock, &mock, Expire(&expire))).unwrap();
+        let mut sensor = sensor.with_timeout(100);
+        let err = block_on(sensor.read_measurement()).unwrap_err();
+        assert_eq!(
+            err,
+            Error::Protocol(ProtocolError::Timeout {
+                command: Command::ReadMeasuredData as u8,
+                elapsed_ms: 100
+            })
+        );
+        assert!(err.is_recoverable());
+        assert_eq!(sensor.stats().timeouts, 1);
+
+        // the late response is not mistaken for the next one
+        expire.set(Some(0));
+        let mut changed = mock.measurement();
+        changed.mass_pm10 += 1.0;
+        mock.set_measurement(changed);
+        let measurement = block_on(sensor.read_measurement()).unwrap();
+        assert_eq!(measurement.mass_pm10, changed.mass_pm10);
+    }
+
+    #[test]
+    fn cancel_at_every_await() {
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        for polls in 0.. {
+            let mock = MockSps30::new().with_latency(1).with_chunk_size(4);
+            let mut sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+            let finished = {
+                let read = sensor.read_measurement();
+                pin_mut!(read);
+                (0..polls).any(|_| read.as_mut().poll(&mut cx).is_ready())
+            };
+            if finished {
+                break;
+            }
+
+            // the same and another command work after cancelling, the
+            // response to the cancelled read is not mistaken for a new one
+            let mut changed = mock.measurement();
+            changed.mass_pm10 += 1.0;
+            mock.set_measurement(changed);
+            let measurement = block_on(sensor.read_measurement()).unwrap();
+            assert_eq!(measurement.mass_pm10, mock.measurement().mass_pm10);
+            let interval = block_on(sensor.read_cleaning_interval()).unwrap();
+            assert_eq!(interval, CleaningInterval::DEFAULT);
+        }
+    }
+}
@@ -5,7 +5,7 @@
     feature = "postcard",
     derive(postcard::experimental::max_size::MaxSize)
 )]
-#[derive(defmt::Format)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 /// Common error for HDLC actions.
 pub enum Error {
     /// Catches duplicate special characters.   
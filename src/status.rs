@@ -0,0 +1,210 @@
+//! The device status register, see
+//! [`Sps30::read_device_status_register`](crate::Sps30::read_device_status_register).
+
+use core::fmt;
+use core::ops::{BitAnd, BitOr, BitOrAssign, Not};
+
+/// Contents of the device status register. Bits not documented by
+/// Sensirion are kept, so [`bits`](Self::bits) returns exactly what the
+/// sensor sent and nothing is lost if newer firmware sets more bits.
+///
+/// ```
+/// use sps30_async::DeviceStatus;
+///
+/// let status = DeviceStatus::from_bits(1 << 4);
+/// assert!(status.fan_failure());
+/// assert!(status.contains(DeviceStatus::FAN_FAILURE));
+/// assert_eq!(status.to_string(), "fan failure");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(transparent)
+)]
+#[cfg_attr(
+    feature = "postcard",
+    derive(postcard::experimental::max_size::MaxSize)
+)]
+pub struct DeviceStatus(u32);
+
+impl DeviceStatus {
+    /// The fan speed is out of range
+    pub const FAN_SPEED_WARNING: Self = Self(1 << 21);
+    /// The laser current is out of range
+    pub const LASER_FAILURE: Self = Self(1 << 5);
+    /// The fan is turned on but not running
+    pub const FAN_FAILURE: Self = Self(1 << 4);
+    /// Every documented bit
+    const KNOWN: Self =
+        Self(Self::FAN_SPEED_WARNING.0 | Self::LASER_FAILURE.0 | Self::FAN_FAILURE.0);
+
+    /// No bit set, the sensor is healthy
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// The register as read from the sensor, undocumented bits included
+    #[must_use]
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    /// The raw register value
+    #[must_use]
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Whether no bit is set
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether every bit set in `other` is also set in `self`
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Bits set that are not documented, zero on current firmware
+    #[must_use]
+    pub const fn unknown_bits(self) -> u32 {
+        self.0 & !Self::KNOWN.0
+    }
+
+    /// The fan speed is too high or too low. Usually transient, for
+    /// example during fan cleaning.
+    #[must_use]
+    pub const fn fan_speed_warning(self) -> bool {
+        self.contains(Self::FAN_SPEED_WARNING)
+    }
+
+    /// The laser current is out of range
+    #[must_use]
+    pub const fn laser_failure(self) -> bool {
+        self.contains(Self::LASER_FAILURE)
+    }
+
+    /// The fan is switched on but the measured speed is zero
+    #[must_use]
+    pub const fn fan_failure(self) -> bool {
+        self.contains(Self::FAN_FAILURE)
+    }
+}
+
+impl From<u32> for DeviceStatus {
+    fn from(bits: u32) -> Self {
+        Self(bits)
+    }
+}
+
+impl From<DeviceStatus> for u32 {
+    fn from(status: DeviceStatus) -> Self {
+        status.0
+    }
+}
+
+impl BitOr for DeviceStatus {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for DeviceStatus {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitAnd for DeviceStatus {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl Not for DeviceStatus {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self(!self.0)
+    }
+}
+
+/// Names of the documented bits, in register order
+const NAMES: [(DeviceStatus, &str); 3] = [
+    (DeviceStatus::FAN_SPEED_WARNING, "fan speed warning"),
+    (DeviceStatus::LASER_FAILURE, "laser failure"),
+    (DeviceStatus::FAN_FAILURE, "fan failure"),
+];
+
+/// Lists the set bits, e.g. `laser failure, fan failure`, or `ok`
+impl fmt::Display for DeviceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("ok");
+        }
+        let mut separator = "";
+        for (flag, name) in NAMES {
+            if self.contains(flag) {
+                write!(f, "{separator}{name}")?;
+                separator = ", ";
+            }
+        }
+        if self.unknown_bits() != 0 {
+            write!(f, "{separator}unknown bits {:#010x}", self.unknown_bits())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for DeviceStatus {
+    fn format(&self, f: defmt::Formatter) {
+        if self.is_empty() {
+            defmt::write!(f, "ok");
+            return;
+        }
+        let mut first = true;
+        for (flag, name) in NAMES {
+            if self.contains(flag) {
+                if !first {
+                    defmt::write!(f, ", ");
+                }
+                defmt::write!(f, "{=str}", name);
+                first = false;
+            }
+        }
+        if self.unknown_bits() != 0 {
+            if !first {
+                defmt::write!(f, ", ");
+            }
+            defmt::write!(f, "unknown bits {=u32:#010x}", self.unknown_bits());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DeviceStatus;
+
+    #[test]
+    fn display() {
+        assert_eq!(DeviceStatus::empty().to_string(), "ok");
+        let status = DeviceStatus::FAN_SPEED_WARNING | DeviceStatus::FAN_FAILURE;
+        assert!(status.fan_speed_warning() && status.fan_failure());
+        assert!(!status.laser_failure());
+        assert_eq!(status.to_string(), "fan speed warning, fan failure");
+
+        let future = DeviceStatus::from_bits(1 << 30 | 1 << 5);
+        assert_eq!(future.unknown_bits(), 1 << 30);
+        assert_eq!(future.bits(), 1 << 30 | 1 << 5);
+        assert_eq!(future.to_string(), "laser failure, unknown bits 0x40000000");
+    }
+}
@@ -0,0 +1,38 @@
+use std::io;
+
+use embedded_io_async::ErrorKind;
+
+/// A [`std::io::Error`] reduced to its kind, so it can be cloned, compared
+/// and formatted with defmt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoError(pub io::ErrorKind);
+
+impl From<io::Error> for IoError {
+    fn from(err: io::Error) -> Self {
+        Self(err.kind())
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for IoError {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{}", defmt::Debug2Format(&self.0));
+    }
+}
+
+impl embedded_io_async::Error for IoError {
+    fn kind(&self) -> ErrorKind {
+        match self.0 {
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+            io::ErrorKind::BrokenPipe => ErrorKind::BrokenPipe,
+            io::ErrorKind::InvalidInput => ErrorKind::InvalidInput,
+            io::ErrorKind::InvalidData => ErrorKind::InvalidData,
+            io::ErrorKind::TimedOut => ErrorKind::TimedOut,
+            io::ErrorKind::Interrupted => ErrorKind::Interrupted,
+            io::ErrorKind::Unsupported => ErrorKind::Unsupported,
+            io::ErrorKind::OutOfMemory => ErrorKind::OutOfMemory,
+            _ => ErrorKind::Other,
+        }
+    }
+}
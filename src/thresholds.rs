@@ -0,0 +1,140 @@
+//! Compare measurements against air quality guidelines.
+//!
+//! The WHO 2021 guideline values are built in. Guidelines are defined on
+//! averages, compare them with a matching average, for example one from a
+//! [`TimeWeightedAverage`](crate::aggregate::TimeWeightedAverage):
+//!
+//! ```
+//! use sps30_async::thresholds::{AirQualityAlert, Thresholds};
+//! # let daily_average = sps30_async::Measurement { mass_pm2_5: 20.0, ..Default::default() };
+//!
+//! let assessment = Thresholds::WHO_2021_24H.assess(&daily_average);
+//! assert_eq!(assessment.pm2_5, AirQualityAlert::AboveGuideline);
+//! ```
+
+use crate::Measurement;
+
+/// How a concentration compares to a [`Threshold`], ordered from good to
+/// bad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum AirQualityAlert {
+    /// At or below the guideline
+    Ok,
+    /// Above the guideline, at or below the limit
+    AboveGuideline,
+    /// Above the limit
+    AboveLimit,
+}
+
+/// Two mass concentrations in µg/m³, the `limit` should not be below the
+/// `guideline`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Threshold {
+    /// Level considered safe
+    pub guideline: f32,
+    /// Level requiring action
+    pub limit: f32,
+}
+
+impl Threshold {
+    /// Classifies a concentration in µg/m³
+    #[must_use]
+    pub fn classify(&self, concentration: f32) -> AirQualityAlert {
+        if concentration > self.limit {
+            AirQualityAlert::AboveLimit
+        } else if concentration > self.guideline {
+            AirQualityAlert::AboveGuideline
+        } else {
+            AirQualityAlert::Ok
+        }
+    }
+}
+
+/// Thresholds per pollutant. Use a built in set or define your own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Thresholds {
+    pub pm2_5: Threshold,
+    pub pm10: Threshold,
+}
+
+impl Thresholds {
+    /// WHO 2021 air quality guidelines for 24 hour means. The limit is the
+    /// first (most lenient) interim target.
+    pub const WHO_2021_24H: Self = Self {
+        pm2_5: Threshold {
+            guideline: 15.0,
+            limit: 75.0,
+        },
+        pm10: Threshold {
+            guideline: 45.0,
+            limit: 150.0,
+        },
+    };
+
+    /// WHO 2021 air quality guidelines for annual means. The limit is the
+    /// first (most lenient) interim target.
+    pub const WHO_2021_ANNUAL: Self = Self {
+        pm2_5: Threshold {
+            guideline: 5.0,
+            limit: 35.0,
+        },
+        pm10: Threshold {
+            guideline: 15.0,
+            limit: 70.0,
+        },
+    };
+
+    /// Classifies each pollutant in `measurement`, usually an average over
+    /// the period the thresholds are defined for.
+    #[must_use]
+    pub fn assess(&self, measurement: &Measurement) -> Assessment {
+        Assessment {
+            pm2_5: self.pm2_5.classify(measurement.mass_pm2_5),
+            pm10: self.pm10.classify(measurement.mass_pm10),
+        }
+    }
+}
+
+/// Result of [`Thresholds::assess`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Assessment {
+    pub pm2_5: AirQualityAlert,
+    pub pm10: AirQualityAlert,
+}
+
+impl Assessment {
+    /// The most severe alert of all pollutants
+    #[must_use]
+    pub fn worst(&self) -> AirQualityAlert {
+        self.pm2_5.max(self.pm10)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AirQualityAlert, Thresholds};
+    use crate::Measurement;
+
+    #[test]
+    fn assess() {
+        let measurement = Measurement {
+            mass_pm2_5: 15.0,
+            mass_pm10: 151.0,
+            ..Measurement::default()
+        };
+        let assessment = Thresholds::WHO_2021_24H.assess(&measurement);
+        assert_eq!(assessment.pm2_5, AirQualityAlert::Ok);
+        assert_eq!(assessment.worst(), AirQualityAlert::AboveLimit);
+
+        let annual = Thresholds::WHO_2021_ANNUAL.assess(&measurement);
+        assert_eq!(annual.pm2_5, AirQualityAlert::AboveGuideline);
+    }
+}
@@ -0,0 +1,152 @@
+//! Detect whether concentrations are rising or falling, for example to tell
+//! a cooking spike from a steady decline.
+//!
+//! ```
+//! use core::time::Duration;
+//! use sps30_async::trend::{TrendAnalyzer, TrendDirection};
+//!
+//! let mut analyzer = TrendAnalyzer::<4>::new(Duration::from_secs(60));
+//! for pm2_5 in [10.0, 14.0, 18.0, 22.0] {
+//!     analyzer.push(pm2_5);
+//! }
+//! let trend = analyzer.trend().unwrap();
+//! assert_eq!(trend.direction, TrendDirection::Rising);
+//! assert_eq!(trend.rate_per_minute, 4.0);
+//! ```
+
+use core::time::Duration;
+
+use heapless::HistoryBuffer;
+
+/// Rate of change below which concentrations are considered stable,
+/// in µg/m³ per minute. See [`TrendAnalyzer::with_stable_rate`].
+pub const DEFAULT_STABLE_RATE: f32 = 0.5;
+/// The sensor measures once a second, values can not arrive faster
+const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum TrendDirection {
+    Rising,
+    Falling,
+    Stable,
+}
+
+/// Result of [`TrendAnalyzer::trend`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Trend {
+    pub direction: TrendDirection,
+    /// Change in concentration per minute, negative when falling
+    pub rate_per_minute: f32,
+}
+
+/// Fits a line through the last `N` values, its slope is the trend. Works
+/// with any concentration though PM2.5 is the usual choice.
+#[derive(Debug, Clone)]
+pub struct TrendAnalyzer<const N: usize> {
+    values: HistoryBuffer<f32, N>,
+    /// Minutes between successive values
+    interval: f32,
+    stable_rate: f32,
+}
+
+impl<const N: usize> TrendAnalyzer<N> {
+    /// Values are pushed every `interval`. Intervals shorter than the one
+    /// second the sensor takes per measurement, including zero, are
+    /// rounded up to it.
+    #[must_use]
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            values: HistoryBuffer::new(),
+            interval: interval.max(MIN_INTERVAL).as_secs_f32() / 60.0,
+            stable_rate: DEFAULT_STABLE_RATE,
+        }
+    }
+
+    /// Rates of change smaller than `rate` (per minute) are reported as
+    /// [`TrendDirection::Stable`]. Defaults to [`DEFAULT_STABLE_RATE`].
+    #[must_use]
+    pub fn with_stable_rate(mut self, rate: f32) -> Self {
+        self.stable_rate = rate;
+        self
+    }
+
+    /// Adds the next value, dropping the oldest once `N` are stored
+    pub fn push(&mut self, value: f32) {
+        self.values.write(value);
+    }
+
+    /// The trend over the stored values, `None` until there are two
+    #[must_use]
+    pub fn trend(&self) -> Option<Trend> {
+        let n = self.values.len();
+        if n < 2 {
+            return None;
+        }
+
+        // least squares slope with x the index of the value
+        #[allow(clippy::cast_precision_loss)] // windows are small
+        let x_mean = (n - 1) as f32 / 2.0;
+        #[allow(clippy::cast_precision_loss)]
+        let y_mean = self.values.oldest_ordered().sum::<f32>() / n as f32;
+        let (covariance, variance) = self.values.oldest_ordered().enumerate().fold(
+            (0.0, 0.0),
+            |(covariance, variance), (x, y)| {
+                #[allow(clippy::cast_precision_loss)]
+                let dx = x as f32 - x_mean;
+                (covariance + dx * (y - y_mean), variance + dx * dx)
+            },
+        );
+        let rate_per_minute = covariance / variance / self.interval;
+
+        let direction = if rate_per_minute.abs() < self.stable_rate {
+            TrendDirection::Stable
+        } else if rate_per_minute > 0.0 {
+            TrendDirection::Rising
+        } else {
+            TrendDirection::Falling
+        };
+        Some(Trend {
+            direction,
+            rate_per_minute,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TrendAnalyzer, TrendDirection};
+    use core::time::Duration;
+
+    #[test]
+    fn window_slides() {
+        let mut analyzer = TrendAnalyzer::<3>::new(Duration::from_secs(30));
+        analyzer.push(50.0);
+        assert!(analyzer.trend().is_none());
+        for pm2_5 in [40.0, 30.0] {
+            analyzer.push(pm2_5);
+        }
+        let trend = analyzer.trend().unwrap();
+        assert_eq!(trend.direction, TrendDirection::Falling);
+        assert_eq!(trend.rate_per_minute, -20.0);
+
+        for pm2_5 in [30.1, 29.9, 30.0] {
+            analyzer.push(pm2_5);
+        }
+        assert_eq!(analyzer.trend().unwrap().direction, TrendDirection::Stable);
+    }
+
+    #[test]
+    fn zero_interval() {
+        let mut analyzer = TrendAnalyzer::<2>::new(Duration::ZERO);
+        for pm2_5 in [10.0, 11.0] {
+            analyzer.push(pm2_5);
+        }
+        let trend = analyzer.trend().unwrap();
+        assert_eq!(trend.direction, TrendDirection::Rising);
+        assert!((trend.rate_per_minute - 60.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,304 @@
+//! Recover from a sensor that stopped answering without anyone having to
+//! power cycle it. A [`Watchdog`] counts the consecutive failed commands,
+//! past a threshold it resets the sensor and starts measuring again.
+//!
+//! ```ignore
+//! let mut watchdog = Watchdog::new();
+//! loop {
+//!     delay.delay_ms(1000).await;
+//!     let result = sensor.read_measurement().await;
+//!     match watchdog.check(&mut sensor, result).await {
+//!         Ok(measurement) => publish(measurement).await,
+//!         Err(e) => warn!("could not read sensor: {}", e),
+//!     }
+//! }
+//! ```
+//!
+//! What the watchdog does is reported to the
+//! [`ProtocolLogger`] of the driver, see
+//! [`WatchdogEvent`].
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+use crate::shdlc::{FrameObserver, ProtocolLogger};
+use crate::{Error, MaybeFormat, Measurement, ProtocolError, Sps30};
+
+/// Consecutive failures after which the sensor is reset by default
+pub const DEFAULT_THRESHOLD: u32 = 3;
+/// Default wait after the first failed recovery
+pub const DEFAULT_BACKOFF_MS: u32 = 1_000;
+/// Default limit on the wait between recoveries
+pub const DEFAULT_MAX_BACKOFF_MS: u32 = 5 * 60 * 1_000;
+
+/// Something the [`Watchdog`] did, passed to
+/// [`ProtocolLogger::on_watchdog`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WatchdogEvent {
+    /// Resetting the sensor after `failures` consecutive failures
+    Resetting { failures: u32 },
+    /// The sensor was reset and measures again
+    Recovered,
+    /// The reset or restart failed, the next attempt follows the next
+    /// failure after at least `backoff_ms`
+    RecoveryFailed { backoff_ms: u32 },
+}
+
+/// Resets an unresponsive sensor, see the [module](self) documentation.
+///
+/// Recoveries that fail are retried with an exponentially growing wait
+/// in between, up to a [maximum](Self::with_backoff). The wait uses the
+/// delay of the driver.
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    threshold: u32,
+    initial_backoff_ms: u32,
+    max_backoff_ms: u32,
+    /// Wait after the next failed recovery
+    backoff_ms: u32,
+    failures: u32,
+    recoveries: u32,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Watchdog {
+    /// Resets after [`DEFAULT_THRESHOLD`] failures, backs off from
+    /// [`DEFAULT_BACKOFF_MS`] up to [`DEFAULT_MAX_BACKOFF_MS`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            threshold: DEFAULT_THRESHOLD,
+            initial_backoff_ms: DEFAULT_BACKOFF_MS,
+            max_backoff_ms: DEFAULT_MAX_BACKOFF_MS,
+            backoff_ms: DEFAULT_BACKOFF_MS,
+            failures: 0,
+            recoveries: 0,
+        }
+    }
+
+    /// Reset after `failures` consecutive failures, at least one
+    #[must_use]
+    pub fn with_threshold(mut self, failures: u32) -> Self {
+        self.threshold = failures.max(1);
+        self
+    }
+
+    /// Wait `initial_ms` after the first failed recovery, doubling for
+    /// every next one up to `max_ms`
+    #[must_use]
+    pub fn with_backoff(mut self, initial_ms: u32, max_ms: u32) -> Self {
+        self.initial_backoff_ms = initial_ms;
+        self.max_backoff_ms = max_ms.max(initial_ms);
+        self.backoff_ms = initial_ms;
+        self
+    }
+
+    /// Failed commands since the last success
+    #[must_use]
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Successful recoveries so far
+    #[must_use]
+    pub fn recoveries(&self) -> u32 {
+        self.recoveries
+    }
+
+    /// Pass the `result` of every command sent to `sensor` through this.
+    /// Recovers the sensor once too many failed in a row. Returns `result`
+    /// unchanged.
+    ///
+    /// Errors that do not mean the sensor stopped answering are not
+    /// counted: [`ProtocolError::EmptyResult`], returned when reading faster
+    /// than the sensor measures, and [`Error::WrongDriverState`], where
+    /// nothing was sent.
+    ///
+    /// # Errors
+    /// Returns the error in `result`, errors during the recovery are only
+    /// reported to the logger.
+    pub async fn check<T, Tx, Rx, D, O, L>(
+        &mut self,
+        sensor: &mut Sps30<Tx, Rx, D, O, L>,
+        result: Result<T, Error<Tx::Error, Rx::Error>>,
+    ) -> Result<T, Error<Tx::Error, Rx::Error>>
+    where
+        Tx: Write,
+        Tx::Error: MaybeFormat,
+        Rx: Read,
+        Rx::Error: MaybeFormat,
+        D: DelayNs,
+        O: FrameObserver,
+        L: ProtocolLogger,
+    {
+        match &result {
+            Ok(_) => {
+                self.failures = 0;
+                return result;
+            }
+            Err(Error::Protocol(ProtocolError::EmptyResult) | Error::WrongDriverState { .. }) => {
+                return result
+            }
+            Err(_) => (),
+        }
+
+        self.failures = self.failures.saturating_add(1);
+        if self.failures >= self.threshold {
+            self.recover(sensor).await;
+        }
+        result
+    }
+
+    /// Reads a measurement, see [`check`](Self::check)
+    ///
+    /// # Errors
+    /// Reading the response can fail, the device can run into an internal
+    /// error or the connection could have issues leading to invalid responses.
+    /// These are caught and reported as Errors.
+    pub async fn read_measurement<Tx, Rx, D, O, L>(
+        &mut self,
+        sensor: &mut Sps30<Tx, Rx, D, O, L>,
+    ) -> Result<Measurement, Error<Tx::Error, Rx::Error>>
+    where
+        Tx: Write,
+        Tx::Error: MaybeFormat,
+        Rx: Read,
+        Rx::Error: MaybeFormat,
+        D: DelayNs,
+        O: FrameObserver,
+        L: ProtocolLogger,
+    {
+        let result = sensor.read_measurement().await;
+        self.check(sensor, result).await
+    }
+
+    async fn recover<Tx, Rx, D, O, L>(&mut self, sensor: &mut Sps30<Tx, Rx, D, O, L>)
+    where
+        Tx: Write,
+        Tx::Error: MaybeFormat,
+        Rx: Read,
+        Rx::Error: MaybeFormat,
+        D: DelayNs,
+        O: FrameObserver,
+        L: ProtocolLogger,
+    {
+        sensor.logger().on_watchdog(WatchdogEvent::Resetting {
+            failures: self.failures,
+        });
+        let restarted = match sensor.reset().await {
+            Ok(()) => sensor.start_measurement().await,
+            Err(e) => Err(e),
+        };
+
+        if restarted.is_ok() {
+            self.failures = 0;
+            self.recoveries = self.recoveries.saturating_add(1);
+            self.backoff_ms = self.initial_backoff_ms;
+            sensor.logger().on_watchdog(WatchdogEvent::Recovered);
+            return;
+        }
+
+        let backoff_ms = self.backoff_ms;
+        sensor
+            .logger()
+            .on_watchdog(WatchdogEvent::RecoveryFailed { backoff_ms });
+        self.backoff_ms = backoff_ms.saturating_mul(2).min(self.max_backoff_ms);
+        sensor.delay().delay_ms(backoff_ms).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+
+    use super::{Watchdog, WatchdogEvent};
+    use crate::mock::{MockSps30, NoDelay};
+    use crate::shdlc::ProtocolLogger;
+    use crate::{Error, Mode, ProtocolError, Sps30};
+
+    #[derive(Default)]
+    struct Events(std::vec::Vec<WatchdogEvent>);
+
+    impl ProtocolLogger for Events {
+        fn on_watchdog(&mut self, event: WatchdogEvent) {
+            self.0.push(event);
+        }
+    }
+
+    #[test]
+    fn resets_after_threshold() {
+        let mock = MockSps30::new();
+        let sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+        let mut sensor = sensor.with_logger(Events::default());
+        let mut watchdog = Watchdog::new().with_threshold(2).with_backoff(10, 15);
+
+        mock.fail_next(0x43);
+        assert!(block_on(watchdog.read_measurement(&mut sensor)).is_err());
+        assert!(sensor.logger().0.is_empty());
+        mock.fail_next(0x43);
+        assert!(block_on(watchdog.read_measurement(&mut sensor)).is_err());
+        assert_eq!(
+            sensor.logger().0,
+            [
+                WatchdogEvent::Resetting { failures: 2 },
+                WatchdogEvent::Recovered
+            ]
+        );
+        assert_eq!((watchdog.failures(), watchdog.recoveries()), (0, 1));
+        assert!(mock.is_measuring());
+        assert!(block_on(watchdog.read_measurement(&mut sensor)).is_ok());
+
+        // the reset itself fails, back off
+        sensor.logger().0.clear();
+        let failed = || Err::<(), _>(Error::Protocol(ProtocolError::InvalidFrame));
+        block_on(watchdog.check(&mut sensor, failed())).unwrap_err();
+        for _ in 0..2 {
+            mock.fail_next(0x43);
+            block_on(watchdog.check(&mut sensor, failed())).unwrap_err();
+        }
+        block_on(watchdog.check(&mut sensor, failed())).unwrap_err();
+        assert_eq!(
+            sensor.logger().0,
+            [
+                WatchdogEvent::Resetting { failures: 2 },
+                WatchdogEvent::RecoveryFailed { backoff_ms: 10 },
+                WatchdogEvent::Resetting { failures: 3 },
+                WatchdogEvent::RecoveryFailed { backoff_ms: 15 },
+                WatchdogEvent::Resetting { failures: 4 },
+                WatchdogEvent::Recovered,
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_harmless_errors() {
+        let mock = MockSps30::new().with_warmup(3);
+        let sensor = block_on(Sps30::from_tx_rx(&mock, &mock, NoDelay)).unwrap();
+        let mut sensor = sensor.with_logger(Events::default());
+        let mut watchdog = Watchdog::new().with_threshold(2);
+
+        mock.fail_next(0x43);
+        block_on(watchdog.read_measurement(&mut sensor)).unwrap_err();
+        // no measurement ready yet
+        for _ in 0..3 {
+            block_on(watchdog.read_measurement(&mut sensor)).unwrap_err();
+        }
+        for _ in 0..3 {
+            let empty = Err::<(), _>(Error::Protocol(ProtocolError::EmptyResult));
+            block_on(watchdog.check(&mut sensor, empty)).unwrap_err();
+            let asleep = Err::<(), _>(Error::WrongDriverState {
+                expected: Mode::Idle,
+                actual: Mode::Sleep,
+            });
+            block_on(watchdog.check(&mut sensor, asleep)).unwrap_err();
+        }
+        assert_eq!(watchdog.failures(), 1);
+        assert!(sensor.logger().0.is_empty());
+    }
+}